@@ -0,0 +1,31 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The guest, reporting the cycles of every block builder stage to the host.
+
+#![no_main]
+
+use risc0_zkvm::guest::env;
+
+risc0_zkvm::guest::entry!(main);
+
+pub fn main() {
+    // Record the cycles of every stage
+    zeth_lib::cycle_tracker::enable(env::get_cycle_count);
+    eth_block::build_block();
+    // Send the cycle counts to the host, outside of the journal
+    if let Some(report) = zeth_lib::cycle_tracker::take_report() {
+        env::write(&report);
+    }
+}
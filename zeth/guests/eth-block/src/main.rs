@@ -14,30 +14,8 @@
 
 #![no_main]
 
-use risc0_zkvm::guest::env;
-use zeth_lib::{
-    block_builder::BlockBuilder, consts::ETH_MAINNET_CHAIN_SPEC, execution::EthTxExecStrategy,
-    finalization::BuildFromMemDbStrategy, initialization::MemDbInitStrategy, input::Input,
-    mem_db::MemDb, preparation::EthHeaderPrepStrategy,
-};
-
 risc0_zkvm::guest::entry!(main);
 
 pub fn main() {
-    // Read the input previous block and transaction data
-    let input: Input = env::read();
-    // Build the resulting block
-    let output = BlockBuilder::<MemDb>::new(&ETH_MAINNET_CHAIN_SPEC, input)
-        .initialize_database::<MemDbInitStrategy>()
-        .expect("Failed to create in-memory evm storage")
-        .prepare_header::<EthHeaderPrepStrategy>()
-        .expect("Failed to create the initial block header fields")
-        .execute_transactions::<EthTxExecStrategy>()
-        .expect("Failed to execute transactions")
-        .build::<BuildFromMemDbStrategy>()
-        .expect("Failed to build the resulting block");
-    // Output the resulting block's hash to the journal
-    env::commit(&output.hash());
-    // Leak memory, save cycles
-    core::mem::forget(output);
+    eth_block::build_block();
 }
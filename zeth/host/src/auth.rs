@@ -0,0 +1,423 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! API key authentication and per-key quotas.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_cors::Cors;
+use actix_web::{
+    dev::ServiceRequest,
+    http::{header, StatusCode},
+    HttpMessage, HttpResponse, ResponseError,
+};
+use anyhow::{bail, Result};
+use dotenv::var;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::warn;
+
+/// Environment variable containing a comma-separated list of API keys without limits.
+const API_KEYS_VAR: &str = "ZETH_API_KEYS";
+/// Environment variable containing a comma-separated list of allowed CORS origins.
+const ALLOWED_ORIGINS_VAR: &str = "ZETH_ALLOWED_ORIGINS";
+
+/// Header carrying the API key. Alternatively, the key can be given as bearer token or,
+/// for websocket clients that cannot set headers, as `api_key` query parameter.
+const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Paths that can be accessed without an API key.
+const PUBLIC_PATHS: &[&str] = &["/", "/metrics"];
+
+/// Limits of a single API key. Missing limits are unlimited.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of jobs running at the same time.
+    pub max_concurrent_jobs: Option<u32>,
+    /// Maximum number of blocks verified per UTC day.
+    pub max_daily_blocks: Option<u32>,
+    /// Maximum number of Bonsai submissions per UTC day.
+    pub max_daily_bonsai_submissions: Option<u32>,
+}
+
+/// An API key and its limits.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiKeyConfig {
+    /// Name of the client, used in logs.
+    pub name: String,
+    pub key: String,
+    /// Whether the key may use the admin API.
+    #[serde(default)]
+    pub admin: bool,
+    #[serde(flatten)]
+    pub limits: Limits,
+}
+
+/// Authentication configuration.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub keys: Vec<ApiKeyConfig>,
+    /// Serves all requests without an API key. The service refuses to start without
+    /// keys unless this is set.
+    #[serde(default)]
+    pub disabled: bool,
+    /// Origins allowed to make cross-origin requests.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+}
+
+impl AuthConfig {
+    /// Adds the keys in `ZETH_API_KEYS` and the origins in `ZETH_ALLOWED_ORIGINS`.
+    pub fn apply_env(&mut self) {
+        if let Ok(keys) = var(API_KEYS_VAR) {
+            for (i, key) in split_list(&keys).enumerate() {
+                self.keys.push(ApiKeyConfig {
+                    name: format!("env-{}", i),
+                    key: key.to_string(),
+                    admin: false,
+                    limits: Limits::default(),
+                });
+            }
+        }
+        if let Ok(origins) = var(ALLOWED_ORIGINS_VAR) {
+            self.allowed_origins
+                .extend(split_list(&origins).map(str::to_string));
+        }
+    }
+
+    /// Checks that all keys are non-empty and unique, and that client names are unique.
+    pub fn validate(&self) -> Result<()> {
+        if self.disabled && !self.keys.is_empty() {
+            bail!("API keys are configured, but authentication is disabled");
+        }
+        let mut keys = HashSet::new();
+        let mut names = HashSet::new();
+        for key in &self.keys {
+            if key.key.is_empty() {
+                bail!("Empty API key for client {}", key.name);
+            }
+            if !keys.insert(&key.key) {
+                bail!("Duplicate API key for client {}", key.name);
+            }
+            if !names.insert(&key.name) {
+                bail!("Duplicate client name {}", key.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|s| !s.is_empty())
+}
+
+/// The authenticated client of a request.
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+    /// Index of the API key in the configuration, which identifies the client in quotas.
+    pub id: usize,
+    pub name: String,
+    pub admin: bool,
+    pub limits: Limits,
+}
+
+impl ApiClient {
+    /// The client of requests when authentication is disabled. It cannot use the admin
+    /// API.
+    fn anonymous() -> Self {
+        ApiClient {
+            id: 0,
+            name: "anonymous".to_string(),
+            admin: false,
+            limits: Limits::default(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("Missing API key")]
+    MissingKey,
+    #[error("Invalid API key")]
+    InvalidKey,
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNAUTHORIZED
+    }
+}
+
+/// Authenticates requests by their API key.
+#[derive(Debug, Clone)]
+pub struct Authenticator {
+    keys: Arc<HashMap<String, ApiClient>>,
+    disabled: bool,
+}
+
+impl Authenticator {
+    /// Creates the authenticator of a validated configuration, failing if it has no keys
+    /// and authentication is not disabled explicitly.
+    pub fn new(config: &AuthConfig) -> Result<Self> {
+        if config.disabled {
+            warn!("Authentication is disabled, all requests are served without an API key");
+        } else if config.keys.is_empty() {
+            bail!(
+                "No API keys configured, add keys to [auth] or set auth.disabled = true to \
+                serve without authentication"
+            );
+        }
+        let keys: HashMap<_, _> = config
+            .keys
+            .iter()
+            .enumerate()
+            .map(|(id, key)| {
+                let client = ApiClient {
+                    id,
+                    name: key.name.clone(),
+                    admin: key.admin,
+                    limits: key.limits.clone(),
+                };
+                (key.key.clone(), client)
+            })
+            .collect();
+
+        Ok(Authenticator {
+            keys: Arc::new(keys),
+            disabled: config.disabled,
+        })
+    }
+
+    /// Authenticates the request and attaches the [ApiClient] to its extensions.
+    pub fn authenticate(&self, req: &ServiceRequest) -> Result<(), AuthError> {
+        let client = if self.disabled {
+            ApiClient::anonymous()
+        } else if PUBLIC_PATHS.contains(&req.path()) {
+            return Ok(());
+        } else {
+            let key = request_key(req).ok_or(AuthError::MissingKey)?;
+            self.keys.get(&key).cloned().ok_or(AuthError::InvalidKey)?
+        };
+        req.extensions_mut().insert(client);
+
+        Ok(())
+    }
+}
+
+/// Extracts the API key from the headers or the query string.
+fn request_key(req: &ServiceRequest) -> Option<String> {
+    let headers = req.headers();
+    if let Some(key) = headers.get(API_KEY_HEADER) {
+        return key.to_str().ok().map(str::to_string);
+    }
+    if let Some(auth) = headers.get(header::AUTHORIZATION) {
+        if let Some(token) = auth.to_str().ok()?.strip_prefix("Bearer ") {
+            return Some(token.trim().to_string());
+        }
+    }
+    req.query_string()
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == "api_key")
+        .map(|(_, key)| key.to_string())
+}
+
+/// Builds the CORS policy allowing only the configured origins.
+pub fn cors(config: &AuthConfig) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST", "DELETE"])
+        .allowed_headers(vec![
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            header::HeaderName::from_static("x-api-key"),
+        ])
+        .max_age(3600);
+    for origin in &config.allowed_origins {
+        cors = cors.allowed_origin(origin);
+    }
+    cors
+}
+
+/// The quota that was exceeded.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Quota {
+    /// Jobs running at the same time across all clients.
+    ServiceConcurrentJobs,
+    ConcurrentJobs,
+    DailyBlocks,
+    DailyBonsaiSubmissions,
+}
+
+#[derive(Debug, Error)]
+#[error("Quota exceeded: {quota:?} (limit {limit})")]
+pub struct QuotaError {
+    pub quota: Quota,
+    pub limit: u32,
+}
+
+impl ResponseError for QuotaError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self.to_json())
+    }
+}
+
+impl QuotaError {
+    /// Returns the error as sent to clients.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "error": "quota_exceeded",
+            "quota": self.quota,
+            "limit": self.limit,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    /// Day the daily counters refer to, in days since the UNIX epoch.
+    day: u64,
+    running_jobs: u32,
+    blocks: u32,
+    bonsai_submissions: u32,
+}
+
+/// Tracks the usage of every client against its limits.
+#[derive(Debug, Default)]
+pub struct Quotas {
+    /// Maximum number of jobs running at the same time, across all clients.
+    max_concurrent_jobs: Option<u32>,
+    /// Usage of every client, keyed by [ApiClient::id].
+    usage: Mutex<HashMap<usize, Usage>>,
+}
+
+impl Quotas {
+    pub fn new(max_concurrent_jobs: Option<u32>) -> Self {
+        Quotas {
+            max_concurrent_jobs,
+            usage: Mutex::default(),
+        }
+    }
+
+    /// Reserves a job for the client, failing if any of its limits would be exceeded.
+    ///
+    /// The job counts as running until the returned permit is dropped. Its block and
+    /// Bonsai submission are only charged to the daily quotas once the job is accepted
+    /// with [JobPermit::accept], and are returned otherwise.
+    pub fn start_job(
+        self: &Arc<Self>,
+        client: &ApiClient,
+        bonsai: bool,
+    ) -> Result<JobPermit, QuotaError> {
+        let limits = &client.limits;
+        let mut usage = self.usage.lock().unwrap();
+        let running_jobs = usage.values().map(|usage| usage.running_jobs).sum();
+        check(
+            Quota::ServiceConcurrentJobs,
+            running_jobs,
+            self.max_concurrent_jobs,
+        )?;
+        let usage = usage.entry(client.id).or_default();
+
+        let today = today();
+        if usage.day != today {
+            usage.day = today;
+            usage.blocks = 0;
+            usage.bonsai_submissions = 0;
+        }
+
+        check(
+            Quota::ConcurrentJobs,
+            usage.running_jobs,
+            limits.max_concurrent_jobs,
+        )?;
+        check(Quota::DailyBlocks, usage.blocks, limits.max_daily_blocks)?;
+        if bonsai {
+            check(
+                Quota::DailyBonsaiSubmissions,
+                usage.bonsai_submissions,
+                limits.max_daily_bonsai_submissions,
+            )?;
+            usage.bonsai_submissions += 1;
+        }
+        usage.running_jobs += 1;
+        usage.blocks += 1;
+
+        Ok(JobPermit {
+            quotas: self.clone(),
+            client: client.id,
+            day: today,
+            bonsai,
+            accepted: false,
+        })
+    }
+}
+
+fn check(quota: Quota, used: u32, limit: Option<u32>) -> Result<(), QuotaError> {
+    match limit {
+        Some(limit) if used >= limit => Err(QuotaError { quota, limit }),
+        _ => Ok(()),
+    }
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 86400
+}
+
+/// A running job of a client. Releases the concurrency slot when dropped.
+#[derive(Debug)]
+pub struct JobPermit {
+    quotas: Arc<Quotas>,
+    /// [ApiClient::id] of the client.
+    client: usize,
+    /// Day the job was reserved on.
+    day: u64,
+    bonsai: bool,
+    accepted: bool,
+}
+
+impl JobPermit {
+    /// Charges the job to the daily quotas of the client, once it has been created.
+    pub fn accept(&mut self) {
+        self.accepted = true;
+    }
+}
+
+impl Drop for JobPermit {
+    fn drop(&mut self) {
+        if let Some(usage) = self.quotas.usage.lock().unwrap().get_mut(&self.client) {
+            usage.running_jobs = usage.running_jobs.saturating_sub(1);
+            // Return the reservation of a job that was never created
+            if !self.accepted && usage.day == self.day {
+                usage.blocks = usage.blocks.saturating_sub(1);
+                if self.bonsai {
+                    usage.bonsai_submissions = usage.bonsai_submissions.saturating_sub(1);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Server-side cache of RPC responses, and the admin API to manage it.
+//!
+//! Cached blocks live at `{dir}/{network}/{block_no}.json.gz`, where `dir` is the
+//! directory of a cache profile configured on the server. Clients only choose whether to
+//! use the cache, or which profile, never a path.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::UNIX_EPOCH,
+};
+
+use actix_web::{delete, get, web, HttpResponse};
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use zeth_lib::host::provider::file_provider::{FileProvider, FileProviderStats};
+
+use crate::{auth::ApiClient, config::Config, NetworkSelection};
+
+/// Name of the profile backed by `cache.root`.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Extension of cache files.
+const CACHE_FILE_EXT: &str = "json.gz";
+
+/// The cache a client asks to use.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum CacheSelection {
+    /// Uses the default profile, if `true`.
+    Enabled(bool),
+    /// Uses the named profile.
+    Profile(String),
+}
+
+impl Default for CacheSelection {
+    fn default() -> Self {
+        CacheSelection::Enabled(true)
+    }
+}
+
+impl FromStr for CacheSelection {
+    type Err = std::convert::Infallible;
+
+    /// Parses `true`, `false` or a profile name, as given in query strings and on the
+    /// command line.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "true" => CacheSelection::Enabled(true),
+            "false" => CacheSelection::Enabled(false),
+            profile => CacheSelection::Profile(profile.to_string()),
+        })
+    }
+}
+
+/// Returns the path of the cache file of a block in the given directory.
+pub fn block_file(dir: &Path, network: NetworkSelection, block_no: u64) -> PathBuf {
+    dir.join(network.to_string())
+        .join(format!("{}.{}", block_no, CACHE_FILE_EXT))
+}
+
+/// A cached block.
+#[derive(Serialize, Debug)]
+pub struct CachedBlock {
+    pub profile: String,
+    pub network: NetworkSelection,
+    pub block_no: u64,
+    /// Size of the compressed file in bytes.
+    pub size: u64,
+    /// Last modification, in seconds since the UNIX epoch.
+    pub modified: Option<u64>,
+}
+
+impl CachedBlock {
+    fn from_file(
+        profile: &str,
+        network: NetworkSelection,
+        block_no: u64,
+        path: &Path,
+    ) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs());
+        Ok(CachedBlock {
+            profile: profile.to_string(),
+            network,
+            block_no,
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// A cached block, together with the number of cached responses it contains.
+#[derive(Serialize, Debug)]
+pub struct CachedBlockDetails {
+    #[serde(flatten)]
+    pub block: CachedBlock,
+    pub entries: FileProviderStats,
+}
+
+/// Lists the cached blocks of every profile. Files not following the cache layout are
+/// skipped.
+pub fn list(config: &Config) -> Result<Vec<CachedBlock>> {
+    let mut blocks = Vec::new();
+    for (profile, dir) in config.cache.profile_dirs() {
+        for network in NetworkSelection::value_variants() {
+            let network_dir = dir.join(network.to_string());
+            let entries = match fs::read_dir(&network_dir) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("Could not read {}", network_dir.display()))
+                }
+            };
+            for entry in entries {
+                let path = entry?.path();
+                let block_no = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.strip_suffix(CACHE_FILE_EXT))
+                    .and_then(|name| name.strip_suffix('.'))
+                    .and_then(|block_no| block_no.parse().ok());
+                if let Some(block_no) = block_no {
+                    blocks.push(CachedBlock::from_file(profile, *network, block_no, &path)?);
+                }
+            }
+        }
+    }
+    blocks.sort_by(|a, b| {
+        (&a.profile, a.network, a.block_no).cmp(&(&b.profile, b.network, b.block_no))
+    });
+    Ok(blocks)
+}
+
+/// Returns the cache file of a block, failing if it does not exist.
+fn existing_file(
+    config: &Config,
+    profile: &str,
+    network: NetworkSelection,
+    block_no: u64,
+) -> Result<PathBuf> {
+    let path = config
+        .cache
+        .block_file(
+            &CacheSelection::Profile(profile.to_string()),
+            network,
+            block_no,
+        )?
+        .expect("profiles always have a directory");
+    if !path.is_file() {
+        bail!(
+            "{} block {} is not cached in profile {}",
+            network,
+            block_no,
+            profile
+        );
+    }
+    Ok(path)
+}
+
+/// Reads a cached block and counts its responses.
+pub fn inspect(
+    config: &Config,
+    profile: &str,
+    network: NetworkSelection,
+    block_no: u64,
+) -> Result<CachedBlockDetails> {
+    let path = existing_file(config, profile, network, block_no)?;
+    let provider = FileProvider::read_from_file(path.to_string_lossy().into_owned())
+        .with_context(|| format!("Could not read {}", path.display()))?;
+    Ok(CachedBlockDetails {
+        block: CachedBlock::from_file(profile, network, block_no, &path)?,
+        entries: provider.stats(),
+    })
+}
+
+/// Deletes a cached block.
+pub fn evict(
+    config: &Config,
+    profile: &str,
+    network: NetworkSelection,
+    block_no: u64,
+) -> Result<()> {
+    let path = existing_file(config, profile, network, block_no)?;
+    fs::remove_file(&path).with_context(|| format!("Could not delete {}", path.display()))?;
+    info!(
+        "Evicted {} block {} from cache profile {}",
+        network, block_no, profile
+    );
+    Ok(())
+}
+
+/// Path of a cached block in the admin API.
+#[derive(Deserialize, Debug)]
+pub struct CachedBlockPath {
+    profile: String,
+    network: NetworkSelection,
+    block_no: u64,
+}
+
+/// Returns a 403 response unless the client is an admin.
+fn require_admin(client: &ApiClient) -> Option<HttpResponse> {
+    (!client.admin).then(|| HttpResponse::Forbidden().body("Admin API key required"))
+}
+
+/// Lists the cached blocks.
+#[get("/admin/cache")]
+pub async fn list_handler(
+    client: web::ReqData<ApiClient>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    if let Some(response) = require_admin(&client) {
+        return response;
+    }
+    match web::block(move || list(&config)).await {
+        Ok(Ok(blocks)) => HttpResponse::Ok().json(blocks),
+        Ok(Err(err)) => HttpResponse::InternalServerError().body(format!("{:#}", err)),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+/// Returns the size and the number of cached responses of a block.
+#[get("/admin/cache/{profile}/{network}/{block_no}")]
+pub async fn inspect_handler(
+    client: web::ReqData<ApiClient>,
+    config: web::Data<Config>,
+    path: web::Path<CachedBlockPath>,
+) -> HttpResponse {
+    if let Some(response) = require_admin(&client) {
+        return response;
+    }
+    let path = path.into_inner();
+    match web::block(move || inspect(&config, &path.profile, path.network, path.block_no)).await {
+        Ok(Ok(details)) => HttpResponse::Ok().json(details),
+        Ok(Err(err)) => HttpResponse::NotFound().body(format!("{:#}", err)),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+/// Deletes a cached block, so that it is fetched again by the next job.
+#[delete("/admin/cache/{profile}/{network}/{block_no}")]
+pub async fn evict_handler(
+    client: web::ReqData<ApiClient>,
+    config: web::Data<Config>,
+    path: web::Path<CachedBlockPath>,
+) -> HttpResponse {
+    if let Some(response) = require_admin(&client) {
+        return response;
+    }
+    let path = path.into_inner();
+    match web::block(move || evict(&config, &path.profile, path.network, path.block_no)).await {
+        Ok(Ok(())) => HttpResponse::NoContent().finish(),
+        Ok(Err(err)) => HttpResponse::NotFound().body(format!("{:#}", err)),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
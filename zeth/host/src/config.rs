@@ -0,0 +1,674 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Configuration of the verification service.
+//!
+//! The configuration is read from a TOML file, see `zeth.example.toml`, after which
+//! environment variables override individual settings. The result is validated before
+//! the service starts.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::BufReader,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use actix_web::web::PayloadConfig;
+use anyhow::{bail, ensure, Context, Result};
+use bonsai_sdk::alpha as bonsai_sdk;
+use dotenv::var;
+use serde::Deserialize;
+use tracing::warn;
+use zeth_lib::{
+    consts::{ChainSpec, ETH_MAINNET_CHAIN_SPEC},
+    host::estimator::CycleModel,
+};
+
+use crate::{
+    auth::AuthConfig,
+    cache::{CacheSelection, DEFAULT_PROFILE},
+    NetworkSelection,
+};
+
+/// Environment variable containing the path of the configuration file.
+pub const CONFIG_VAR: &str = "ZETH_CONFIG";
+/// Configuration file used if none is given and it exists.
+const DEFAULT_CONFIG_FILE: &str = "zeth.toml";
+
+/// Smallest segment size supported by the zkVM.
+const MIN_SEGMENT_LIMIT_PO2: u32 = 13;
+/// Largest segment size supported by the zkVM.
+const MAX_SEGMENT_LIMIT_PO2: u32 = 24;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub server: ServerConfig,
+    /// Supported networks, keyed by their lowercase name.
+    #[serde(default)]
+    pub networks: BTreeMap<NetworkSelection, NetworkConfig>,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub executor: ExecutorConfig,
+    /// Model predicting the guest cycles of a block, see `zeth calibrate`. Estimates are
+    /// unavailable without it.
+    pub estimator: Option<CycleModel>,
+    #[serde(default)]
+    pub prover: ProverConfig,
+    #[serde(default)]
+    pub jobs: JobsConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address the service listens on.
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// Serves HTTPS instead of HTTP, if present.
+    pub tls: Option<TlsConfig>,
+    /// Time running jobs are given to finish on shutdown, in seconds.
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// Largest accepted upload of a receipt or input file, in MiB.
+    #[serde(default = "default_max_upload_mib")]
+    pub max_upload_mib: usize,
+    /// Largest size an uploaded input file may decompress to, in MiB.
+    #[serde(default = "default_max_input_mib")]
+    pub max_input_mib: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: default_bind(),
+            tls: None,
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            max_upload_mib: default_max_upload_mib(),
+            max_input_mib: default_max_input_mib(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Returns the payload limit of the upload endpoints.
+    pub fn upload_config(&self) -> PayloadConfig {
+        PayloadConfig::new(self.max_upload_mib << 20)
+    }
+
+    /// Returns the largest size an uploaded input file may decompress to, in bytes.
+    pub fn max_input_size(&self) -> u64 {
+        self.max_input_mib.saturating_mul(1 << 20)
+    }
+}
+
+fn default_bind() -> String {
+    "0.0.0.0:8000".to_string()
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    60
+}
+
+fn default_max_upload_mib() -> usize {
+    256
+}
+
+fn default_max_input_mib() -> u64 {
+    4096
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file containing the certificate chain.
+    pub cert: PathBuf,
+    /// PEM file containing the private key.
+    pub key: PathBuf,
+}
+
+impl TlsConfig {
+    /// Loads the certificate chain and PKCS#8 private key.
+    pub fn server_config(&self) -> Result<rustls::ServerConfig> {
+        let mut cert_file = BufReader::new(
+            File::open(&self.cert)
+                .with_context(|| format!("Could not open {}", self.cert.display()))?,
+        );
+        let certs = rustls_pemfile::certs(&mut cert_file)?
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect();
+        let mut key_file = BufReader::new(
+            File::open(&self.key)
+                .with_context(|| format!("Could not open {}", self.key.display()))?,
+        );
+        let key = rustls_pemfile::pkcs8_private_keys(&mut key_file)?
+            .into_iter()
+            .next()
+            .with_context(|| format!("No PKCS#8 private key in {}", self.key.display()))?;
+
+        Ok(rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, rustls::PrivateKey(key))?)
+    }
+}
+
+/// Name of a chain specification compiled into the service.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainSpecName {
+    Mainnet,
+}
+
+impl ChainSpecName {
+    /// Returns the chain specification.
+    pub fn spec(&self) -> &'static ChainSpec {
+        match self {
+            ChainSpecName::Mainnet => &ETH_MAINNET_CHAIN_SPEC,
+        }
+    }
+
+    /// Returns the chain specification of the given network, if there is one.
+    fn for_network(network: NetworkSelection) -> Option<Self> {
+        match network {
+            NetworkSelection::Ethereum => Some(ChainSpecName::Mainnet),
+            NetworkSelection::Sepolia | NetworkSelection::Goerli => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// RPC endpoints of the network, tried in order.
+    pub rpc_urls: Vec<String>,
+    /// Chain specification the blocks of the network are built with.
+    pub chain_spec: ChainSpecName,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Directory RPC responses are cached in, used by the `default` profile. Caching is
+    /// disabled if not set.
+    pub root: Option<PathBuf>,
+    /// Additional named cache directories clients can choose from.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, PathBuf>,
+}
+
+impl CacheConfig {
+    /// Returns every configured profile and its directory.
+    pub fn profile_dirs(&self) -> impl Iterator<Item = (&str, &Path)> {
+        self.root
+            .as_deref()
+            .map(|root| (DEFAULT_PROFILE, root))
+            .into_iter()
+            .chain(
+                self.profiles
+                    .iter()
+                    .map(|(name, dir)| (name.as_str(), dir.as_path())),
+            )
+    }
+
+    /// Returns the directory of the given profile.
+    pub fn profile_dir(&self, profile: &str) -> Result<&Path> {
+        self.profile_dirs()
+            .find(|(name, _)| *name == profile)
+            .map(|(_, dir)| dir)
+            .with_context(|| format!("Unknown cache profile {}", profile))
+    }
+
+    /// Returns the directory selected by a client, or `None` if caching is disabled.
+    ///
+    /// Enabling the cache without a configured root is not an error, the block is then
+    /// fetched over RPC only.
+    pub fn select(&self, selection: &CacheSelection) -> Result<Option<&Path>> {
+        match selection {
+            CacheSelection::Enabled(false) => Ok(None),
+            CacheSelection::Enabled(true) => Ok(self.root.as_deref()),
+            CacheSelection::Profile(profile) => self.profile_dir(profile).map(Some),
+        }
+    }
+
+    /// Returns the path of the cache file of the given block in the selected directory.
+    pub fn block_file(
+        &self,
+        selection: &CacheSelection,
+        network: NetworkSelection,
+        block_no: u64,
+    ) -> Result<Option<PathBuf>> {
+        ensure!(block_no > 0, "Invalid block number {}", block_no);
+        Ok(self
+            .select(selection)?
+            .map(|dir| crate::cache::block_file(dir, network, block_no)))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExecutorConfig {
+    /// Segment limit used when a request enables the executor without choosing one.
+    #[serde(default = "default_segment_limit_po2")]
+    pub default_segment_limit_po2: u32,
+    /// Largest segment limit a request may choose.
+    #[serde(default = "max_segment_limit_po2")]
+    pub max_segment_limit_po2: u32,
+    /// Whether to minimize the witness of prepared inputs.
+    #[serde(default = "default_minimize_input")]
+    pub minimize_input: bool,
+    /// Whether to run the cycle-tracking build of the guest after the executor, in a
+    /// separate pass, to count the cycles of every block builder stage.
+    #[serde(default)]
+    pub track_cycles: bool,
+}
+
+impl Default for ExecutorConfig {
+    fn default() -> Self {
+        ExecutorConfig {
+            default_segment_limit_po2: default_segment_limit_po2(),
+            max_segment_limit_po2: max_segment_limit_po2(),
+            minimize_input: default_minimize_input(),
+            track_cycles: false,
+        }
+    }
+}
+
+fn default_segment_limit_po2() -> u32 {
+    20
+}
+
+fn max_segment_limit_po2() -> u32 {
+    22
+}
+
+fn default_minimize_input() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ProverConfig {
+    /// URL of the Bonsai API. Bonsai submissions are rejected if not set.
+    pub bonsai_api_url: Option<String>,
+    /// Key of the Bonsai API.
+    pub bonsai_api_key: Option<String>,
+    /// Interval in which running Bonsai sessions are polled.
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// Maximum number of jobs running at the same time, across all clients.
+    pub max_concurrent_jobs: Option<u32>,
+}
+
+impl Default for ProverConfig {
+    fn default() -> Self {
+        ProverConfig {
+            bonsai_api_url: None,
+            bonsai_api_key: None,
+            poll_interval_secs: default_poll_interval_secs(),
+            max_concurrent_jobs: None,
+        }
+    }
+}
+
+fn default_poll_interval_secs() -> u64 {
+    15
+}
+
+impl ProverConfig {
+    /// Returns whether Bonsai is configured.
+    pub fn bonsai_enabled(&self) -> bool {
+        self.bonsai_api_url.is_some() && self.bonsai_api_key.is_some()
+    }
+
+    /// Creates a client of the configured Bonsai API.
+    pub fn bonsai_client(&self) -> Result<bonsai_sdk::Client> {
+        let (Some(url), Some(key)) = (&self.bonsai_api_url, &self.bonsai_api_key) else {
+            bail!("Bonsai is not configured");
+        };
+        Ok(bonsai_sdk::Client::from_parts(url.clone(), key.clone())?)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct JobsConfig {
+    /// Directory jobs and their artifacts are persisted to.
+    #[serde(default = "default_jobs_dir")]
+    pub dir: PathBuf,
+    /// JSON manifest of additional guest images.
+    pub guest_registry: Option<PathBuf>,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        JobsConfig {
+            dir: default_jobs_dir(),
+            guest_registry: None,
+        }
+    }
+}
+
+fn default_jobs_dir() -> PathBuf {
+    PathBuf::from("jobs")
+}
+
+impl Config {
+    /// Loads and validates the configuration.
+    ///
+    /// The file is taken from `path`, `ZETH_CONFIG` or `zeth.toml`, in this order. Only
+    /// an explicitly given file must exist.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = path
+            .map(Path::to_path_buf)
+            .or_else(|| var(CONFIG_VAR).ok().map(PathBuf::from));
+        let mut config = match path {
+            Some(path) => Config::read(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+                Config::read(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Config::default(),
+        };
+        config.apply_env()?;
+        config.validate().context("Invalid configuration")?;
+
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read configuration {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Could not parse configuration {}", path.display()))
+    }
+
+    /// Overrides settings with the corresponding environment variables.
+    fn apply_env(&mut self) -> Result<()> {
+        if let Ok(bind) = var("ZETH_BIND_ADDRESS") {
+            self.server.bind = bind;
+        }
+        if let Some(secs) = parse_var("ZETH_SHUTDOWN_TIMEOUT_SECS")? {
+            self.server.shutdown_timeout_secs = secs;
+        }
+        if let Some(mib) = parse_var("ZETH_MAX_UPLOAD_MIB")? {
+            self.server.max_upload_mib = mib;
+        }
+        if let Some(mib) = parse_var("ZETH_MAX_INPUT_MIB")? {
+            self.server.max_input_mib = mib;
+        }
+        match (var("ZETH_TLS_CERT"), var("ZETH_TLS_KEY")) {
+            (Ok(cert), Ok(key)) => {
+                self.server.tls = Some(TlsConfig {
+                    cert: cert.into(),
+                    key: key.into(),
+                })
+            }
+            (Err(_), Err(_)) => {}
+            _ => bail!("ZETH_TLS_CERT and ZETH_TLS_KEY must be set together"),
+        }
+
+        for (network, name) in [
+            (NetworkSelection::Ethereum, "ETHEREUM_RPC_URL"),
+            (NetworkSelection::Sepolia, "SEPOLIA_RPC_URL"),
+            (NetworkSelection::Goerli, "GOERLI_RPC_URL"),
+        ] {
+            let Ok(rpc_url) = var(name) else {
+                continue;
+            };
+            if let Some(config) = self.networks.get_mut(&network) {
+                config.rpc_urls = vec![rpc_url];
+            } else if let Some(chain_spec) = ChainSpecName::for_network(network) {
+                self.networks.insert(
+                    network,
+                    NetworkConfig {
+                        rpc_urls: vec![rpc_url],
+                        chain_spec,
+                    },
+                );
+            } else {
+                warn!("Ignoring {}: no chain spec available for {}", name, network);
+            }
+        }
+
+        if let Ok(root) = var("ZETH_CACHE_ROOT") {
+            self.cache.root = Some(root.into());
+        }
+        if let Some(po2) = parse_var("ZETH_DEFAULT_SEGMENT_LIMIT_PO2")? {
+            self.executor.default_segment_limit_po2 = po2;
+        }
+        if let Some(po2) = parse_var("ZETH_MAX_SEGMENT_LIMIT_PO2")? {
+            self.executor.max_segment_limit_po2 = po2;
+        }
+        if let Some(minimize) = parse_var("ZETH_MINIMIZE_INPUT")? {
+            self.executor.minimize_input = minimize;
+        }
+        if let Some(track) = parse_var("ZETH_TRACK_CYCLES")? {
+            self.executor.track_cycles = track;
+        }
+        if let Ok(url) = var("BONSAI_API_URL") {
+            self.prover.bonsai_api_url = Some(url);
+        }
+        if let Ok(key) = var("BONSAI_API_KEY") {
+            self.prover.bonsai_api_key = Some(key);
+        }
+        if let Some(max) = parse_var("ZETH_MAX_CONCURRENT_JOBS")? {
+            self.prover.max_concurrent_jobs = Some(max);
+        }
+        if let Ok(dir) = var("ZETH_JOBS_DIR") {
+            self.jobs.dir = dir.into();
+        }
+        if let Ok(path) = var("ZETH_GUEST_REGISTRY") {
+            self.jobs.guest_registry = Some(path.into());
+        }
+        if let Some(disabled) = parse_var("ZETH_AUTH_DISABLED")? {
+            self.auth.disabled = disabled;
+        }
+        self.auth.apply_env();
+
+        Ok(())
+    }
+
+    /// Checks the configuration for consistency.
+    fn validate(&self) -> Result<()> {
+        SocketAddr::from_str(&self.server.bind)
+            .with_context(|| format!("server.bind: invalid address {}", self.server.bind))?;
+        if let Some(tls) = &self.server.tls {
+            ensure!(
+                tls.cert.is_file(),
+                "server.tls.cert: {} does not exist",
+                tls.cert.display()
+            );
+            ensure!(
+                tls.key.is_file(),
+                "server.tls.key: {} does not exist",
+                tls.key.display()
+            );
+        }
+        ensure!(
+            self.server.max_upload_mib > 0,
+            "server.max_upload_mib: must be positive"
+        );
+        ensure!(
+            self.server.max_input_mib > 0,
+            "server.max_input_mib: must be positive"
+        );
+
+        if self.networks.is_empty() {
+            warn!("No networks configured, jobs can only use cached blocks");
+        }
+        for (network, config) in &self.networks {
+            let name = network.to_string().to_lowercase();
+            for url in &config.rpc_urls {
+                ensure!(
+                    url.starts_with("http://") || url.starts_with("https://"),
+                    "networks.{}.rpc_urls: {} is not an HTTP URL",
+                    name,
+                    url
+                );
+            }
+            ensure!(
+                Some(config.chain_spec) == ChainSpecName::for_network(*network),
+                "networks.{}.chain_spec: {:?} is not the chain spec of {}",
+                name,
+                config.chain_spec,
+                network
+            );
+        }
+
+        for name in self.cache.profiles.keys() {
+            ensure!(
+                name != DEFAULT_PROFILE,
+                "cache.profiles: {} is reserved for cache.root",
+                DEFAULT_PROFILE
+            );
+            ensure!(
+                !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+                "cache.profiles: invalid profile name {:?}",
+                name
+            );
+        }
+
+        let executor = &self.executor;
+        for (key, po2) in [
+            (
+                "default_segment_limit_po2",
+                executor.default_segment_limit_po2,
+            ),
+            ("max_segment_limit_po2", executor.max_segment_limit_po2),
+        ] {
+            ensure!(
+                (MIN_SEGMENT_LIMIT_PO2..=MAX_SEGMENT_LIMIT_PO2).contains(&po2),
+                "executor.{}: {} is not between {} and {}",
+                key,
+                po2,
+                MIN_SEGMENT_LIMIT_PO2,
+                MAX_SEGMENT_LIMIT_PO2
+            );
+        }
+        ensure!(
+            executor.default_segment_limit_po2 <= executor.max_segment_limit_po2,
+            "executor.default_segment_limit_po2: {} exceeds max_segment_limit_po2 {}",
+            executor.default_segment_limit_po2,
+            executor.max_segment_limit_po2
+        );
+
+        ensure!(
+            self.prover.bonsai_api_url.is_some() == self.prover.bonsai_api_key.is_some(),
+            "prover: bonsai_api_url and bonsai_api_key must be set together"
+        );
+        ensure!(
+            self.prover.poll_interval_secs > 0,
+            "prover.poll_interval_secs: must be positive"
+        );
+        ensure!(
+            self.prover.max_concurrent_jobs != Some(0),
+            "prover.max_concurrent_jobs: must be positive"
+        );
+
+        if let Some(path) = &self.jobs.guest_registry {
+            ensure!(
+                path.is_file(),
+                "jobs.guest_registry: {} does not exist",
+                path.display()
+            );
+        }
+        self.auth.validate().context("auth")?;
+
+        Ok(())
+    }
+
+    /// Returns the chain specification of the given network.
+    pub fn chain_spec(&self, network: NetworkSelection) -> Result<&'static ChainSpec> {
+        self.networks
+            .get(&network)
+            .map(|config| config.chain_spec)
+            .or_else(|| ChainSpecName::for_network(network))
+            .map(|name| name.spec())
+            .with_context(|| format!("No chain spec available for {}", network))
+    }
+
+    /// Returns the chain spec to minimize inputs of the given network with, or `None` if
+    /// minimization is disabled.
+    pub fn minimize_with(&self, network: NetworkSelection) -> Result<Option<&'static ChainSpec>> {
+        if self.executor.minimize_input {
+            self.chain_spec(network).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the RPC endpoints of the given network, which may be empty.
+    pub fn rpc_urls(&self, network: NetworkSelection) -> Vec<String> {
+        self.networks
+            .get(&network)
+            .map(|config| config.rpc_urls.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the path of the cache file of the given block in the selected directory,
+    /// or `None` if caching is disabled.
+    ///
+    /// The network must have a chain spec, so that only supported networks end up in the
+    /// path.
+    pub fn cache_file(
+        &self,
+        selection: &CacheSelection,
+        network: NetworkSelection,
+        block_no: u64,
+    ) -> Result<Option<String>> {
+        self.chain_spec(network)?;
+        Ok(self
+            .cache
+            .block_file(selection, network, block_no)?
+            .map(|path| path.to_string_lossy().into_owned()))
+    }
+
+    /// Returns the segment limit to run the executor with, given the one requested.
+    pub fn segment_limit_po2(&self, requested: Option<u32>) -> Result<u32> {
+        let po2 = requested.unwrap_or(self.executor.default_segment_limit_po2);
+        ensure!(
+            (MIN_SEGMENT_LIMIT_PO2..=self.executor.max_segment_limit_po2).contains(&po2),
+            "segment_limit_po2 {} is not between {} and {}",
+            po2,
+            MIN_SEGMENT_LIMIT_PO2,
+            self.executor.max_segment_limit_po2
+        );
+        Ok(po2)
+    }
+}
+
+fn parse_var<T: FromStr>(name: &str) -> Result<Option<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match var(name) {
+        Ok(value) => {
+            Ok(Some(value.parse().with_context(|| {
+                format!("{}: invalid value {}", name, value)
+            })?))
+        }
+        Err(_) => Ok(None),
+    }
+}
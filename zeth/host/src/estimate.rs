@@ -0,0 +1,274 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cycle estimates of blocks, and the calibration of the model behind them.
+//!
+//! Estimates only need the preflight of a block, which is read from the cache when
+//! possible, and converted into the input the same way jobs prepare it, minimized unless
+//! `executor.minimize_input` is disabled. Neither the executor nor the prover runs.
+//! Estimates are not charged against the quotas of a client, so only admin clients may
+//! preflight uncached blocks over RPC.
+
+use std::{path::Path, time::Instant};
+
+use actix_web::{post, web, HttpResponse};
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use risc0_zkvm::{serde::to_vec, ExecutorEnv};
+use serde::{Deserialize, Serialize};
+use tracing::{info, info_span};
+use zeth_lib::{
+    host::estimator::{estimate_segments, CycleModel, InputFeatures},
+    input::Input,
+};
+
+use crate::{
+    auth::ApiClient,
+    cache::{self, CacheSelection, DEFAULT_PROFILE},
+    config::Config,
+    images::GuestRegistry,
+    monitoring::{self, Stage},
+    preflight,
+    profile::{self, SessionCycles},
+    to_input, NetworkSelection,
+};
+
+/// Block to estimate.
+#[derive(Deserialize, Debug)]
+pub struct EstimateRequest {
+    /// Whether to use the server's cache, or which cache profile.
+    #[serde(default)]
+    cache: CacheSelection,
+    network: NetworkSelection,
+    block_no: u64,
+    /// Segment limit to estimate the segments for. Defaults to the configured default.
+    #[serde(default)]
+    segment_limit_po2: Option<u32>,
+}
+
+/// Size of a block and the estimated cost of proving it.
+#[derive(Serialize, Debug)]
+pub struct Estimate {
+    pub network: NetworkSelection,
+    pub block_no: u64,
+    pub transactions: u64,
+    /// Accounts touched by the block.
+    pub accounts: u64,
+    /// Storage slots touched by the block.
+    pub storage_slots: u64,
+    pub state_trie_nodes: u64,
+    /// Nodes of all storage tries.
+    pub storage_trie_nodes: u64,
+    /// Size of the serialized guest input in bytes.
+    pub input_bytes: u64,
+    /// Total size of all contracts.
+    pub contract_bytes: u64,
+    pub estimated_user_cycles: u64,
+    pub segment_limit_po2: u32,
+    /// Estimated number of segments, a lower bound as segments also spend cycles outside
+    /// of the guest code.
+    pub estimated_segments: u64,
+}
+
+impl Estimate {
+    fn new(
+        network: NetworkSelection,
+        block_no: u64,
+        input: &Input,
+        model: &CycleModel,
+        segment_limit_po2: u32,
+    ) -> Result<Self> {
+        let features = InputFeatures::of(input);
+        let state_trie_nodes = input.parent_state_trie.size() as u64;
+        let estimated_user_cycles = model.estimate(&features);
+        Ok(Estimate {
+            network,
+            block_no,
+            transactions: features.transactions,
+            accounts: features.accounts,
+            storage_slots: features.storage_slots,
+            state_trie_nodes,
+            storage_trie_nodes: features.trie_nodes - state_trie_nodes,
+            input_bytes: 4 * to_vec(input)?.len() as u64,
+            contract_bytes: features.contract_bytes,
+            estimated_user_cycles,
+            segment_limit_po2,
+            estimated_segments: estimate_segments(estimated_user_cycles, segment_limit_po2),
+        })
+    }
+}
+
+/// Estimates the size and proving cost of a block, after a preflight. Clients without
+/// admin rights are limited to cached blocks.
+#[post("/estimate")]
+pub async fn estimate_handler(
+    request: web::Json<EstimateRequest>,
+    client: web::ReqData<ApiClient>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    let EstimateRequest {
+        cache,
+        network,
+        block_no,
+        segment_limit_po2,
+    } = request.into_inner();
+    let check = || -> Result<_> {
+        config.chain_spec(network)?;
+        let segment_limit_po2 = config.segment_limit_po2(segment_limit_po2)?;
+        Ok((
+            segment_limit_po2,
+            config.cache_file(&cache, network, block_no)?,
+            config.minimize_with(network)?,
+        ))
+    };
+    let (segment_limit_po2, cache, minimize_with) = match check() {
+        Ok(prepared) => prepared,
+        Err(err) => return HttpResponse::BadRequest().body(format!("{:#}", err)),
+    };
+
+    let model = match config.estimator.clone() {
+        Some(model) => model,
+        None => {
+            return HttpResponse::ServiceUnavailable()
+                .body("No cycle model configured, fit one with `zeth calibrate`")
+        }
+    };
+    let rpc_urls = if client.admin {
+        config.rpc_urls(network)
+    } else if cache
+        .as_deref()
+        .map_or(false, |path| Path::new(path).is_file())
+    {
+        Vec::new()
+    } else {
+        return HttpResponse::Forbidden().body(format!(
+            "{} block {} is not cached, only admin clients may estimate uncached blocks",
+            network, block_no
+        ));
+    };
+    let span = info_span!("estimate", block_no, network = %network);
+    let start_time = Instant::now();
+    let estimate = web::block(move || {
+        let _span = span.entered();
+        let input = to_input(&preflight(cache, rpc_urls, block_no)?, minimize_with);
+        Estimate::new(network, block_no, &input, &model, segment_limit_po2)
+    })
+    .await;
+    monitoring::observe_stage(Stage::Preflight, start_time.elapsed());
+
+    match estimate {
+        Ok(Ok(estimate)) => {
+            info!(
+                "Estimated {} block {} at {} user cycles",
+                network, block_no, estimate.estimated_user_cycles
+            );
+            HttpResponse::Ok().json(estimate)
+        }
+        Ok(Err(err)) => HttpResponse::InternalServerError()
+            .body(format!("Could not estimate block {}: {:#}", block_no, err)),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct CalibrateArgs {
+    /// Network name.
+    #[arg(short, long, value_enum, default_value_t = NetworkSelection::Ethereum)]
+    network: NetworkSelection,
+    /// Cache profile whose blocks are executed.
+    #[arg(short, long, default_value = DEFAULT_PROFILE)]
+    profile: String,
+    /// Version of the guest to execute. Defaults to the newest guest.
+    #[arg(long)]
+    guest_version: Option<String>,
+    /// Segment limit to run the executor with.
+    #[arg(long, default_value_t = 20)]
+    segment_limit_po2: u32,
+}
+
+/// Runs the `calibrate` subcommand: executes every cached block of the profile, fits the
+/// cycle model to their user cycles and prints it as `estimator` configuration.
+pub async fn run_cli(args: CalibrateArgs, config: Config) -> Result<()> {
+    let guests = GuestRegistry::load(config.jobs.guest_registry.as_deref())?;
+    let image = match &args.guest_version {
+        Some(version) => guests
+            .get(version)
+            .with_context(|| format!("Unknown guest version {}", version))?,
+        None => &guests.images()[0],
+    }
+    .clone();
+
+    let selection = CacheSelection::Profile(args.profile.clone());
+    let mut blocks = Vec::new();
+    for block in cache::list(&config)? {
+        if block.profile == args.profile && block.network == args.network {
+            let path = config
+                .cache_file(&selection, args.network, block.block_no)?
+                .expect("profiles always have a directory");
+            blocks.push((block.block_no, path));
+        }
+    }
+    if blocks.is_empty() {
+        bail!(
+            "No cached {} blocks in profile {}",
+            args.network,
+            args.profile
+        );
+    }
+
+    let segment_limit_po2 = config.segment_limit_po2(Some(args.segment_limit_po2))?;
+    let minimize_with = config.minimize_with(args.network)?;
+    let samples = web::block(move || {
+        let mut samples = Vec::new();
+        for (block_no, path) in blocks {
+            let init = zeth_lib::host::get_initial_data(Some(path), None, block_no)
+                .with_context(|| format!("Could not init block {}", block_no))?;
+            let input = to_input(&init, minimize_with);
+            let features = InputFeatures::of(&input);
+            let cycles = execute(&input, &image.elf, segment_limit_po2)?;
+            info!(
+                "Block {}: {} user cycles, {:?}",
+                block_no, cycles.user_cycles, features
+            );
+            samples.push((features, cycles.user_cycles));
+        }
+        Ok::<_, anyhow::Error>(samples)
+    })
+    .await??;
+
+    let model = CycleModel::fit(&samples)?;
+    for (features, cycles) in &samples {
+        let estimate = model.estimate(features);
+        info!(
+            "Estimated {} user cycles for {} (error {:+.1}%)",
+            estimate,
+            cycles,
+            100.0 * (estimate as f64 - *cycles as f64) / *cycles as f64
+        );
+    }
+    println!("[estimator]\n{}", toml::to_string(&model)?);
+
+    Ok(())
+}
+
+/// Executes the guest on the input and counts its cycles, like the executor of a job.
+fn execute(input: &Input, elf: &[u8], segment_limit_po2: u32) -> Result<SessionCycles> {
+    let env = ExecutorEnv::builder()
+        .session_limit(None)
+        .segment_limit_po2(segment_limit_po2)
+        .add_input(&to_vec(input)?)
+        .build()?;
+    let (_, cycles) = profile::execute(env, elf)?;
+    Ok(cycles)
+}
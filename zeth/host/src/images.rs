@@ -0,0 +1,243 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Registry of the guest images this service can prove and verify with.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use actix_web::{get, web, HttpResponse};
+use anyhow::{bail, Context, Result};
+use risc0_zkvm::{sha::Digest, MemoryImage, Program};
+use serde::{Deserialize, Serialize};
+use zeth_guests::{ETH_BLOCK_ELF, ETH_BLOCK_ID, ETH_BLOCK_TRACKED_ELF};
+
+use crate::{jobs::ZETH_VERSION, NetworkSelection};
+
+/// First block supported by the compiled guest: Paris (Block no. 15537394).
+const BUILTIN_FIRST_BLOCK: u64 = 15537394;
+
+/// A guest ELF, together with the blocks it is able to prove.
+#[derive(Clone, Debug)]
+pub struct GuestImage {
+    /// Version label of the guest.
+    pub version: String,
+    /// The guest ELF.
+    pub elf: Vec<u8>,
+    /// The same guest, built to report the cycles of its block builder stages. It is only
+    /// run in a separate pass, never proven, and only available for the compiled guest.
+    pub tracked_elf: Option<Vec<u8>>,
+    /// Image ID of the guest.
+    pub image_id: Digest,
+    /// Networks whose chain spec is compiled into the guest.
+    pub networks: Vec<NetworkSelection>,
+    /// First supported block number.
+    pub first_block: Option<u64>,
+    /// Last supported block number.
+    pub last_block: Option<u64>,
+}
+
+impl GuestImage {
+    /// Returns the hex-encoded image ID.
+    pub fn image_id_hex(&self) -> String {
+        hex::encode(self.image_id)
+    }
+
+    /// Returns whether the guest supports the given block.
+    pub fn supports(&self, network: NetworkSelection, block_no: u64) -> bool {
+        self.networks.contains(&network)
+            && self.first_block.map_or(true, |first| block_no >= first)
+            && self.last_block.map_or(true, |last| block_no <= last)
+    }
+}
+
+/// An entry of the registry manifest.
+#[derive(Deserialize, Debug)]
+struct ManifestEntry {
+    version: String,
+    /// Path of the guest ELF.
+    elf: PathBuf,
+    /// Expected hex-encoded image ID. If present, it is checked against the ELF.
+    image_id: Option<String>,
+    networks: Vec<NetworkSelection>,
+    first_block: Option<u64>,
+    last_block: Option<u64>,
+}
+
+/// Summary of a guest image, as returned by the API.
+#[derive(Serialize, Debug)]
+pub struct GuestImageInfo {
+    pub version: String,
+    pub image_id: String,
+    pub networks: Vec<NetworkSelection>,
+    pub first_block: Option<u64>,
+    pub last_block: Option<u64>,
+}
+
+impl From<&GuestImage> for GuestImageInfo {
+    fn from(image: &GuestImage) -> Self {
+        GuestImageInfo {
+            version: image.version.clone(),
+            image_id: image.image_id_hex(),
+            networks: image.networks.clone(),
+            first_block: image.first_block,
+            last_block: image.last_block,
+        }
+    }
+}
+
+/// The guest images known to this service, newest first.
+#[derive(Clone, Debug)]
+pub struct GuestRegistry {
+    images: Vec<GuestImage>,
+}
+
+impl GuestRegistry {
+    /// Creates a registry containing only the compiled guest.
+    pub fn builtin() -> Self {
+        GuestRegistry {
+            images: vec![GuestImage {
+                version: ZETH_VERSION.to_string(),
+                elf: ETH_BLOCK_ELF.to_vec(),
+                tracked_elf: Some(ETH_BLOCK_TRACKED_ELF.to_vec()),
+                image_id: Digest::from(ETH_BLOCK_ID),
+                networks: vec![NetworkSelection::Ethereum],
+                first_block: Some(BUILTIN_FIRST_BLOCK),
+                last_block: None,
+            }],
+        }
+    }
+
+    /// Creates a registry containing the compiled guest and every guest listed in the
+    /// given manifest.
+    pub fn load(manifest: Option<&Path>) -> Result<Self> {
+        let mut registry = GuestRegistry::builtin();
+        if let Some(path) = manifest {
+            let manifest = fs::read(path)
+                .with_context(|| format!("Could not read guest registry {}", path.display()))?;
+            let entries: Vec<ManifestEntry> = serde_json::from_slice(&manifest)
+                .with_context(|| format!("Invalid guest registry {}", path.display()))?;
+            for entry in entries {
+                registry.register(load_image(entry)?)?;
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Adds an older guest image to the registry.
+    pub fn register(&mut self, image: GuestImage) -> Result<()> {
+        if self.get(&image.version).is_some() {
+            bail!("Duplicate guest version {}", image.version);
+        }
+        self.images.push(image);
+        Ok(())
+    }
+
+    /// Returns all registered images, newest first.
+    pub fn images(&self) -> &[GuestImage] {
+        &self.images
+    }
+
+    /// Returns the image with the given version label.
+    pub fn get(&self, version: &str) -> Option<&GuestImage> {
+        self.images.iter().find(|image| image.version == version)
+    }
+
+    /// Returns the image with the given image ID.
+    pub fn find_by_id(&self, image_id: &Digest) -> Option<&GuestImage> {
+        self.images.iter().find(|image| &image.image_id == image_id)
+    }
+
+    /// Returns the image ID of every registered image.
+    pub fn image_ids(&self) -> Vec<Digest> {
+        self.images.iter().map(|image| image.image_id).collect()
+    }
+
+    /// Selects the image to prove the given block with.
+    ///
+    /// If a version is pinned, that image is used, provided it supports the block.
+    /// Otherwise, the newest image supporting the block is used.
+    pub fn select(
+        &self,
+        version: Option<&str>,
+        network: NetworkSelection,
+        block_no: u64,
+    ) -> Result<&GuestImage> {
+        match version {
+            Some(version) => {
+                let image = self
+                    .get(version)
+                    .with_context(|| format!("Unknown guest version {}", version))?;
+                if !image.supports(network, block_no) {
+                    bail!(
+                        "Guest version {} does not support {} block {}",
+                        version,
+                        network,
+                        block_no
+                    );
+                }
+                Ok(image)
+            }
+            None => self
+                .images
+                .iter()
+                .find(|image| image.supports(network, block_no))
+                .with_context(|| format!("No guest supports {} block {}", network, block_no)),
+        }
+    }
+}
+
+/// Loads the ELF of a manifest entry and computes its image ID.
+fn load_image(entry: ManifestEntry) -> Result<GuestImage> {
+    let elf = fs::read(&entry.elf)
+        .with_context(|| format!("Could not read guest ELF {}", entry.elf.display()))?;
+    let image_id = compute_image_id(&elf)
+        .with_context(|| format!("Invalid guest ELF {}", entry.elf.display()))?;
+    if let Some(expected) = &entry.image_id {
+        if crate::receipts::parse_image_id(expected)? != image_id {
+            bail!(
+                "Image ID mismatch for guest version {}: {} (expected {})",
+                entry.version,
+                hex::encode(image_id),
+                expected
+            );
+        }
+    }
+
+    Ok(GuestImage {
+        version: entry.version,
+        elf,
+        tracked_elf: None,
+        image_id,
+        networks: entry.networks,
+        first_block: entry.first_block,
+        last_block: entry.last_block,
+    })
+}
+
+/// Computes the image ID of a guest ELF.
+pub fn compute_image_id(elf: &[u8]) -> Result<Digest> {
+    let program = Program::load_elf(elf, risc0_zkvm::MEM_SIZE as u32)?;
+    let image = MemoryImage::new(&program, risc0_zkvm::PAGE_SIZE as u32)?;
+    Ok(image.compute_id())
+}
+
+/// Lists the registered guest images.
+#[get("/guests")]
+pub async fn guests_handler(registry: web::Data<GuestRegistry>) -> HttpResponse {
+    let images: Vec<GuestImageInfo> = registry.images().iter().map(Into::into).collect();
+    HttpResponse::Ok().json(images)
+}
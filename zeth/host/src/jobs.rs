@@ -0,0 +1,392 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Verification jobs and the artifacts they produce.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use zeth_lib::{
+    cycle_tracker::CycleReport,
+    host::input_file::{read_header_from, InputFile, InputHeader},
+};
+use zeth_primitives::BlockHash;
+
+use crate::{
+    profile::{CycleProfile, SessionCycles},
+    Data, NetworkSelection,
+};
+
+const JOB_FILE: &str = "job.json";
+const RECEIPT_FILE: &str = "receipt.bin";
+const INPUT_FILE: &str = "input.bin";
+const PPROF_FILE: &str = "profile.pb";
+
+/// Version of this service, recorded with every artifact.
+pub const ZETH_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum JobStatus {
+    /// The job has been accepted, but not started.
+    Pending,
+    /// The job is being processed.
+    Running,
+    /// The job finished successfully.
+    Succeeded,
+    /// The job failed with the given error.
+    Failed(String),
+}
+
+/// A verification job of a single block.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Job {
+    pub id: Uuid,
+    pub network: NetworkSelection,
+    pub block_no: u64,
+    pub status: JobStatus,
+    /// Version of the guest the block is proven with.
+    #[serde(default)]
+    pub guest_version: Option<String>,
+    /// Bonsai session proving the block, if the job was submitted to Bonsai.
+    pub bonsai_session_uuid: Option<String>,
+    /// The request of the job, used to requeue it after a restart.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<Data>,
+    /// Cycle breakdown of the local execution, if the service was built with the
+    /// `profiler` feature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<CycleProfile>,
+    /// Cycles of the block builder stages, as reported by the cycle-tracking build of the
+    /// guest in a second pass of the local execution, see `executor.track_cycles`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guest_cycles: Option<CycleReport>,
+    /// Cycles of the local execution.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executor_cycles: Option<SessionCycles>,
+    /// Whether the job proves an imported input, whose final state is not checked
+    /// against a provider.
+    #[serde(default)]
+    pub imported: bool,
+}
+
+impl Job {
+    /// Returns whether the job has neither succeeded nor failed.
+    pub fn is_unfinished(&self) -> bool {
+        matches!(self.status, JobStatus::Pending | JobStatus::Running)
+    }
+}
+
+/// A successfully verified receipt, together with everything needed to re-verify it
+/// independently.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReceiptArtifact {
+    /// Version of the service that produced the receipt.
+    pub zeth_version: String,
+    /// Network of the proven block.
+    pub network: NetworkSelection,
+    /// Number of the proven block.
+    pub block_no: u64,
+    /// Version of the guest that produced the receipt.
+    pub guest_version: String,
+    /// Hex-encoded image ID the receipt verifies against.
+    pub image_id: String,
+    /// Block hash decoded from the receipt journal.
+    pub block_hash: BlockHash,
+    /// Whether the input was imported. The final state of imported blocks is not checked
+    /// against a provider, and the network, block number and expected hash are the ones
+    /// given in the input file.
+    #[serde(default)]
+    pub imported: bool,
+    /// The bincode-encoded receipt. Hex-encoded in human readable formats.
+    #[serde(with = "receipt_bytes")]
+    pub receipt: Vec<u8>,
+}
+
+/// Stores jobs in memory and persists them, together with their artifacts, to disk.
+///
+/// Every job is stored in its own directory, named after its ID.
+pub struct JobStore {
+    dir: PathBuf,
+    jobs: Mutex<HashMap<Uuid, Job>>,
+}
+
+impl JobStore {
+    /// Opens the store in the given directory, loading all previously persisted jobs.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Could not create jobs directory {}", dir.display()))?;
+
+        let mut jobs = HashMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let job_file = entry?.path().join(JOB_FILE);
+            if !job_file.is_file() {
+                continue;
+            }
+            match read_job(&job_file) {
+                Ok(job) => drop(jobs.insert(job.id, job)),
+                Err(err) => warn!("Skipping job {}: {:#}", job_file.display(), err),
+            }
+        }
+
+        Ok(JobStore {
+            dir,
+            jobs: Mutex::new(jobs),
+        })
+    }
+
+    /// Creates and persists a new pending job.
+    pub fn create(&self, request: &Data, guest_version: String, imported: bool) -> Result<Job> {
+        let job = Job {
+            id: Uuid::new_v4(),
+            network: request.network,
+            block_no: request.block_no,
+            status: JobStatus::Pending,
+            guest_version: Some(guest_version),
+            bonsai_session_uuid: None,
+            request: Some(request.clone()),
+            profile: None,
+            guest_cycles: None,
+            executor_cycles: None,
+            imported,
+        };
+        self.persist(&job)?;
+        self.jobs.lock().unwrap().insert(job.id, job.clone());
+
+        Ok(job)
+    }
+
+    /// Returns the job with the given ID.
+    pub fn get(&self, id: &Uuid) -> Option<Job> {
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+
+    /// Returns all pending and running jobs.
+    pub fn unfinished(&self) -> Vec<Job> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|job| job.is_unfinished())
+            .cloned()
+            .collect()
+    }
+
+    /// Applies `f` to the job with the given ID and persists the result.
+    pub fn update(&self, id: &Uuid, f: impl FnOnce(&mut Job)) -> Result<Job> {
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let job = jobs
+                .get_mut(id)
+                .with_context(|| format!("Unknown job {}", id))?;
+            f(job);
+            job.clone()
+        };
+        self.persist(&job)?;
+
+        Ok(job)
+    }
+
+    /// Sets the status of the job with the given ID.
+    pub fn set_status(&self, id: &Uuid, status: JobStatus) -> Result<Job> {
+        self.update(id, |job| job.status = status)
+    }
+
+    /// Persists the receipt artifact of the job with the given ID.
+    pub fn save_receipt(&self, id: &Uuid, artifact: &ReceiptArtifact) -> Result<()> {
+        let path = self.job_dir(id).join(RECEIPT_FILE);
+        fs::write(&path, bincode::serialize(artifact)?)
+            .with_context(|| format!("Could not write receipt {}", path.display()))
+    }
+
+    /// Loads the receipt artifact of the job with the given ID, if there is one.
+    pub fn load_receipt(&self, id: &Uuid) -> Result<Option<ReceiptArtifact>> {
+        let path = self.job_dir(id).join(RECEIPT_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let buf = fs::read(&path)?;
+        Ok(Some(bincode::deserialize(&buf)?))
+    }
+
+    /// Persists the prepared input of the job with the given ID.
+    pub fn save_input(&self, id: &Uuid, input: &InputFile) -> Result<()> {
+        input.save(self.input_path(id))
+    }
+
+    /// Loads the prepared input of the job with the given ID, if there is one.
+    pub fn load_input(&self, id: &Uuid) -> Result<Option<InputFile>> {
+        let path = self.input_path(id);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(InputFile::load(path)?))
+    }
+
+    /// Loads the header of the prepared input of the job with the given ID, if there is
+    /// one.
+    pub fn load_input_header(&self, id: &Uuid) -> Result<Option<InputHeader>> {
+        let path = self.input_path(id);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let file =
+            fs::File::open(&path).with_context(|| format!("Could not open {}", path.display()))?;
+        Ok(Some(read_header_from(BufReader::new(file)).with_context(
+            || format!("Invalid input file {}", path.display()),
+        )?))
+    }
+
+    /// Persists the raw pprof profile of the job with the given ID, and records its
+    /// breakdown with the job.
+    pub fn save_profile(&self, id: &Uuid, pprof: &[u8], profile: CycleProfile) -> Result<Job> {
+        let path = self.job_dir(id).join(PPROF_FILE);
+        fs::write(&path, pprof)
+            .with_context(|| format!("Could not write profile {}", path.display()))?;
+        self.update(id, |job| job.profile = Some(profile))
+    }
+
+    /// Returns the path of the prepared input of the job with the given ID.
+    pub fn input_path(&self, id: &Uuid) -> PathBuf {
+        self.job_dir(id).join(INPUT_FILE)
+    }
+
+    fn job_dir(&self, id: &Uuid) -> PathBuf {
+        self.dir.join(id.to_string())
+    }
+
+    fn persist(&self, job: &Job) -> Result<()> {
+        let dir = self.job_dir(&job.id);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(JOB_FILE), serde_json::to_vec_pretty(job)?)
+            .with_context(|| format!("Could not persist job {}", job.id))
+    }
+}
+
+fn read_job(path: &Path) -> Result<Job> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+/// Serializes the receipt bytes as hex in human readable formats and as raw bytes
+/// otherwise.
+mod receipt_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            hex::decode(s.trim_start_matches("0x")).map_err(D::Error::custom)
+        } else {
+            Vec::<u8>::deserialize(deserializer)
+        }
+    }
+}
+
+/// Encoding of a downloaded receipt artifact.
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtifactFormat {
+    /// The artifact as JSON, with the receipt hex-encoded.
+    #[default]
+    Json,
+    /// The artifact encoded with bincode.
+    Bincode,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ArtifactQuery {
+    #[serde(default)]
+    format: ArtifactFormat,
+}
+
+/// Returns the job with the given ID.
+#[get("/jobs/{id}")]
+pub async fn job_handler(jobs: web::Data<JobStore>, id: web::Path<Uuid>) -> HttpResponse {
+    match jobs.get(&id) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().body(format!("Unknown job {}", id)),
+    }
+}
+
+/// Downloads the receipt artifact of the job with the given ID.
+#[get("/jobs/{id}/receipt")]
+pub async fn receipt_handler(
+    jobs: web::Data<JobStore>,
+    id: web::Path<Uuid>,
+    query: web::Query<ArtifactQuery>,
+) -> HttpResponse {
+    if jobs.get(&id).is_none() {
+        return HttpResponse::NotFound().body(format!("Unknown job {}", id));
+    }
+    let artifact = match jobs.load_receipt(&id) {
+        Ok(Some(artifact)) => artifact,
+        Ok(None) => return HttpResponse::NotFound().body(format!("Job {} has no receipt", id)),
+        Err(err) => return HttpResponse::InternalServerError().body(format!("{:#}", err)),
+    };
+
+    match query.format {
+        ArtifactFormat::Json => HttpResponse::Ok().json(artifact),
+        ArtifactFormat::Bincode => match bincode::serialize(&artifact) {
+            Ok(buf) => HttpResponse::Ok()
+                .content_type(ContentType::octet_stream())
+                .insert_header((
+                    "Content-Disposition",
+                    format!("attachment; filename=\"{}.receipt.bin\"", id),
+                ))
+                .body(buf),
+            Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+        },
+    }
+}
+
+/// Downloads the prepared input of the job with the given ID, as written after preflight.
+///
+/// The file can be used to start a job on a service without RPC access.
+#[get("/jobs/{id}/input")]
+pub async fn input_handler(jobs: web::Data<JobStore>, id: web::Path<Uuid>) -> HttpResponse {
+    if jobs.get(&id).is_none() {
+        return HttpResponse::NotFound().body(format!("Unknown job {}", id));
+    }
+    match fs::read(jobs.input_path(&id)) {
+        Ok(buf) => HttpResponse::Ok()
+            .content_type(ContentType::octet_stream())
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}.input.bin\"", id),
+            ))
+            .body(buf),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            HttpResponse::NotFound().body(format!("Job {} has no input", id))
+        }
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
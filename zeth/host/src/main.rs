@@ -14,34 +14,79 @@
 
 extern crate core;
 
-use std::{error::Error, net::TcpListener, time::Instant};
+mod auth;
+mod cache;
+mod config;
+mod estimate;
+mod images;
+mod jobs;
+mod monitoring;
+mod profile;
+mod receipts;
+mod shutdown;
+mod telemetry;
+
+use std::{
+    error::Error,
+    net::TcpListener,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use actix::{Actor, Addr, AsyncContext, StreamHandler};
-use actix_cors::Cors;
 use actix_web::{
-    dev::Server, get, http::header::ContentType, post, web, web::Bytes, App, HttpRequest,
-    HttpResponse, HttpServer, Responder,
+    dev::{Server, Service},
+    get,
+    http::header::ContentType,
+    web,
+    web::Bytes,
+    App, HttpRequest, HttpResponse, HttpServer, Responder, ResponseError,
 };
 use actix_web_actors::ws;
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use bonsai_sdk::alpha as bonsai_sdk;
-use dotenv::var;
-use log::{error, info};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use futures_util::future::{ready, Either};
 use risc0_zkvm::{
     serde::{from_slice, to_vec},
-    Executor, ExecutorEnv, FileSegmentRef, MemoryImage, Program, Receipt,
+    ExecutorEnv, MemoryImage, Program, Receipt,
 };
-use serde::Deserialize;
-use tempfile::tempdir;
-use zeth_guests::{ETH_BLOCK_ELF, ETH_BLOCK_ID};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, info_span, warn, Instrument, Span};
+use uuid::Uuid;
 use zeth_lib::{
-    block_builder::BlockBuilder, consts::ETH_MAINNET_CHAIN_SPEC, execution::EthTxExecStrategy,
-    finalization::DebugBuildFromMemDbStrategy, host::Init, initialization::MemDbInitStrategy,
-    input::Input, mem_db::MemDb, preparation::EthHeaderPrepStrategy,
+    block_builder::BlockBuilder,
+    consts::ChainSpec,
+    execution::EthTxExecStrategy,
+    finalization::DebugBuildFromMemDbStrategy,
+    host::{estimator::InputFeatures, input_file::InputFile, witness, Init},
+    initialization::MemDbInitStrategy,
+    input::Input,
+    mem_db::MemDb,
+    preparation::EthHeaderPrepStrategy,
 };
 use zeth_primitives::BlockHash;
 
-pub struct ZethSocket;
+use crate::{
+    auth::{ApiClient, Authenticator, Quotas},
+    cache::CacheSelection,
+    config::{Config, TlsConfig},
+    images::{GuestImage, GuestRegistry},
+    jobs::{Job, JobStatus, JobStore, ReceiptArtifact, ZETH_VERSION},
+    monitoring::Stage,
+    shutdown::{Interrupted, RunningJob, Shutdown},
+};
+
+pub struct ZethSocket {
+    config: web::Data<Config>,
+    client: ApiClient,
+    quotas: Arc<Quotas>,
+    jobs: web::Data<JobStore>,
+    guests: web::Data<GuestRegistry>,
+    shutdown: web::Data<Shutdown>,
+}
+
 impl Actor for ZethSocket {
     type Context = ws::WebsocketContext<Self>;
 }
@@ -59,38 +104,87 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ZethSocket {
                 let data: Result<Data, _> = serde_json::from_str(&text);
                 match data {
                     Ok(data) => {
-                        // Get the Init
-                        let rpc_url = match data.network {
-                            NetworkSelection::Ethereum => var("ETHEREUM_RPC_URL").ok(),
-                            NetworkSelection::Goerli => var("GOERLI_RPC_URL").ok(),
-                            NetworkSelection::Sepolia => var("SEPOLIA_RPC_URL").ok(),
+                        let running = match self.shutdown.start_job() {
+                            Some(running) => running,
+                            None => {
+                                ctx.text("The service is shutting down, try again later");
+                                return;
+                            }
+                        };
+
+                        let image = match self.guests.select(
+                            data.guest_version.as_deref(),
+                            data.network,
+                            data.block_no,
+                        ) {
+                            Ok(image) => image.clone(),
+                            Err(err) => {
+                                ctx.text(format!("{:#}", err));
+                                return;
+                            }
+                        };
+                        info!("Using guest version {}", image.version);
+
+                        let segment_limit_po2 = match check_request(&self.config, &data) {
+                            Ok(segment_limit_po2) => segment_limit_po2,
+                            Err(err) => {
+                                ctx.text(format!("{:#}", err));
+                                return;
+                            }
+                        };
+
+                        let cache =
+                            match self
+                                .config
+                                .cache_file(&data.cache, data.network, data.block_no)
+                            {
+                                Ok(cache) => cache,
+                                Err(err) => {
+                                    ctx.text(format!("{:#}", err));
+                                    return;
+                                }
+                            };
+
+                        let mut permit =
+                            match self.quotas.start_job(&self.client, data.submit_to_bonsai) {
+                                Ok(permit) => permit,
+                                Err(err) => {
+                                    info!("Rejected job of {}: {}", self.client.name, err);
+                                    ctx.text(err.to_json().to_string());
+                                    return;
+                                }
+                            };
+
+                        let job = match self.jobs.create(&data, image.version.clone(), false) {
+                            Ok(job) => job,
+                            Err(err) => {
+                                ctx.text(format!("Could not create job: {:#}", err));
+                                return;
+                            }
                         };
-                        let block_no = data.block_no.clone();
-                        let cache = data.cache.as_ref().map(|dir| {
-                            cache_file_path(
-                                dir,
-                                &data.network.to_string(),
-                                data.block_no,
-                                "json.gz",
+                        permit.accept();
+                        ctx.text(format!("Job ID: {}", job.id));
+                        monitoring::job_queued();
+
+                        let span = job_span(&job);
+                        actix::spawn(
+                            process_job(
+                                QueuedJob {
+                                    id: job.id,
+                                    data,
+                                    image,
+                                    segment_limit_po2,
+                                    source: JobSource::Preflight { cache },
+                                    permit: Some(permit),
+                                    running,
+                                },
+                                Progress(Some(ctx.address())),
+                                self.config.clone(),
+                                self.jobs.clone(),
+                                self.shutdown.clone(),
                             )
-                        });
-                        // Spawn a new task to get the initial data
-                        let addr = ctx.address();
-                        actix::spawn(async move {
-                            let init = actix_web::web::block(move || {
-                                zeth_lib::host::get_initial_data(cache, rpc_url, block_no)
-                                    .expect("Could not init")
-                            })
-                            .await
-                            .unwrap();
-
-                            // Send a message to the actor to run verification
-                            addr.do_send(RunVerification {
-                                data,
-                                init,
-                                ctx: addr.clone(),
-                            });
-                        });
+                            .instrument(span),
+                        );
                     }
                     Err(e) => {
                         // Handle the error
@@ -103,22 +197,6 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ZethSocket {
         }
     }
 }
-pub struct RunVerification {
-    data: Data,
-    init: Init,
-    ctx: Addr<ZethSocket>,
-}
-impl actix::Message for RunVerification {
-    type Result = ();
-}
-
-// impl actix::Handler<RunVerification> for ZethSocket {
-//     type Result = ();
-
-//     fn handle(&mut self, msg: RunVerification, _: &mut Self::Context) -> Self::Result {
-//         let _ = run_verification(msg.data, msg.init, msg.ctx);
-//     }
-// }
 
 pub struct SendText {
     text: String,
@@ -136,45 +214,235 @@ impl actix::Handler<SendText> for ZethSocket {
     }
 }
 
-impl actix::Handler<RunVerification> for ZethSocket {
-    type Result = ();
+/// Sends the progress messages of a job to its websocket. Jobs resumed after a restart
+/// have none.
+#[derive(Clone)]
+struct Progress(Option<Addr<ZethSocket>>);
 
-    fn handle(&mut self, msg: RunVerification, ctx: &mut Self::Context) -> Self::Result {
-        let addr = ctx.address();
-        actix::spawn(run_verification(msg.data, msg.init, addr));
+impl Progress {
+    fn send(&self, text: impl Into<String>) {
+        if let Some(addr) = &self.0 {
+            addr.do_send(SendText { text: text.into() });
+        }
     }
 }
 
-// Constants
-const SERVER_ADDRESS: &str = "0.0.0.0:8000";
+/// A job that has been accepted and is ready to run.
+struct QueuedJob {
+    id: Uuid,
+    data: Data,
+    image: GuestImage,
+    /// Segment limit of the local executor, if it should be run.
+    segment_limit_po2: Option<u32>,
+    source: JobSource,
+    /// Quota reservation of the job, released when the job is done. Resumed jobs have
+    /// none.
+    permit: Option<auth::JobPermit>,
+    /// Registration of the job with the shutdown, which waits for it.
+    running: RunningJob,
+}
 
-#[derive(Deserialize, Debug, Clone)]
-struct Data {
-    cache: Option<String>,
+/// Where the guest input of a job comes from.
+enum JobSource {
+    /// Preflight over RPC, using the given cache file if caching is enabled.
+    Preflight { cache: Option<String> },
+    /// An input prepared by an earlier preflight, possibly on another machine.
+    Input(Box<InputFile>),
+}
+
+/// The guest input of a job, and what its execution is checked against.
+struct Prepared {
+    input: Input,
+    /// Hash of the block the guest must derive.
+    block_hash: BlockHash,
+    /// Result of the preflight, used to check the final state. Imported inputs have
+    /// none.
+    init: Option<Init>,
+}
+
+/// Returns the span all records of a job are emitted in.
+fn job_span(job: &Job) -> Span {
+    info_span!(
+        "job",
+        job_id = %job.id,
+        block_no = job.block_no,
+        network = %job.network,
+    )
+}
+
+/// Runs a job to completion and records its outcome.
+///
+/// A job interrupted by a shutdown is left unfinished, so that it is resumed on restart.
+async fn process_job(
+    job: QueuedJob,
+    progress: Progress,
+    config: web::Data<Config>,
+    jobs: web::Data<JobStore>,
+    shutdown: web::Data<Shutdown>,
+) {
+    let QueuedJob {
+        id,
+        data,
+        image,
+        segment_limit_po2,
+        source,
+        permit: _permit,
+        running: _running,
+    } = job;
+    let network = data.network;
+    if let Err(err) = jobs.set_status(&id, JobStatus::Running) {
+        error!("Could not update job {}: {:#}", id, err);
+    }
+    monitoring::job_started();
+
+    let result = async {
+        let prepared = match source {
+            JobSource::Preflight { cache } => {
+                // Get the Init, and persist the Input it is converted to
+                let rpc_urls = config.rpc_urls(data.network);
+                let minimize_with = config.minimize_with(data.network)?;
+                let (network, block_no) = (data.network, data.block_no);
+                let image_id = image.image_id_hex();
+                let jobs = jobs.clone();
+                let start_time = Instant::now();
+                let span = Span::current();
+                let prepared = web::block(move || {
+                    let _span = span.entered();
+                    let init = preflight(cache, rpc_urls, block_no)?;
+                    let input_file =
+                        to_input_file(network, block_no, image_id, &init, minimize_with);
+                    if let Err(err) = jobs.save_input(&id, &input_file) {
+                        warn!("Could not save the input of job {}: {:#}", id, err);
+                    }
+                    Ok::<_, anyhow::Error>(Prepared {
+                        input: input_file.input,
+                        block_hash: input_file.header.block_hash,
+                        init: Some(init),
+                    })
+                })
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|prepared| prepared);
+                monitoring::observe_stage(Stage::Preflight, start_time.elapsed());
+                prepared.context("Could not init")?
+            }
+            JobSource::Input(input_file) => {
+                info!("Using the prepared input, skipping preflight");
+                Prepared {
+                    input: input_file.input,
+                    block_hash: input_file.header.block_hash,
+                    init: None,
+                }
+            }
+        };
+        shutdown.checkpoint()?;
+
+        run_verification(
+            data,
+            image,
+            prepared,
+            segment_limit_po2,
+            &progress,
+            &config,
+            &jobs,
+            &shutdown,
+            id,
+        )
+        .await
+    }
+    .await;
+
+    finish_job(id, network, result, &progress, &jobs);
+}
+
+/// Records the outcome of a job.
+fn finish_job(
+    id: Uuid,
+    network: NetworkSelection,
+    result: Result<()>,
+    progress: &Progress,
+    jobs: &JobStore,
+) {
+    let status = match result {
+        Ok(()) => JobStatus::Succeeded,
+        Err(err) if err.is::<Interrupted>() => {
+            info!("Job {} interrupted, it is resumed after a restart", id);
+            progress.send("Interrupted by shutdown, the job is resumed after a restart");
+            return;
+        }
+        Err(err) => {
+            error!("Job {} failed: {:#}", id, err);
+            progress.send(format!("Job failed: {:#}", err));
+            JobStatus::Failed(format!("{:#}", err))
+        }
+    };
+    monitoring::job_finished(network, status == JobStatus::Succeeded);
+    if let Err(err) = jobs.set_status(&id, status) {
+        error!("Could not update job {}: {:#}", id, err);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Data {
+    /// Whether to use the server's cache, or which cache profile. Defaults to the
+    /// default profile.
+    #[serde(default)]
+    cache: CacheSelection,
     network: NetworkSelection,
     block_no: u64,
-    local_exec: Option<usize>,
+    local_exec: Option<LocalExec>,
     submit_to_bonsai: bool,
     verify_bonsai_receipt_uuid: Option<String>,
+    /// Version of the guest to prove with. Defaults to the newest guest supporting the
+    /// block.
+    guest_version: Option<String>,
 }
 
 impl Default for Data {
     fn default() -> Self {
         Self {
-            cache: None,
+            cache: CacheSelection::default(),
             network: NetworkSelection::Ethereum, // Choose a default network
             block_no: 0,
             local_exec: None,
             submit_to_bonsai: false,
             verify_bonsai_receipt_uuid: None,
+            guest_version: None,
         }
     }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+/// Whether to run the local executor, and with which segment limit.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(untagged)]
+enum LocalExec {
+    /// Runs the executor with the default segment limit, if `true`.
+    Enabled(bool),
+    /// Runs the executor with the given segment limit.
+    SegmentLimitPo2(u32),
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    ValueEnum,
+)]
 pub enum NetworkSelection {
+    #[default]
+    #[serde(alias = "ethereum")]
     Ethereum,
+    #[serde(alias = "sepolia")]
     Sepolia,
+    #[serde(alias = "goerli")]
     Goerli,
 }
 
@@ -188,24 +456,120 @@ impl std::fmt::Display for NetworkSelection {
     }
 }
 
-fn cache_file_path(cache_path: &String, network: &String, block_no: u64, ext: &str) -> String {
-    format!("{}/{}/{}.{}", cache_path, network, block_no, ext)
+/// Checks a request against the configuration and returns the segment limit of the
+/// local executor, if it should be run.
+fn check_request(config: &Config, data: &Data) -> Result<Option<u32>> {
+    config.chain_spec(data.network)?;
+    if (data.submit_to_bonsai || data.verify_bonsai_receipt_uuid.is_some())
+        && !config.prover.bonsai_enabled()
+    {
+        bail!("Bonsai is not configured");
+    }
+    match data.local_exec {
+        None | Some(LocalExec::Enabled(false)) => Ok(None),
+        Some(LocalExec::Enabled(true)) => Ok(Some(config.segment_limit_po2(None)?)),
+        Some(LocalExec::SegmentLimitPo2(po2)) => Ok(Some(config.segment_limit_po2(Some(po2))?)),
+    }
+}
+
+/// Fetches the initial data of the block, trying the RPC endpoints in order.
+///
+/// Without endpoints, the data must be cached.
+fn preflight(cache: Option<String>, rpc_urls: Vec<String>, block_no: u64) -> Result<Init> {
+    if rpc_urls.is_empty() {
+        return zeth_lib::host::get_initial_data(cache, None, block_no);
+    }
+    let count = rpc_urls.len();
+    let mut result = Err(anyhow::anyhow!("No RPC endpoint"));
+    for (i, rpc_url) in rpc_urls.into_iter().enumerate() {
+        result = zeth_lib::host::get_initial_data(cache.clone(), Some(rpc_url), block_no);
+        match &result {
+            Ok(_) => break,
+            Err(err) => warn!("RPC endpoint {} of {} failed: {:#}", i + 1, count, err),
+        }
+    }
+    result
+}
+
+/// Converts the result of a preflight into the input file of the given guest, see
+/// [to_input].
+fn to_input_file(
+    network: NetworkSelection,
+    block_no: u64,
+    image_id: String,
+    init: &Init,
+    minimize_with: Option<&ChainSpec>,
+) -> InputFile {
+    InputFile::new(
+        network.to_string(),
+        block_no,
+        image_id,
+        init.fini_block.hash(),
+        to_input(init, minimize_with),
+    )
+}
+
+/// Converts the result of a preflight into the input of the guest, as jobs prove it.
+///
+/// With a chain spec, the witness of the input is minimized. Should that fail, the full
+/// input is used.
+fn to_input(init: &Init, minimize_with: Option<&ChainSpec>) -> Input {
+    let mut input: Input = init.clone().into();
+    if let Some(chain_spec) = minimize_with {
+        let start_time = Instant::now();
+        match witness::minimize(chain_spec, input.clone()) {
+            Ok((minimized, report)) => {
+                info!(
+                    "Minimized the input from {} to {} bytes in {:?} (state trie {} to {}, \
+                     storage tries {} to {}, contracts {} to {}, ancestor headers {} to {})",
+                    report.before.total,
+                    report.after.total,
+                    start_time.elapsed(),
+                    report.before.state_trie,
+                    report.after.state_trie,
+                    report.before.storage_tries,
+                    report.after.storage_tries,
+                    report.before.contracts,
+                    report.after.contracts,
+                    report.before.ancestor_headers,
+                    report.after.ancestor_headers,
+                );
+                input = minimized;
+            }
+            Err(err) => warn!("Could not minimize the input, using it as is: {:#}", err),
+        }
+    }
+
+    input
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_verification(
     args: Data,
-    init: Init,
+    image: GuestImage,
+    prepared: Prepared,
+    segment_limit_po2: Option<u32>,
     // ctx: &mut ws::WebsocketContext<ZethSocket>,
     // ctx: Arc<Mutex<ws::WebsocketContext<ZethSocket>>>,
-    ctx: Addr<ZethSocket>,
+    progress: &Progress,
+    config: &Config,
+    jobs: &JobStore,
+    shutdown: &Shutdown,
+    job_id: Uuid,
 ) -> Result<()> {
-    let input: Input = init.clone().into();
+    let Prepared {
+        input,
+        block_hash: expected_hash,
+        init,
+    } = prepared;
+    // imported inputs come without the data of a provider
+    let imported = init.is_none();
 
     // Verify that the transactions run correctly
     {
         {
-            let input: Input = from_slice(&to_vec(&input).expect("Input serialization failed"))
-                .expect("Input deserialization failed");
+            let input: Input = from_slice(&to_vec(&input).context("Input serialization failed")?)
+                .context("Input deserialization failed")?;
 
             info!("Running from memory ...");
             // let mut bytes = Bytes::from("Running from memory ...".to_string());
@@ -215,28 +579,32 @@ async fn run_verification(
             // ctx.lock().unwrap().text("Running from memory ...");
             let mut message = "Running from memory ...";
 
-            ctx.do_send(SendText {
-                text: message.to_owned(),
-            });
+            progress.send(message.to_owned());
 
             // let resp = ws::start(ZethSocket {}, &req, stream);
 
             // addr.do_send(ws::Message::Text("Running from memory ...".to_string()));
 
-            let block_builder = BlockBuilder::<MemDb>::new(&ETH_MAINNET_CHAIN_SPEC, input)
+            let start_time = Instant::now();
+            let chain_spec: &ChainSpec = config.chain_spec(args.network)?;
+            let block_builder = BlockBuilder::<MemDb>::new(chain_spec, input)
                 .initialize_database::<MemDbInitStrategy>()
-                .expect("Error initializing MemDb from Input")
+                .context("Error initializing MemDb from Input")?
                 .prepare_header::<EthHeaderPrepStrategy>()
-                .expect("Error creating initial block header")
+                .context("Error creating initial block header")?
                 .execute_transactions::<EthTxExecStrategy>()
-                .expect("Error while running transactions");
+                .context("Error while running transactions")?;
 
-            let fini_db = block_builder.db().unwrap().clone();
+            let fini_db = block_builder
+                .db()
+                .context("The block builder has no database")?
+                .clone();
             let accounts_len = fini_db.accounts_len();
 
             let (validated_header, storage_deltas) = block_builder
                 .build::<DebugBuildFromMemDbStrategy>()
-                .expect("Error while verifying final state");
+                .context("Error while verifying final state")?;
+            monitoring::observe_stage(Stage::Execution, start_time.elapsed());
 
             info!(
                 "Memory-backed execution is Done! Database contains {} accounts",
@@ -249,7 +617,7 @@ async fn run_verification(
             )
             .to_owned();
             // ctx.text(message);
-            ctx.do_send(SendText { text: message });
+            progress.send(message);
 
             // ctx.text("Memory-backed execution is Done! Database contains {} accounts",
             // accounts_len);
@@ -258,85 +626,91 @@ async fn run_verification(
             //     accounts_len
             // )));
 
-            // Verify final state
-            let message = "Verifying final state using provider data ...".to_owned();
-            info!("Verifying final state using provider data ...");
-            ctx.do_send(SendText { text: message });
-
-            // addr.do_send(ws::Message::Text(format!(
-            //     "Verifying final state using provider data ..."
-            // )));
-
-            // ctx.text("Verifying final state using provider data ...");
-            let errors = zeth_lib::host::verify_state(fini_db, init.fini_proofs, storage_deltas)
-                .expect("Could not verify final state!");
-            for (address, address_errors) in &errors {
-                info!(
-                    "Verify found {:?} error(s) for address {:?}",
-                    address_errors.len(),
-                    address
-                );
-                for error in address_errors {
-                    match error {
-                        zeth_lib::host::VerifyError::BalanceMismatch {
-                            rpc_value,
-                            our_value,
-                            difference,
-                        } => error!(
-                            "  Error: BalanceMismatch: rpc_value={} our_value={} difference={}",
-                            rpc_value, our_value, difference
-                        ),
-                        _ => error!("  Error: {:?}", error),
+            if let Some(init) = init {
+                // Verify final state
+                let message = "Verifying final state using provider data ...".to_owned();
+                info!("Verifying final state using provider data ...");
+                progress.send(message);
+
+                // addr.do_send(ws::Message::Text(format!(
+                //     "Verifying final state using provider data ..."
+                // )));
+
+                // ctx.text("Verifying final state using provider data ...");
+                let start_time = Instant::now();
+                let errors =
+                    zeth_lib::host::verify_state(fini_db, init.fini_proofs, storage_deltas)
+                        .context("Could not verify final state")?;
+                monitoring::observe_stage(Stage::StateVerification, start_time.elapsed());
+                for (address, address_errors) in &errors {
+                    info!(
+                        "Verify found {:?} error(s) for address {:?}",
+                        address_errors.len(),
+                        address
+                    );
+                    for error in address_errors {
+                        match error {
+                            zeth_lib::host::VerifyError::BalanceMismatch {
+                                rpc_value,
+                                our_value,
+                                difference,
+                            } => error!(
+                                "  Error: BalanceMismatch: rpc_value={} our_value={} difference={}",
+                                rpc_value, our_value, difference
+                            ),
+                            _ => error!("  Error: {:?}", error),
+                        }
                     }
                 }
-            }
 
-            let errors_len = errors.len();
-            if errors_len > 0 {
-                error!(
-                    "Verify found {:?} account(s) with error(s) ({}% correct)",
-                    errors_len,
-                    (100.0 * (accounts_len - errors_len) as f64 / accounts_len as f64)
-                );
-            }
+                let errors_len = errors.len();
+                if errors_len > 0 {
+                    error!(
+                        "Verify found {:?} account(s) with error(s) ({}% correct)",
+                        errors_len,
+                        (100.0 * (accounts_len - errors_len) as f64 / accounts_len as f64)
+                    );
+                }
 
-            if validated_header.base_fee_per_gas != init.fini_block.base_fee_per_gas {
-                error!(
-                    "Base fee mismatch {} (expected {})",
-                    validated_header.base_fee_per_gas, init.fini_block.base_fee_per_gas
-                );
-            }
+                if validated_header.base_fee_per_gas != init.fini_block.base_fee_per_gas {
+                    error!(
+                        "Base fee mismatch {} (expected {})",
+                        validated_header.base_fee_per_gas, init.fini_block.base_fee_per_gas
+                    );
+                }
 
-            if validated_header.state_root != init.fini_block.state_root {
-                error!(
-                    "State root mismatch {} (expected {})",
-                    validated_header.state_root, init.fini_block.state_root
-                );
-            }
+                if validated_header.state_root != init.fini_block.state_root {
+                    error!(
+                        "State root mismatch {} (expected {})",
+                        validated_header.state_root, init.fini_block.state_root
+                    );
+                }
 
-            if validated_header.transactions_root != init.fini_block.transactions_root {
-                error!(
-                    "Transactions root mismatch {} (expected {})",
-                    validated_header.transactions_root, init.fini_block.transactions_root
-                );
-            }
+                if validated_header.transactions_root != init.fini_block.transactions_root {
+                    error!(
+                        "Transactions root mismatch {} (expected {})",
+                        validated_header.transactions_root, init.fini_block.transactions_root
+                    );
+                }
 
-            if validated_header.receipts_root != init.fini_block.receipts_root {
-                error!(
-                    "Receipts root mismatch {} (expected {})",
-                    validated_header.receipts_root, init.fini_block.receipts_root
-                );
-            }
+                if validated_header.receipts_root != init.fini_block.receipts_root {
+                    error!(
+                        "Receipts root mismatch {} (expected {})",
+                        validated_header.receipts_root, init.fini_block.receipts_root
+                    );
+                }
 
-            if validated_header.withdrawals_root != init.fini_block.withdrawals_root {
-                error!(
-                    "Withdrawals root mismatch {:?} (expected {:?})",
-                    validated_header.withdrawals_root, init.fini_block.withdrawals_root
-                );
+                if validated_header.withdrawals_root != init.fini_block.withdrawals_root {
+                    error!(
+                        "Withdrawals root mismatch {:?} (expected {:?})",
+                        validated_header.withdrawals_root, init.fini_block.withdrawals_root
+                    );
+                }
+            } else {
+                info!("Skipping the final state check, the input was imported");
             }
 
             let found_hash = validated_header.hash();
-            let expected_hash = init.fini_block.hash();
             if found_hash.as_slice() != expected_hash.as_slice() {
                 error!(
                     "Final block hash mismatch {} (expected {})",
@@ -348,17 +722,22 @@ async fn run_verification(
 
             info!("Final block hash derived successfully. {}", found_hash);
             let message = format!("Final block hash derived successfully. {}", found_hash);
-            ctx.do_send(SendText { text: message });
+            progress.send(message);
         }
+        shutdown.checkpoint()?;
 
         // Run in the executor (if requested)
-        if let Some(segment_limit_po2) = args.local_exec {
+        if let Some(segment_limit_po2) = segment_limit_po2 {
             info!(
                 "Running in executor with segment_limit_po2 = {:?}",
                 segment_limit_po2
             );
 
-            let input = to_vec(&input).expect("Could not serialize input!");
+            let estimate = config
+                .estimator
+                .as_ref()
+                .map(|model| model.estimate(&InputFeatures::of(&input)));
+            let input = to_vec(&input).context("Could not serialize input")?;
             info!(
                 "Input size: {} words ( {} MB )",
                 input.len(),
@@ -366,31 +745,24 @@ async fn run_verification(
             );
 
             #[cfg(feature = "profiler")]
-            let mut profiler =
-                risc0_zkvm::Profiler::new(zeth_guests::ETH_BLOCK_PATH, ETH_BLOCK_ELF).unwrap();
+            let mut profiler = risc0_zkvm::Profiler::new(zeth_guests::ETH_BLOCK_PATH, &image.elf)?;
 
             info!("Running the executor...");
             let start_time = Instant::now();
-            let session = {
-                let mut builder = ExecutorEnv::builder();
-                builder
-                    .session_limit(None)
-                    .segment_limit_po2(segment_limit_po2)
-                    .add_input(&input);
-
-                #[cfg(feature = "profiler")]
-                builder.trace_callback(profiler.make_trace_callback());
-
-                let env = builder.build().unwrap();
-                let mut exec = Executor::from_elf(env, ETH_BLOCK_ELF).unwrap();
-
-                let segment_dir = tempdir().unwrap();
-
-                exec.run_with_callback(|segment| {
-                    Ok(Box::new(FileSegmentRef::new(&segment, segment_dir.path())?))
-                })
-                .unwrap()
-            };
+            let (session, cycles) =
+                info_span!("executor", segment_limit_po2).in_scope(|| -> Result<_> {
+                    let mut builder = ExecutorEnv::builder();
+                    builder
+                        .session_limit(None)
+                        .segment_limit_po2(segment_limit_po2)
+                        .add_input(&input);
+
+                    #[cfg(feature = "profiler")]
+                    builder.trace_callback(profiler.make_trace_callback());
+
+                    profile::execute(builder.build()?, &image.elf)
+                })?;
+            monitoring::observe_stage(Stage::Executor, start_time.elapsed());
             info!(
                 "Generated {:?} segments; elapsed time: {:?}",
                 session.segments.len(),
@@ -401,175 +773,740 @@ async fn run_verification(
             {
                 profiler.finalize();
 
-                let sys_time = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap();
-                tokio::fs::write(
-                    format!("profile_{}.pb", sys_time.as_secs()),
-                    &profiler.encode_to_vec(),
-                )
-                .await
-                .expect("Failed to write profiling output");
+                let pprof = profiler.encode_to_vec();
+                match profile::CycleProfile::from_pprof(&pprof) {
+                    Ok(profile) => {
+                        info!(
+                            "Profiled {} cycles: initialize_database {}, prepare_header {}, \
+                             execute_transactions {}, build {}; signature recovery {}, EVM \
+                             execution {}, trie hashing {}",
+                            profile.total_cycles,
+                            profile.stages.initialize_database,
+                            profile.stages.prepare_header,
+                            profile.stages.execute_transactions,
+                            profile.stages.build,
+                            profile.categories.signature_recovery,
+                            profile.categories.evm_execution,
+                            profile.categories.trie_hashing,
+                        );
+                        if let Err(err) = jobs.save_profile(&job_id, &pprof, profile) {
+                            warn!("Could not save the profile of job {}: {:#}", job_id, err);
+                        }
+                    }
+                    Err(err) => warn!("Could not analyze the profile: {:#}", err),
+                }
             }
 
+            monitoring::observe_executor(cycles.total_cycles, cycles.segments);
             info!(
-                "Executor ran in (roughly) {} cycles",
-                session.segments.len() * (1 << segment_limit_po2)
+                "Executor ran in {} user cycles, {} total cycles",
+                cycles.user_cycles, cycles.total_cycles
             );
+            if let Some(estimate) = estimate {
+                info!("The estimator predicted {} user cycles", estimate);
+            }
 
-            let expected_hash = init.fini_block.hash();
-            let found_hash: BlockHash = from_slice(&session.journal).unwrap();
+            if let Err(err) = jobs.update(&job_id, |job| job.executor_cycles = Some(cycles)) {
+                warn!("Could not save the cycles of job {}: {:#}", job_id, err);
+            }
 
-            if found_hash == expected_hash {
-                info!("Block hash (from executor): {}", found_hash);
-            } else {
-                error!(
+            // The stages are counted by a separate build of the guest, in a second pass
+            if config.executor.track_cycles {
+                match &image.tracked_elf {
+                    Some(tracked_elf) => {
+                        info!("Running the cycle-tracking guest...");
+                        let tracked = info_span!("cycle_tracker").in_scope(|| {
+                            profile::track_cycles(&input, tracked_elf, segment_limit_po2)
+                        });
+                        match tracked {
+                            Ok((report, cycles)) => {
+                                let table = profile::cycle_table(&report, &cycles);
+                                info!("Guest cycles:\n{}", table);
+                                progress.send(table);
+                                if let Err(err) =
+                                    jobs.update(&job_id, |job| job.guest_cycles = Some(report))
+                                {
+                                    warn!("Could not save the cycles of job {}: {:#}", job_id, err);
+                                }
+                            }
+                            Err(err) => warn!("Could not track the guest cycles: {:#}", err),
+                        }
+                    }
+                    None => info!(
+                        "Guest version {} has no cycle-tracking build",
+                        image.version
+                    ),
+                }
+            }
+
+            let found_hash: BlockHash =
+                from_slice(&session.journal).context("Could not decode the journal")?;
+            if found_hash != expected_hash {
+                bail!(
                     "Final block hash mismatch (from executor) {} (expected {})",
-                    found_hash, expected_hash,
+                    found_hash,
+                    expected_hash,
                 );
             }
+            info!("Block hash (from executor): {}", found_hash);
         }
 
-        let mut bonsai_session_uuid = args.verify_bonsai_receipt_uuid;
+        shutdown.checkpoint()?;
+
+        let mut bonsai_session_uuid = args.verify_bonsai_receipt_uuid.clone();
+        let bonsai_start_time = Instant::now();
 
         if bonsai_session_uuid.is_none() && args.submit_to_bonsai {
+            let _span = info_span!("bonsai_submit").entered();
             // Run in Bonsai (if requested)
-            ctx.do_send(SendText {
-                text: "Verifying with Bonsai".to_owned(),
-            });
+            progress.send("Verifying with Bonsai".to_owned());
             info!("Creating Bonsai client");
-            let client = bonsai_sdk::Client::from_env().expect("Could not create Bonsai client");
+            let client = config.prover.bonsai_client()?;
 
             // create the memoryImg, upload it and return the imageId
             info!("Uploading memory image");
             // ctx.do_send("Uploading memory image".to_owned());
-            ctx.do_send(SendText {
-                text: "Uploading memory image".to_owned(),
-            });
+            progress.send("Uploading memory image".to_owned());
 
             let img_id = {
-                let program = Program::load_elf(ETH_BLOCK_ELF, risc0_zkvm::MEM_SIZE as u32)
-                    .expect("Could not load ELF");
-                let image = MemoryImage::new(&program, risc0_zkvm::PAGE_SIZE as u32)
-                    .expect("Could not create memory image");
-                let image_id = hex::encode(image.compute_id());
-                let image = bincode::serialize(&image).expect("Failed to serialize memory img");
-
-                match client.upload_img(&image_id, image) {
+                let program = Program::load_elf(&image.elf, risc0_zkvm::MEM_SIZE as u32)
+                    .context("Could not load ELF")?;
+                let memory_image = MemoryImage::new(&program, risc0_zkvm::PAGE_SIZE as u32)
+                    .context("Could not create memory image")?;
+                let image_id = image.image_id_hex();
+                let memory_image = bincode::serialize(&memory_image)
+                    .context("Failed to serialize memory image")?;
+
+                match client.upload_img(&image_id, memory_image) {
                     Ok(_) => (),
                     Err(bonsai_sdk::SdkErr::ImageIdExists) => (),
-                    Err(err) => panic!("Could not upload ELF: {}", err),
+                    Err(err) => bail!("Could not upload ELF: {}", err),
                 };
                 image_id
             };
 
             // Prepare input data and upload it.
             info!("Uploading inputs");
-            let input_data = to_vec(&input).unwrap();
+            let input_data = to_vec(&input).context("Could not serialize input")?;
             let input_data = bytemuck::cast_slice(&input_data).to_vec();
             let input_id = client
                 .upload_input(input_data)
-                .expect("Could not upload inputs");
+                .context("Could not upload inputs")?;
 
             // Start a session running the prover
             info!("Starting session");
             let session = client
                 .create_session(img_id, input_id)
-                .expect("Could not create Bonsai session");
+                .context("Could not create Bonsai session")?;
 
-            println!("Bonsai session UUID: {}", session.uuid);
+            info!(session_uuid = %session.uuid, "Bonsai session created");
+            let session_uuid = session.uuid.clone();
+            jobs.update(&job_id, |job| job.bonsai_session_uuid = Some(session_uuid))?;
             bonsai_session_uuid = Some(session.uuid)
         }
 
         // Verify receipt from Bonsai (if requested)
         if let Some(session_uuid) = bonsai_session_uuid {
-            let client = bonsai_sdk::Client::from_env().expect("Could not create Bonsai client");
-            let session = bonsai_sdk::SessionId { uuid: session_uuid };
-
-            loop {
-                let res = session
-                    .status(&client)
-                    .expect("Could not fetch Bonsai status");
-                if res.status == "RUNNING" {
-                    tokio::time::sleep(std::time::Duration::from_secs(15)).await;
-                    continue;
+            if args.verify_bonsai_receipt_uuid.is_some() {
+                let session_uuid = session_uuid.clone();
+                jobs.update(&job_id, |job| job.bonsai_session_uuid = Some(session_uuid))?;
+            }
+            let found_hash = poll_bonsai(
+                session_uuid,
+                &image,
+                args.network,
+                args.block_no,
+                expected_hash,
+                imported,
+                job_id,
+                config,
+                jobs,
+                shutdown,
+            )
+            .await?;
+            monitoring::observe_stage(Stage::Bonsai, bonsai_start_time.elapsed());
+
+            info!("Block hash (from Bonsai): {}", found_hash);
+            let message = format!("Block hash (from Bonsai): {}", found_hash).to_owned();
+            progress.send(message);
+        }
+    }
+
+    Ok(())
+}
+
+/// Polls a Bonsai session until it finishes, then verifies its receipt and stores it if
+/// the journal contains the expected block hash.
+///
+/// Returns the block hash committed to the journal. Stops with [Interrupted] if the
+/// service shuts down while the session is running. The receipt records whether the input
+/// was `imported`.
+#[allow(clippy::too_many_arguments)]
+async fn poll_bonsai(
+    session_uuid: String,
+    image: &GuestImage,
+    network: NetworkSelection,
+    block_no: u64,
+    expected_hash: BlockHash,
+    imported: bool,
+    job_id: Uuid,
+    config: &Config,
+    jobs: &JobStore,
+    shutdown: &Shutdown,
+) -> Result<BlockHash> {
+    let client = config.prover.bonsai_client()?;
+    let session = bonsai_sdk::SessionId { uuid: session_uuid };
+
+    let span = info_span!("bonsai_poll", session_uuid = %session.uuid);
+    async {
+        loop {
+            let res = session
+                .status(&client)
+                .context("Could not fetch Bonsai status")?;
+            if res.status == "RUNNING" {
+                shutdown.checkpoint()?;
+                tokio::time::sleep(std::time::Duration::from_secs(
+                    config.prover.poll_interval_secs,
+                ))
+                .await;
+                continue;
+            }
+            ensure!(res.status == "SUCCEEDED", "Workflow exited: {}", res.status);
+
+            // Download the receipt, containing the output
+            let receipt_url = res
+                .receipt_url
+                .context("API error, missing receipt on completed session")?;
+
+            let receipt_buf = client
+                .download(&receipt_url)
+                .context("Could not download receipt")?;
+            let receipt: Receipt =
+                bincode::deserialize(&receipt_buf).context("Could not deserialize receipt")?;
+            let (image_id, found_hash) = receipts::verify_receipt(&receipt, &[image.image_id])?;
+            if found_hash != expected_hash {
+                bail!(
+                    "Final block hash mismatch (from Bonsai) {} (expected {})",
+                    found_hash,
+                    expected_hash,
+                );
+            }
+            jobs.save_receipt(
+                &job_id,
+                &ReceiptArtifact {
+                    zeth_version: ZETH_VERSION.to_string(),
+                    network,
+                    block_no,
+                    guest_version: image.version.clone(),
+                    image_id: hex::encode(image_id),
+                    block_hash: found_hash,
+                    imported,
+                    receipt: receipt_buf,
+                },
+            )?;
+
+            return Ok(found_hash);
+        }
+    }
+    .instrument(span)
+    .await
+}
+
+/// Resumes the jobs left unfinished by a previous run.
+///
+/// Jobs with a Bonsai session poll it again; all other jobs are requeued from the start.
+fn resume_jobs(
+    config: &web::Data<Config>,
+    jobs: &web::Data<JobStore>,
+    guests: &GuestRegistry,
+    shutdown: &web::Data<Shutdown>,
+) {
+    for job in jobs.unfinished() {
+        let span = job_span(&job);
+        let _entered = span.enter();
+        let running = match shutdown.start_job() {
+            Some(running) => running,
+            None => return,
+        };
+        let image = match resume_job(&job, config, guests) {
+            Ok(resumed) => resumed,
+            Err(err) => {
+                error!("Could not resume job {}: {:#}", job.id, err);
+                let status = JobStatus::Failed(format!("Could not resume: {:#}", err));
+                if let Err(err) = jobs.set_status(&job.id, status) {
+                    error!("Could not update job {}: {:#}", job.id, err);
                 }
-                if res.status == "SUCCEEDED" {
-                    // Download the receipt, containing the output
-                    let receipt_url = res
-                        .receipt_url
-                        .expect("API error, missing receipt on completed session");
-
-                    let receipt_buf = client
-                        .download(&receipt_url)
-                        .expect("Could not download receipt");
-                    let receipt: Receipt =
-                        bincode::deserialize(&receipt_buf).expect("Could not deserialize receipt");
-                    receipt
-                        .verify(ETH_BLOCK_ID)
-                        .expect("Receipt verification failed");
-
-                    let expected_hash = init.fini_block.hash();
-                    let found_hash: BlockHash = from_slice(&receipt.journal).unwrap();
-
-                    if found_hash == expected_hash {
-                        info!("Block hash (from Bonsai): {}", found_hash);
-                        let message =
-                            format!("Block hash (from Bonsai): {}", found_hash).to_owned();
-                        ctx.do_send(SendText { text: message });
-                    } else {
-                        error!(
-                            "Final block hash mismatch (from Bonsai) {} (expected {})",
-                            found_hash, expected_hash,
-                        );
+                continue;
+            }
+        };
+
+        let future = match job.bonsai_session_uuid.clone() {
+            Some(session_uuid) => {
+                info!("Resuming Bonsai session {} of job {}", session_uuid, job.id);
+                let (config, jobs, shutdown) = (config.clone(), jobs.clone(), shutdown.clone());
+                monitoring::job_queued();
+                Either::Left(async move {
+                    let _running = running;
+                    monitoring::job_started();
+                    let result = async {
+                        // The block hash is recorded in the input prepared before submitting
+                        let expected_hash = jobs
+                            .load_input_header(&job.id)?
+                            .context("The input of the job is missing")?
+                            .block_hash;
+                        poll_bonsai(
+                            session_uuid,
+                            &image,
+                            job.network,
+                            job.block_no,
+                            expected_hash,
+                            job.imported,
+                            job.id,
+                            &config,
+                            &jobs,
+                            &shutdown,
+                        )
+                        .await
                     }
-                } else {
-                    panic!("Workflow exited: {}", res.status);
-                }
+                    .await
+                    .map(|found_hash| info!("Block hash (from Bonsai): {}", found_hash));
+                    finish_job(job.id, job.network, result, &Progress(None), &jobs);
+                })
+            }
+            None => {
+                info!("Requeuing job {}", job.id);
+                let queued = job
+                    .request
+                    .clone()
+                    .context("The job was created by an older version without its request")
+                    .and_then(|data| {
+                        let segment_limit_po2 = check_request(config, &data)?;
+                        // Skip the preflight if it already completed before the restart
+                        let source = match jobs.load_input(&job.id)? {
+                            Some(input_file) => JobSource::Input(Box::new(input_file)),
+                            None => JobSource::Preflight {
+                                cache: config.cache_file(
+                                    &data.cache,
+                                    data.network,
+                                    data.block_no,
+                                )?,
+                            },
+                        };
+                        Ok((data, segment_limit_po2, source))
+                    });
+                let (data, segment_limit_po2, source) = match queued {
+                    Ok(queued) => queued,
+                    Err(err) => {
+                        error!("Could not requeue job {}: {:#}", job.id, err);
+                        let status = JobStatus::Failed(format!("Could not requeue: {:#}", err));
+                        if let Err(err) = jobs.set_status(&job.id, status) {
+                            error!("Could not update job {}: {:#}", job.id, err);
+                        }
+                        continue;
+                    }
+                };
+                monitoring::job_queued();
+                Either::Right(process_job(
+                    QueuedJob {
+                        id: job.id,
+                        data,
+                        image,
+                        segment_limit_po2,
+                        source,
+                        permit: None,
+                        running,
+                    },
+                    Progress(None),
+                    config.clone(),
+                    jobs.clone(),
+                    shutdown.clone(),
+                ))
+            }
+        };
+        actix::spawn(future.instrument(span.clone()));
+    }
+}
 
-                break;
+/// Marks the jobs still running in a local stage as pending, so that they are requeued on
+/// restart. Jobs polling a Bonsai session keep running and are resumed instead.
+fn requeue_running(jobs: &JobStore) {
+    for job in jobs.unfinished() {
+        if job.status == JobStatus::Running && job.bonsai_session_uuid.is_none() {
+            warn!("Job {} did not finish in time, requeuing it", job.id);
+            if let Err(err) = jobs.set_status(&job.id, JobStatus::Pending) {
+                error!("Could not update job {}: {:#}", job.id, err);
             }
         }
     }
+}
 
-    Ok(())
+/// Returns the guest image an unfinished job was started with.
+fn resume_job(job: &Job, config: &Config, guests: &GuestRegistry) -> Result<GuestImage> {
+    config.chain_spec(job.network)?;
+    let image = guests.select(job.guest_version.as_deref(), job.network, job.block_no)?;
+    Ok(image.clone())
 }
 
+/// Reports the service as unavailable while it is shutting down, so that load balancers
+/// stop routing new jobs to it.
 #[get("/")]
-async fn health_check() -> impl Responder {
+async fn health_check(shutdown: web::Data<Shutdown>) -> impl Responder {
+    if shutdown.is_draining() {
+        return HttpResponse::ServiceUnavailable().body("Server is shutting down");
+    }
     HttpResponse::Ok().body("Server is running")
 }
 
+#[derive(Parser, Debug)]
+#[command(name = "zeth", version, about = "Zeth block verification service")]
+struct Cli {
+    /// Path of the configuration file. Defaults to `ZETH_CONFIG` or `zeth.toml`.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Runs the verification web service. This is the default.
+    Serve,
+    /// Verifies a bincode-encoded receipt offline, without contacting Bonsai.
+    VerifyReceipt(receipts::VerifyReceiptArgs),
+    /// Fetches the data of a block and writes the prepared guest input to a file, which
+    /// can be imported by a service without RPC access.
+    Preflight(PreflightArgs),
+    /// Executes the cached blocks of a cache profile and fits the cycle estimator to
+    /// them.
+    Calibrate(estimate::CalibrateArgs),
+}
+
+#[derive(Args, Debug)]
+struct PreflightArgs {
+    /// Network name.
+    #[arg(short, long, value_enum, default_value_t = NetworkSelection::Ethereum)]
+    network: NetworkSelection,
+    /// Block number to prepare the input of.
+    #[arg(short, long)]
+    block_no: u64,
+    /// Version of the guest the input is prepared for. Defaults to the newest guest
+    /// supporting the block.
+    #[arg(long)]
+    guest_version: Option<String>,
+    /// Whether to use the configured cache for RPC calls (`true` or `false`), or the
+    /// name of a cache profile.
+    #[arg(short, long, default_value = "true")]
+    cache: CacheSelection,
+    /// Path of the input file to write.
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+/// Runs the `preflight` subcommand.
+async fn run_preflight(args: PreflightArgs, config: Config) -> Result<()> {
+    let guests = GuestRegistry::load(config.jobs.guest_registry.as_deref())?;
+    let image = guests.select(args.guest_version.as_deref(), args.network, args.block_no)?;
+    let cache = config.cache_file(&args.cache, args.network, args.block_no)?;
+    let rpc_urls = config.rpc_urls(args.network);
+    let minimize_with = config.minimize_with(args.network)?;
+
+    let (network, block_no) = (args.network, args.block_no);
+    let image_id = image.image_id_hex();
+    let input_file = web::block(move || {
+        let init = preflight(cache, rpc_urls, block_no)?;
+        Ok::<_, anyhow::Error>(to_input_file(
+            network,
+            block_no,
+            image_id,
+            &init,
+            minimize_with,
+        ))
+    })
+    .await??;
+    input_file.save(&args.output)?;
+    info!(
+        "Wrote the input of {} block {} for guest version {} to {}",
+        network,
+        block_no,
+        image.version,
+        args.output.display()
+    );
+
+    Ok(())
+}
+
 #[actix_web::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    use env_logger::Env;
+    telemetry::init()?;
+    let cli = Cli::parse();
+    let config = Config::load(cli.config.as_deref())?;
+
+    let result = match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config).await,
+        Command::VerifyReceipt(args) => Ok(receipts::run_cli(args, config).await?),
+        Command::Preflight(args) => Ok(run_preflight(args, config).await?),
+        Command::Calibrate(args) => Ok(estimate::run_cli(args, config).await?),
+    };
+    telemetry::shutdown();
+
+    result
+}
 
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-    let listener = TcpListener::bind(SERVER_ADDRESS)?;
+async fn serve(config: Config) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(&config.server.bind)?;
+    let metrics = web::Data::new(monitoring::install()?);
+    let jobs = web::Data::new(JobStore::open(&config.jobs.dir)?);
+    let guests = web::Data::new(GuestRegistry::load(config.jobs.guest_registry.as_deref())?);
+    let authenticator = Authenticator::new(&config.auth)?;
+    let quotas = web::Data::new(Quotas::new(config.prover.max_concurrent_jobs));
+    let shutdown = web::Data::new(Shutdown::default());
+    let shutdown_timeout = Duration::from_secs(config.server.shutdown_timeout_secs);
+    let tls = config
+        .server
+        .tls
+        .as_ref()
+        .map(TlsConfig::server_config)
+        .transpose()?;
+    let config = web::Data::new(config);
+    for image in guests.images() {
+        info!(
+            "Registered guest version {} with image ID {}",
+            image.version,
+            image.image_id_hex()
+        );
+    }
+    resume_jobs(&config, &jobs, &guests, &shutdown);
 
     let server = HttpServer::new(move || {
         App::new()
-            .wrap(
-                Cors::permissive()
-                    .allow_any_origin()
-                    .allowed_methods(vec!["GET", "POST"])
-                    .allowed_header(actix_web::http::header::CONTENT_TYPE)
-                    .max_age(3600),
-            )
+            .app_data(config.clone())
+            .app_data(metrics.clone())
+            .app_data(jobs.clone())
+            .app_data(guests.clone())
+            .app_data(quotas.clone())
+            .app_data(shutdown.clone())
+            .wrap_fn({
+                let authenticator = authenticator.clone();
+                move |req, srv| match authenticator.authenticate(&req) {
+                    Ok(()) => Either::Left(srv.call(req)),
+                    Err(err) => Either::Right(ready(Err(err.into()))),
+                }
+            })
+            .wrap(auth::cors(&config.auth))
             // .service(verify_handler)
             .service(health_check)
+            .service(monitoring::metrics_handler)
+            .service(
+                web::resource("/receipts/verify")
+                    .app_data(config.server.upload_config())
+                    .route(web::post().to(receipts::verify_handler)),
+            )
+            .service(jobs::job_handler)
+            .service(jobs::receipt_handler)
+            .service(jobs::input_handler)
+            .service(
+                web::resource("/jobs/import")
+                    .app_data(config.server.upload_config())
+                    .route(web::post().to(import_handler)),
+            )
+            .service(estimate::estimate_handler)
+            .service(images::guests_handler)
+            .service(cache::list_handler)
+            .service(cache::inspect_handler)
+            .service(cache::evict_handler)
             .route("/ws/verify", web::get().to(ws_index))
     })
-    .listen(listener)?
+    .disable_signals();
+    let (drain_shutdown, drain_jobs) = (shutdown.clone(), jobs.clone());
+    let server = match tls {
+        Some(tls) => server.listen_rustls(listener, tls)?,
+        None => server.listen(listener)?,
+    }
     .run();
 
+    // Drain the running jobs before stopping the server
+    let handle = server.handle();
+    actix::spawn(async move {
+        if let Err(err) = crate::shutdown::signal().await {
+            error!("Could not listen for shutdown signals: {}", err);
+            return;
+        }
+        info!(
+            "Shutting down, waiting up to {:?} for running jobs",
+            shutdown_timeout
+        );
+        if !drain_shutdown.drain(shutdown_timeout).await {
+            requeue_running(&drain_jobs);
+        }
+        handle.stop(true).await;
+    });
+
     server.await?;
 
     Ok(())
 }
 
-async fn ws_index(req: HttpRequest, stream: web::Payload) -> HttpResponse {
-    match ws::start(ZethSocket {}, &req, stream) {
+/// Options of a job started from an input file, given in the query string.
+#[derive(Deserialize, Debug)]
+struct ImportQuery {
+    /// Runs the local executor, with the default segment limit unless one is given.
+    #[serde(default)]
+    local_exec: bool,
+    segment_limit_po2: Option<u32>,
+    #[serde(default)]
+    submit_to_bonsai: bool,
+}
+
+/// Starts a job from an input file, as written by the `preflight` subcommand or
+/// downloaded from `/jobs/{id}/input`. The job does not need any RPC access.
+///
+/// Progress is reported by `/jobs/{id}` only. Served at `POST /jobs/import`, with the
+/// upload limit of the server configuration.
+#[allow(clippy::too_many_arguments)]
+async fn import_handler(
+    body: Bytes,
+    query: web::Query<ImportQuery>,
+    config: web::Data<Config>,
+    client: web::ReqData<ApiClient>,
+    quotas: web::Data<Quotas>,
+    jobs: web::Data<JobStore>,
+    guests: web::Data<GuestRegistry>,
+    shutdown: web::Data<Shutdown>,
+) -> HttpResponse {
+    let running = match shutdown.start_job() {
+        Some(running) => running,
+        None => {
+            return HttpResponse::ServiceUnavailable()
+                .body("The service is shutting down, try again later")
+        }
+    };
+
+    let buf = body.clone();
+    let max_size = config.server.max_input_size();
+    let input_file = match web::block(move || InputFile::read_from(buf.as_ref(), max_size)).await {
+        Ok(Ok(input_file)) => input_file,
+        Ok(Err(err)) => return HttpResponse::BadRequest().body(format!("{:#}", err)),
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+    let (image, data) = match import_request(&input_file, &query, &guests) {
+        Ok(request) => request,
+        Err(err) => return HttpResponse::BadRequest().body(format!("{:#}", err)),
+    };
+    let segment_limit_po2 = match check_request(&config, &data) {
+        Ok(segment_limit_po2) => segment_limit_po2,
+        Err(err) => return HttpResponse::BadRequest().body(format!("{:#}", err)),
+    };
+    let mut permit = match quotas.start_job(&client, data.submit_to_bonsai) {
+        Ok(permit) => permit,
+        Err(err) => {
+            info!("Rejected job of {}: {}", client.name, err);
+            return err.error_response();
+        }
+    };
+
+    let job = match jobs.create(&data, image.version.clone(), true) {
+        Ok(job) => job,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Could not create job: {:#}", err))
+        }
+    };
+    permit.accept();
+    // Keep the input with the job, so that it can be requeued after a restart
+    let path = jobs.input_path(&job.id);
+    match web::block(move || std::fs::write(path, body)).await {
+        Ok(Ok(())) => (),
+        Ok(Err(err)) => error!("Could not save the input of job {}: {}", job.id, err),
+        Err(err) => error!("Could not save the input of job {}: {}", job.id, err),
+    }
+    monitoring::job_queued();
+
+    actix::spawn(
+        process_job(
+            QueuedJob {
+                id: job.id,
+                data,
+                image,
+                segment_limit_po2,
+                source: JobSource::Input(Box::new(input_file)),
+                permit: Some(permit),
+                running,
+            },
+            Progress(None),
+            config.clone(),
+            jobs.clone(),
+            shutdown.clone(),
+        )
+        .instrument(job_span(&job)),
+    );
+
+    HttpResponse::Accepted().json(job)
+}
+
+/// Checks the header of an imported input file against its input and returns the guest
+/// and the request of its job.
+fn import_request(
+    input_file: &InputFile,
+    query: &ImportQuery,
+    guests: &GuestRegistry,
+) -> Result<(GuestImage, Data)> {
+    let header = &input_file.header;
+    let parent_no = input_file.input.parent_header.number;
+    ensure!(
+        parent_no.checked_add(1) == Some(header.block_no),
+        "The input builds on block {}, which is not the parent of block {}",
+        parent_no,
+        header.block_no
+    );
+    let network = <NetworkSelection as ValueEnum>::from_str(&header.network, true)
+        .map_err(|_| anyhow!("Unknown network {}", header.network))?;
+    let image_id = receipts::parse_image_id(&header.image_id)?;
+    let image = guests
+        .find_by_id(&image_id)
+        .with_context(|| format!("No registered guest has image ID {}", header.image_id))?;
+    ensure!(
+        image.supports(network, header.block_no),
+        "Guest version {} does not support {} block {}",
+        image.version,
+        network,
+        header.block_no
+    );
+
+    let local_exec = match query.segment_limit_po2 {
+        Some(po2) => LocalExec::SegmentLimitPo2(po2),
+        None => LocalExec::Enabled(query.local_exec),
+    };
+    let data = Data {
+        cache: CacheSelection::Enabled(false),
+        network,
+        block_no: header.block_no,
+        local_exec: Some(local_exec),
+        submit_to_bonsai: query.submit_to_bonsai,
+        verify_bonsai_receipt_uuid: None,
+        guest_version: Some(image.version.clone()),
+    };
+    Ok((image.clone(), data))
+}
+
+async fn ws_index(
+    req: HttpRequest,
+    stream: web::Payload,
+    config: web::Data<Config>,
+    client: web::ReqData<ApiClient>,
+    quotas: web::Data<Quotas>,
+    jobs: web::Data<JobStore>,
+    guests: web::Data<GuestRegistry>,
+    shutdown: web::Data<Shutdown>,
+) -> HttpResponse {
+    let socket = ZethSocket {
+        config,
+        client: client.into_inner(),
+        quotas: quotas.into_inner(),
+        jobs,
+        guests,
+        shutdown,
+    };
+    match ws::start(socket, &req, stream) {
         Ok(resp) => resp,
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
@@ -0,0 +1,125 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Prometheus metrics of the verification service.
+//!
+//! The provider metrics are recorded by `zeth_lib` through the `metrics` facade; this
+//! module installs the recorder and defines the service-level metrics.
+
+use std::time::Duration;
+
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use anyhow::Result;
+use metrics::{decrement_gauge, histogram, increment_counter, increment_gauge};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::NetworkSelection;
+
+/// Histogram buckets of durations, in seconds.
+const DURATION_BUCKETS: &[f64] = &[
+    0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 1800.0, 3600.0,
+];
+/// Histogram buckets of cycle counts.
+const CYCLE_BUCKETS: &[f64] = &[1e6, 5e6, 1e7, 5e7, 1e8, 2.5e8, 5e8, 1e9, 2.5e9, 5e9, 1e10];
+/// Histogram buckets of segment counts.
+const SEGMENT_BUCKETS: &[f64] = &[1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0];
+
+/// Stage of a verification job, used as label of the stage histogram.
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    /// Fetching the block and its witness from the provider.
+    Preflight,
+    /// Building the block from memory on the host.
+    Execution,
+    /// Verifying the final state against the provider.
+    StateVerification,
+    /// Running the guest in the local executor.
+    Executor,
+    /// Proving the block with Bonsai.
+    Bonsai,
+}
+
+impl Stage {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Preflight => "preflight",
+            Stage::Execution => "execution",
+            Stage::StateVerification => "state_verification",
+            Stage::Executor => "executor",
+            Stage::Bonsai => "bonsai",
+        }
+    }
+}
+
+/// Installs the global Prometheus recorder.
+pub fn install() -> Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), DURATION_BUCKETS)?
+        .set_buckets_for_metric(
+            Matcher::Full("zeth_executor_cycles".to_string()),
+            CYCLE_BUCKETS,
+        )?
+        .set_buckets_for_metric(
+            Matcher::Full("zeth_executor_segments".to_string()),
+            SEGMENT_BUCKETS,
+        )?
+        .install_recorder()?;
+
+    Ok(handle)
+}
+
+/// Records a job entering the queue.
+pub fn job_queued() {
+    increment_gauge!("zeth_jobs_queued", 1.0);
+}
+
+/// Records a queued job being started.
+pub fn job_started() {
+    decrement_gauge!("zeth_jobs_queued", 1.0);
+    increment_gauge!("zeth_jobs_in_flight", 1.0);
+}
+
+/// Records the outcome of a started job.
+pub fn job_finished(network: NetworkSelection, succeeded: bool) {
+    decrement_gauge!("zeth_jobs_in_flight", 1.0);
+    let outcome = if succeeded { "succeeded" } else { "failed" };
+    increment_counter!(
+        "zeth_jobs_total",
+        "network" => network.to_string(),
+        "outcome" => outcome
+    );
+}
+
+/// Records the duration of a job stage.
+pub fn observe_stage(stage: Stage, duration: Duration) {
+    histogram!(
+        "zeth_stage_duration_seconds",
+        duration.as_secs_f64(),
+        "stage" => stage.as_str()
+    );
+}
+
+/// Records the cycles and segments the executor needed for a block.
+pub fn observe_executor(cycles: u64, segments: usize) {
+    histogram!("zeth_executor_cycles", cycles as f64);
+    histogram!("zeth_executor_segments", segments as f64);
+}
+
+/// Renders all metrics in the Prometheus text format.
+#[get("/metrics")]
+pub async fn metrics_handler(handle: web::Data<PrometheusHandle>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(handle.render())
+}
@@ -0,0 +1,675 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cycle breakdowns of guest executions.
+//!
+//! The guest reports the cycles of every block builder stage through its stdout, see
+//! [zeth_lib::cycle_tracker].
+//!
+//! A more detailed breakdown is derived from the output of the risc0 profiler. The
+//! profiler (enabled with the `profiler` feature) samples the call stack of the guest and
+//! writes a pprof profile. The breakdown attributes the cycles of every sample to the
+//! block builder stage and the kind of work found on its stack.
+
+#[cfg(feature = "profiler")]
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use anyhow::{Context, Result};
+use risc0_zkvm::{serde::from_slice, Executor, ExecutorEnv, FileSegmentRef, Session};
+use serde::{Deserialize, Serialize};
+use tempfile::tempdir;
+use zeth_lib::cycle_tracker::CycleReport;
+
+/// Cycles of an executor session.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SessionCycles {
+    /// Cycles spent executing guest instructions.
+    pub user_cycles: u64,
+    /// Cycles of all segments, including paging and padding to the segment size. These
+    /// determine the proving cost.
+    pub total_cycles: u64,
+    pub segments: usize,
+}
+
+impl SessionCycles {
+    /// Sums the cycles of all segments of the session. The segments must still be
+    /// available.
+    pub fn of(session: &Session) -> Result<Self> {
+        let mut cycles = SessionCycles {
+            segments: session.segments.len(),
+            ..Default::default()
+        };
+        for segment in &session.segments {
+            let segment = segment.resolve().context("Could not load segment")?;
+            cycles.user_cycles += segment.insn_cycles as u64;
+            cycles.total_cycles += 1 << segment.po2;
+        }
+        Ok(cycles)
+    }
+}
+
+/// Decodes the cycle report the guest wrote to its stdout, if any.
+pub fn read_cycle_report(stdout: &[u8]) -> Result<Option<CycleReport>> {
+    if stdout.is_empty() {
+        return Ok(None);
+    }
+    let words: Vec<u32> = stdout
+        .chunks(4)
+        .map(|chunk| {
+            let mut word = [0u8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u32::from_le_bytes(word)
+        })
+        .collect();
+    let report = from_slice(&words).context("Invalid cycle report")?;
+    Ok(Some(report))
+}
+
+/// Executes the guest and counts the cycles of its session.
+///
+/// The jobs and the calibration of the estimator both measure the proven guest with this,
+/// so that estimates and measurements are comparable. Only the journal of the returned
+/// session is available, its segments are removed.
+pub fn execute(env: ExecutorEnv<'_>, elf: &[u8]) -> Result<(Session, SessionCycles)> {
+    let mut exec = Executor::from_elf(env, elf)?;
+    let segment_dir = tempdir()?;
+    let session = exec.run_with_callback(|segment| {
+        Ok(Box::new(FileSegmentRef::new(&segment, segment_dir.path())?))
+    })?;
+    // the segments are only available until the directory is removed
+    let cycles = SessionCycles::of(&session).context("Could not count the executor cycles")?;
+    Ok((session, cycles))
+}
+
+/// Runs the cycle-tracking build of the guest on the serialized input, and returns the
+/// cycles it reports together with the cycles of its session.
+///
+/// The tracked build is not the proven guest, so its session cycles include the overhead
+/// of the tracking.
+pub fn track_cycles(
+    input: &[u32],
+    tracked_elf: &[u8],
+    segment_limit_po2: u32,
+) -> Result<(CycleReport, SessionCycles)> {
+    let mut stdout = Vec::new();
+    let (_, cycles) = {
+        let env = ExecutorEnv::builder()
+            .session_limit(None)
+            .segment_limit_po2(segment_limit_po2)
+            .add_input(input)
+            .stdout(&mut stdout)
+            .build()?;
+        execute(env, tracked_elf)?
+    };
+    let report = read_cycle_report(&stdout)?.context("The guest reported no cycles")?;
+    Ok((report, cycles))
+}
+
+/// Formats the cycle report as a table, next to the cycles of the session.
+pub fn cycle_table(report: &CycleReport, session: &SessionCycles) -> String {
+    let stages = [
+        ("read_input", report.read_input),
+        ("initialize_database", report.initialize_database),
+        ("prepare_header", report.prepare_header),
+        ("execute_transactions", report.execute_transactions),
+        ("  recover_from", report.recover_from),
+        ("build", report.build),
+    ];
+    let percent = |cycles: u64| 100.0 * cycles as f64 / session.user_cycles.max(1) as f64;
+
+    let mut table = format!("{:<24}{:>16}{:>8}\n", "Stage", "Cycles", "%");
+    for (stage, cycles) in stages {
+        writeln!(
+            table,
+            "{:<24}{:>16}{:>7.1}%",
+            stage,
+            cycles,
+            percent(cycles)
+        )
+        .unwrap();
+    }
+    if let Some(max) = report.transactions.iter().max() {
+        let average = report.transactions.iter().sum::<u64>() / report.transactions.len() as u64;
+        writeln!(
+            table,
+            "{} transactions: {} cycles on average, at most {}",
+            report.transactions.len(),
+            average,
+            max
+        )
+        .unwrap();
+    }
+    write!(
+        table,
+        "User cycles: {}, total cycles: {} in {} segments",
+        session.user_cycles, session.total_cycles, session.segments
+    )
+    .unwrap();
+    table
+}
+
+/// Number of functions listed in [CycleProfile::top_functions].
+pub const TOP_FUNCTIONS: usize = 20;
+
+/// Cycle breakdown of a guest execution.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CycleProfile {
+    /// Cycles of all samples.
+    pub total_cycles: u64,
+    /// Cycles per block builder stage.
+    pub stages: StageCycles,
+    /// Cycles per kind of work.
+    pub categories: CategoryCycles,
+    /// Functions with the most cycles spent in their own code, in descending order.
+    pub top_functions: Vec<FunctionCycles>,
+}
+
+/// Cycles spent in each stage of the block builder. Cycles outside of all stages, such
+/// as reading the input, count as `other`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StageCycles {
+    pub initialize_database: u64,
+    pub prepare_header: u64,
+    pub execute_transactions: u64,
+    pub build: u64,
+    pub other: u64,
+}
+
+/// Cycles spent on each kind of work. A sample counts towards the kind of its innermost
+/// recognized function, e.g. hashing within the EVM counts as EVM execution.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CategoryCycles {
+    pub signature_recovery: u64,
+    pub evm_execution: u64,
+    pub trie_hashing: u64,
+    pub other: u64,
+}
+
+/// Cycles attributed to a function.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FunctionCycles {
+    pub name: String,
+    /// Cycles spent in the code of the function itself.
+    pub self_cycles: u64,
+    /// Cycles spent in the function, including its callees.
+    pub total_cycles: u64,
+}
+
+#[cfg(feature = "profiler")]
+#[derive(Clone, Copy)]
+enum Stage {
+    InitializeDatabase,
+    PrepareHeader,
+    ExecuteTransactions,
+    Build,
+}
+
+#[cfg(feature = "profiler")]
+impl Stage {
+    /// Returns the stage implemented by the given function.
+    fn of(function: &str) -> Option<Self> {
+        if !function.contains("zeth_lib::") {
+            return None;
+        }
+        if function.ends_with("::initialize_database") {
+            Some(Stage::InitializeDatabase)
+        } else if function.ends_with("::prepare_header") {
+            Some(Stage::PrepareHeader)
+        } else if function.ends_with("::execute_transactions") {
+            Some(Stage::ExecuteTransactions)
+        } else if function.ends_with("::build") {
+            Some(Stage::Build)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "profiler")]
+#[derive(Clone, Copy)]
+enum Category {
+    SignatureRecovery,
+    EvmExecution,
+    TrieHashing,
+}
+
+#[cfg(feature = "profiler")]
+impl Category {
+    /// Returns the kind of work done by the given function.
+    fn of(function: &str) -> Option<Self> {
+        if function.contains("::verify_signature")
+            || function.contains("::recover_from")
+            || function.contains("k256::")
+        {
+            Some(Category::SignatureRecovery)
+        } else if function.contains("revm") {
+            Some(Category::EvmExecution)
+        } else if function.contains("MptNode")
+            && (function.ends_with("::hash") || function.contains("::reference"))
+        {
+            Some(Category::TrieHashing)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "profiler")]
+impl CycleProfile {
+    /// Adds a sample, given its call stack from the innermost function outwards.
+    fn add_sample(
+        &mut self,
+        stack: &[&str],
+        cycles: u64,
+        functions: &mut HashMap<String, FunctionCycles>,
+    ) {
+        self.total_cycles += cycles;
+
+        let stage = stack.iter().rev().find_map(|function| Stage::of(function));
+        *match stage {
+            Some(Stage::InitializeDatabase) => &mut self.stages.initialize_database,
+            Some(Stage::PrepareHeader) => &mut self.stages.prepare_header,
+            Some(Stage::ExecuteTransactions) => &mut self.stages.execute_transactions,
+            Some(Stage::Build) => &mut self.stages.build,
+            None => &mut self.stages.other,
+        } += cycles;
+
+        let category = stack.iter().find_map(|function| Category::of(function));
+        *match category {
+            Some(Category::SignatureRecovery) => &mut self.categories.signature_recovery,
+            Some(Category::EvmExecution) => &mut self.categories.evm_execution,
+            Some(Category::TrieHashing) => &mut self.categories.trie_hashing,
+            None => &mut self.categories.other,
+        } += cycles;
+
+        // recursive functions only count once towards their total
+        let mut seen = HashSet::new();
+        for (depth, function) in stack.iter().enumerate() {
+            if !seen.insert(*function) {
+                continue;
+            }
+            let entry = functions
+                .entry(function.to_string())
+                .or_insert_with(|| FunctionCycles {
+                    name: function.to_string(),
+                    ..Default::default()
+                });
+            entry.total_cycles += cycles;
+            if depth == 0 {
+                entry.self_cycles += cycles;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "profiler")]
+mod pprof {
+    //! The parts of the pprof format needed for the breakdown, see
+    //! <https://github.com/google/pprof/blob/main/proto/profile.proto>.
+
+    use std::collections::HashMap;
+
+    use anyhow::{Context, Result};
+    use prost::Message;
+
+    use super::{CycleProfile, FunctionCycles, TOP_FUNCTIONS};
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Profile {
+        #[prost(message, repeated, tag = "1")]
+        sample_type: Vec<ValueType>,
+        #[prost(message, repeated, tag = "2")]
+        sample: Vec<Sample>,
+        #[prost(message, repeated, tag = "4")]
+        location: Vec<Location>,
+        #[prost(message, repeated, tag = "5")]
+        function: Vec<Function>,
+        #[prost(string, repeated, tag = "6")]
+        string_table: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct ValueType {
+        #[prost(int64, tag = "1")]
+        r#type: i64,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Sample {
+        #[prost(uint64, repeated, tag = "1")]
+        location_id: Vec<u64>,
+        #[prost(int64, repeated, tag = "2")]
+        value: Vec<i64>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Location {
+        #[prost(uint64, tag = "1")]
+        id: u64,
+        #[prost(message, repeated, tag = "4")]
+        line: Vec<Line>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Line {
+        #[prost(uint64, tag = "1")]
+        function_id: u64,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Function {
+        #[prost(uint64, tag = "1")]
+        id: u64,
+        #[prost(int64, tag = "2")]
+        name: i64,
+    }
+
+    impl CycleProfile {
+        /// Derives the breakdown from an encoded pprof profile.
+        pub fn from_pprof(bytes: &[u8]) -> Result<Self> {
+            let profile = Profile::decode(bytes).context("Invalid pprof profile")?;
+            let string = |index: i64| -> Result<&str> {
+                profile
+                    .string_table
+                    .get(index as usize)
+                    .map(String::as_str)
+                    .with_context(|| format!("Invalid string index {}", index))
+            };
+
+            let value_index = profile
+                .sample_type
+                .iter()
+                .position(|value_type| matches!(string(value_type.r#type), Ok("cycles")))
+                .unwrap_or_default();
+            let functions: HashMap<u64, &str> = profile
+                .function
+                .iter()
+                .map(|function| Ok((function.id, string(function.name)?)))
+                .collect::<Result<_>>()?;
+            // inlined functions come first within a location
+            let locations: HashMap<u64, Vec<&str>> = profile
+                .location
+                .iter()
+                .map(|location| {
+                    let frames = location
+                        .line
+                        .iter()
+                        .map(|line| {
+                            functions.get(&line.function_id).copied().with_context(|| {
+                                format!("Invalid function ID {}", line.function_id)
+                            })
+                        })
+                        .collect::<Result<_>>()?;
+                    Ok((location.id, frames))
+                })
+                .collect::<Result<_>>()?;
+
+            let mut result = CycleProfile::default();
+            let mut cycles_per_function: HashMap<String, FunctionCycles> = HashMap::new();
+            for sample in &profile.sample {
+                let cycles = sample.value.get(value_index).copied().unwrap_or_default();
+                let mut stack = Vec::new();
+                for id in &sample.location_id {
+                    let frames = locations
+                        .get(id)
+                        .with_context(|| format!("Invalid location ID {}", id))?;
+                    stack.extend(frames.iter().copied());
+                }
+                result.add_sample(&stack, cycles.max(0) as u64, &mut cycles_per_function);
+            }
+
+            let mut top_functions: Vec<_> = cycles_per_function.into_values().collect();
+            top_functions.sort_by(|a, b| {
+                b.self_cycles
+                    .cmp(&a.self_cycles)
+                    .then_with(|| a.name.cmp(&b.name))
+            });
+            top_functions.truncate(TOP_FUNCTIONS);
+            result.top_functions = top_functions;
+
+            Ok(result)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const EXECUTE: &str = "zeth_lib::block_builder::BlockBuilder<D>::execute_transactions";
+        const BUILD: &str = "zeth_lib::block_builder::BlockBuilder<D>::build";
+        const TRANSACT: &str = "revm::evm_impl::EVMImpl::transact";
+        const RECOVER: &str = "k256::ecdsa::recovery::VerifyingKey::recover_from_prehash";
+
+        /// Builds a profile whose samples are given as stacks of locations, each a list
+        /// of function names from the innermost outwards.
+        fn profile(samples: &[(&[&[&str]], i64)]) -> Vec<u8> {
+            let mut profile = Profile {
+                sample_type: vec![ValueType { r#type: 1 }, ValueType { r#type: 2 }],
+                string_table: vec!["".into(), "samples".into(), "cycles".into()],
+                ..Default::default()
+            };
+            for (locations, cycles) in samples {
+                let mut location_id = Vec::new();
+                for frames in *locations {
+                    let mut line = Vec::new();
+                    for name in *frames {
+                        let function_id = match profile
+                            .function
+                            .iter()
+                            .find(|f| profile.string_table[f.name as usize] == *name)
+                            .map(|f| f.id)
+                        {
+                            Some(id) => id,
+                            None => {
+                                profile.string_table.push(name.to_string());
+                                let id = profile.function.len() as u64 + 1;
+                                profile.function.push(Function {
+                                    id,
+                                    name: profile.string_table.len() as i64 - 1,
+                                });
+                                id
+                            }
+                        };
+                        line.push(Line { function_id });
+                    }
+                    let id = profile.location.len() as u64 + 1;
+                    profile.location.push(Location { id, line });
+                    location_id.push(id);
+                }
+                profile.sample.push(Sample {
+                    location_id,
+                    value: vec![1, *cycles],
+                });
+            }
+            profile.encode_to_vec()
+        }
+
+        #[test]
+        fn from_pprof() {
+            let bytes = profile(&[
+                // signature recovery inlined into the transaction loop
+                (&[&[RECOVER, EXECUTE], &["main"]], 300),
+                (&[&[TRANSACT], &[EXECUTE], &["main"]], 200),
+                (&[&[BUILD], &["main"]], 50),
+                (&[&["main"]], 10),
+            ]);
+            let profile = CycleProfile::from_pprof(&bytes).unwrap();
+
+            assert_eq!(profile.total_cycles, 560);
+            assert_eq!(profile.stages.execute_transactions, 500);
+            assert_eq!(profile.stages.build, 50);
+            assert_eq!(profile.stages.other, 10);
+            assert_eq!(profile.categories.signature_recovery, 300);
+            assert_eq!(profile.categories.evm_execution, 200);
+            assert_eq!(profile.categories.other, 60);
+
+            let names: Vec<_> = profile
+                .top_functions
+                .iter()
+                .map(|f| f.name.as_str())
+                .collect();
+            assert_eq!(names, [RECOVER, TRANSACT, BUILD, "main", EXECUTE]);
+            let main = &profile.top_functions[3];
+            assert_eq!((main.self_cycles, main.total_cycles), (10, 560));
+            let execute = &profile.top_functions[4];
+            assert_eq!((execute.self_cycles, execute.total_cycles), (0, 500));
+        }
+
+        #[test]
+        fn from_pprof_rejects_unknown_locations() {
+            let mut profile = Profile::decode(profile(&[(&[&["main"]], 10)]).as_slice()).unwrap();
+            profile.sample[0].location_id.push(7);
+            assert!(CycleProfile::from_pprof(&profile.encode_to_vec()).is_err());
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use risc0_zkvm::serde::to_vec;
+
+    use super::*;
+
+    fn report() -> CycleReport {
+        CycleReport {
+            read_input: 100,
+            initialize_database: 200,
+            prepare_header: 50,
+            execute_transactions: 500,
+            build: 150,
+            recover_from: 120,
+            transactions: vec![100, 250, 150],
+        }
+    }
+
+    #[test]
+    fn reads_cycle_report() {
+        let bytes: Vec<u8> = to_vec(&report())
+            .unwrap()
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect();
+        assert_eq!(read_cycle_report(&bytes).unwrap(), Some(report()));
+        assert_eq!(read_cycle_report(&[]).unwrap(), None);
+        // a truncated report
+        assert!(read_cycle_report(&bytes[..6]).is_err());
+    }
+
+    #[test]
+    fn formats_cycle_table() {
+        let session = SessionCycles {
+            user_cycles: 1000,
+            total_cycles: 4096,
+            segments: 2,
+        };
+        let table = cycle_table(&report(), &session);
+        let lines: Vec<_> = table.lines().collect();
+
+        assert_eq!(lines.len(), 9);
+        assert!(lines[0].starts_with("Stage"));
+        assert!(lines[4].starts_with("execute_transactions"));
+        assert!(lines[4].ends_with("500   50.0%"));
+        assert!(lines[5].starts_with("  recover_from"));
+        assert_eq!(
+            lines[7],
+            "3 transactions: 166 cycles on average, at most 250"
+        );
+        assert_eq!(
+            lines[8],
+            "User cycles: 1000, total cycles: 4096 in 2 segments"
+        );
+    }
+
+    #[cfg(feature = "profiler")]
+    #[test]
+    fn stage_of() {
+        assert!(matches!(
+            Stage::of("zeth_lib::block_builder::BlockBuilder<D>::initialize_database"),
+            Some(Stage::InitializeDatabase)
+        ));
+        assert!(matches!(
+            Stage::of("zeth_lib::block_builder::BlockBuilder<D>::prepare_header"),
+            Some(Stage::PrepareHeader)
+        ));
+        assert!(matches!(
+            Stage::of("zeth_lib::block_builder::BlockBuilder<D>::execute_transactions"),
+            Some(Stage::ExecuteTransactions)
+        ));
+        assert!(matches!(
+            Stage::of("zeth_lib::block_builder::BlockBuilder<D>::build"),
+            Some(Stage::Build)
+        ));
+        // only functions of the block builder
+        assert!(Stage::of("revm::builder::EvmBuilder::build").is_none());
+        assert!(Stage::of("zeth_lib::input::Input::read").is_none());
+    }
+
+    #[cfg(feature = "profiler")]
+    #[test]
+    fn category_of() {
+        assert!(matches!(
+            Category::of("zeth_primitives::transaction::Transaction::recover_from"),
+            Some(Category::SignatureRecovery)
+        ));
+        assert!(matches!(
+            Category::of("k256::arithmetic::field::FieldElement::mul"),
+            Some(Category::SignatureRecovery)
+        ));
+        assert!(matches!(
+            Category::of("revm_interpreter::interpreter::Interpreter::run"),
+            Some(Category::EvmExecution)
+        ));
+        assert!(matches!(
+            Category::of("zeth_primitives::trie::mpt::MptNode::hash"),
+            Some(Category::TrieHashing)
+        ));
+        assert!(matches!(
+            Category::of("zeth_primitives::trie::mpt::MptNode::reference"),
+            Some(Category::TrieHashing)
+        ));
+        assert!(Category::of("zeth_primitives::trie::mpt::MptNode::get").is_none());
+        assert!(Category::of("tiny_keccak::keccakf").is_none());
+    }
+
+    #[cfg(feature = "profiler")]
+    #[test]
+    fn add_sample_attributes_cycles() {
+        const EXECUTE: &str = "zeth_lib::block_builder::BlockBuilder<D>::execute_transactions";
+        const BUILD: &str = "zeth_lib::finalization::BuildFromMemDbStrategy::build";
+        const HASH: &str = "zeth_primitives::trie::mpt::MptNode::hash";
+        const TRANSACT: &str = "revm::evm_impl::EVMImpl::transact";
+
+        let mut profile = CycleProfile::default();
+        let mut functions = HashMap::new();
+        // trie hashing within the EVM, within a recursion of the hash
+        profile.add_sample(&[HASH, HASH, TRANSACT, EXECUTE], 10, &mut functions);
+        // the outermost stage counts
+        profile.add_sample(&[HASH, BUILD, EXECUTE], 5, &mut functions);
+        profile.add_sample(&["main"], 1, &mut functions);
+
+        assert_eq!(profile.total_cycles, 16);
+        assert_eq!(profile.stages.execute_transactions, 15);
+        assert_eq!(profile.stages.other, 1);
+        // the innermost category counts
+        assert_eq!(profile.categories.trie_hashing, 15);
+        assert_eq!(profile.categories.other, 1);
+
+        let hash = &functions[HASH];
+        assert_eq!((hash.self_cycles, hash.total_cycles), (15, 15));
+        let transact = &functions[TRANSACT];
+        assert_eq!((transact.self_cycles, transact.total_cycles), (0, 10));
+        let execute = &functions[EXECUTE];
+        assert_eq!((execute.self_cycles, execute.total_cycles), (0, 15));
+    }
+}
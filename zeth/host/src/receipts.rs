@@ -0,0 +1,370 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Offline verification of receipts produced by the block guest.
+
+use std::path::PathBuf;
+
+use actix_web::{web, web::Bytes, HttpRequest, HttpResponse};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use clap::Args;
+use dotenv::var;
+use log::{error, info};
+use risc0_zkvm::{serde::from_slice, sha::Digest, Receipt};
+use serde::{Deserialize, Serialize};
+use zeth_lib::host::provider::{new_provider, BlockQuery};
+use zeth_primitives::{ethers::from_ethers_h256, BlockHash};
+
+use crate::{
+    cache::CacheSelection,
+    config::Config,
+    images::{GuestImage, GuestRegistry},
+    jobs::ReceiptArtifact,
+    NetworkSelection,
+};
+
+/// Environment variable containing a comma-separated list of additional image IDs that
+/// receipts may be verified against.
+const KNOWN_IMAGE_IDS_VAR: &str = "ZETH_KNOWN_IMAGE_IDS";
+
+/// Parameters of a receipt verification.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ReceiptRequest {
+    /// Hex-encoded image ID to verify against. It must be one of the known image IDs.
+    pub image_id: Option<String>,
+    /// Further hex-encoded image IDs to verify against. They must be known image IDs.
+    #[serde(default)]
+    pub image_ids: Vec<String>,
+    /// Network of the proven block.
+    #[serde(default)]
+    pub network: NetworkSelection,
+    /// Number of the proven block. If present, the journal is compared against the
+    /// block hash reported by the provider.
+    pub block_no: Option<u64>,
+    /// Whether to look up the block in the server's cache, or in which cache profile.
+    #[serde(default)]
+    pub cache: CacheSelection,
+    /// RPC URL used to look up the block. Defaults to the first configured URL of the
+    /// network.
+    #[serde(skip)]
+    pub rpc_url: Option<String>,
+}
+
+/// Verification parameters of a raw receipt upload, given in the query string.
+#[derive(Deserialize, Debug)]
+pub struct ReceiptQuery {
+    /// Hex-encoded known image ID to verify against.
+    image_id: Option<String>,
+    #[serde(default)]
+    network: NetworkSelection,
+    block_no: Option<u64>,
+    /// `true`, `false` or the name of a cache profile.
+    cache: Option<String>,
+}
+
+impl From<ReceiptQuery> for ReceiptRequest {
+    fn from(query: ReceiptQuery) -> Self {
+        ReceiptRequest {
+            image_id: query.image_id,
+            image_ids: Vec::new(),
+            network: query.network,
+            block_no: query.block_no,
+            cache: query
+                .cache
+                .map(|cache| cache.parse().unwrap())
+                .unwrap_or_default(),
+            rpc_url: None,
+        }
+    }
+}
+
+/// A receipt pasted as hex, together with the verification parameters.
+#[derive(Deserialize, Debug)]
+struct PastedReceipt {
+    /// Hex-encoded bincode receipt.
+    receipt: String,
+    #[serde(flatten)]
+    request: ReceiptRequest,
+}
+
+/// Outcome of a successful receipt verification.
+#[derive(Serialize, Debug, Clone)]
+pub struct ReceiptVerification {
+    /// Hex-encoded image ID the receipt was verified against.
+    pub image_id: String,
+    /// Version of the registered guest with that image ID, if any.
+    pub guest_version: Option<String>,
+    /// Block hash committed to the journal by the guest.
+    pub block_hash: BlockHash,
+    /// Block hash reported by the provider, if a block number was given.
+    pub expected_hash: Option<BlockHash>,
+    /// Whether the journal matches the expected block hash.
+    pub hash_matches: Option<bool>,
+}
+
+/// Command line arguments of the `verify-receipt` subcommand.
+#[derive(Args, Debug)]
+pub struct VerifyReceiptArgs {
+    /// Path of the bincode-encoded receipt or receipt artifact.
+    #[arg(short, long)]
+    pub receipt: PathBuf,
+    /// Hex-encoded known image ID to restrict the verification to. Can be repeated.
+    #[arg(long = "image-id")]
+    pub image_ids: Vec<String>,
+    /// Network name.
+    #[arg(short, long, value_enum, default_value_t = NetworkSelection::Ethereum)]
+    pub network: NetworkSelection,
+    /// Block number to compare the journal against.
+    #[arg(short, long)]
+    pub block_no: Option<u64>,
+    /// Whether to use the configured cache for RPC calls (`true` or `false`), or the
+    /// name of a cache profile.
+    #[arg(short, long, default_value = "true")]
+    pub cache: CacheSelection,
+    /// URL of the chain RPC node. Defaults to the first URL configured for the network.
+    #[arg(long)]
+    pub rpc_url: Option<String>,
+}
+
+/// Parses a hex-encoded image ID, with or without `0x` prefix.
+pub fn parse_image_id(image_id: &str) -> Result<Digest> {
+    let bytes: [u8; 32] = hex::decode(image_id.trim_start_matches("0x"))
+        .with_context(|| format!("Invalid image ID: {}", image_id))?
+        .try_into()
+        .map_err(|_| anyhow!("Invalid image ID: {} is not 32 bytes", image_id))?;
+    Ok(Digest::from(bytemuck::cast::<_, [u32; 8]>(bytes)))
+}
+
+/// Returns the image IDs receipts are verified against: every registered guest and every
+/// image ID listed in `ZETH_KNOWN_IMAGE_IDS`.
+pub fn known_image_ids(registry: &GuestRegistry) -> Result<Vec<Digest>> {
+    let mut image_ids = registry.image_ids();
+    if let Ok(list) = var(KNOWN_IMAGE_IDS_VAR) {
+        for image_id in list.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            image_ids.push(parse_image_id(image_id)?);
+        }
+    }
+    Ok(image_ids)
+}
+
+/// Selects the image IDs to verify against.
+///
+/// Only known image IDs are trusted. Requested image IDs can narrow them down, but a
+/// requested image ID that is not known is rejected.
+fn select_image_ids<'a>(
+    requested: impl IntoIterator<Item = &'a String>,
+    known: Vec<Digest>,
+) -> Result<Vec<Digest>> {
+    let mut image_ids = Vec::new();
+    for image_id in requested {
+        let image_id = parse_image_id(image_id)?;
+        if !known.contains(&image_id) {
+            bail!(
+                "Image ID {} is not a known guest image",
+                hex::encode(image_id)
+            );
+        }
+        image_ids.push(image_id);
+    }
+    if image_ids.is_empty() {
+        image_ids = known;
+    }
+    Ok(image_ids)
+}
+
+/// Verifies the receipt against the given image IDs and decodes its journal.
+///
+/// Returns the image ID that verified the receipt and the committed block hash.
+pub fn verify_receipt(receipt: &Receipt, image_ids: &[Digest]) -> Result<(Digest, BlockHash)> {
+    let image_id = image_ids
+        .iter()
+        .find(|image_id| receipt.verify(**image_id).is_ok())
+        .context("Receipt does not verify against any known image ID")?;
+    let block_hash: BlockHash =
+        from_slice(&receipt.journal).context("Could not decode receipt journal")?;
+
+    Ok((*image_id, block_hash))
+}
+
+/// Fetches the hash of the given block from the provider.
+pub fn fetch_block_hash(
+    cache_path: Option<String>,
+    rpc_url: Option<String>,
+    block_no: u64,
+) -> Result<BlockHash> {
+    let mut provider = new_provider(cache_path, rpc_url)?;
+    let block = provider.get_partial_block(&BlockQuery { block_no })?;
+    provider.save()?;
+
+    let hash = block
+        .hash
+        .with_context(|| format!("Block {} has no hash", block_no))?;
+    Ok(from_ethers_h256(hash))
+}
+
+/// Decodes a bincode-encoded receipt artifact or plain receipt.
+///
+/// For artifacts, the recorded network and block number fill in the request parameters
+/// that were not given. The recorded image ID is not trusted, it only selects the
+/// registered guest the receipt must verify against, which is returned as well.
+fn decode_receipt<'a>(
+    buf: &[u8],
+    request: &mut ReceiptRequest,
+    registry: &'a GuestRegistry,
+) -> Result<(Receipt, Option<&'a GuestImage>)> {
+    if let Ok(artifact) = bincode::deserialize::<ReceiptArtifact>(buf) {
+        if let Ok(image_id) = parse_image_id(&artifact.image_id) {
+            let image = registry.find_by_id(&image_id).with_context(|| {
+                format!(
+                    "The artifact was produced by guest version {} with image ID {}, which is \
+                     not registered",
+                    artifact.guest_version, artifact.image_id
+                )
+            })?;
+            if request.block_no.is_none() {
+                request.network = artifact.network;
+                request.block_no = Some(artifact.block_no);
+            }
+            let receipt = bincode::deserialize(&artifact.receipt)
+                .context("Could not deserialize artifact receipt")?;
+            return Ok((receipt, Some(image)));
+        }
+    }
+    let receipt = bincode::deserialize(buf).context("Could not deserialize receipt")?;
+    Ok((receipt, None))
+}
+
+/// Verifies a bincode-encoded receipt or receipt artifact without contacting Bonsai.
+///
+/// This performs blocking provider queries, if a block number is given.
+pub fn verify(
+    receipt_buf: &[u8],
+    mut request: ReceiptRequest,
+    registry: &GuestRegistry,
+    config: &Config,
+) -> Result<ReceiptVerification> {
+    let (receipt, image) = decode_receipt(receipt_buf, &mut request, registry)?;
+
+    let mut image_ids = select_image_ids(
+        request.image_id.iter().chain(&request.image_ids),
+        known_image_ids(registry)?,
+    )?;
+    if let Some(image) = image {
+        image_ids.retain(|image_id| image_id == &image.image_id);
+        ensure!(
+            !image_ids.is_empty(),
+            "The artifact was produced by guest version {}, which is not among the requested \
+             image IDs",
+            image.version
+        );
+    }
+    let (image_id, block_hash) = verify_receipt(&receipt, &image_ids)?;
+    info!("Receipt verified, journal block hash: {}", block_hash);
+
+    let expected_hash = match request.block_no {
+        Some(block_no) => {
+            let cache = config.cache_file(&request.cache, request.network, block_no)?;
+            let rpc_url = request.rpc_url.or_else(|| {
+                config
+                    .networks
+                    .get(&request.network)
+                    .and_then(|network| network.rpc_urls.first().cloned())
+            });
+            Some(fetch_block_hash(cache, rpc_url, block_no)?)
+        }
+        None => None,
+    };
+    let hash_matches = expected_hash.map(|expected_hash| expected_hash == block_hash);
+    if hash_matches == Some(false) {
+        error!(
+            "Block hash mismatch (from receipt) {} (expected {})",
+            block_hash,
+            expected_hash.unwrap()
+        );
+    }
+
+    Ok(ReceiptVerification {
+        image_id: hex::encode(image_id),
+        guest_version: registry
+            .find_by_id(&image_id)
+            .map(|image| image.version.clone()),
+        block_hash,
+        expected_hash,
+        hash_matches,
+    })
+}
+
+/// Verifies a receipt uploaded as a raw bincode body, or pasted as hex in a JSON body.
+///
+/// For raw uploads, the verification parameters are taken from the query string. The
+/// JSON form of a receipt artifact can be posted as is.
+///
+/// Served at `POST /receipts/verify`, with the upload limit of the server configuration.
+pub async fn verify_handler(
+    req: HttpRequest,
+    registry: web::Data<GuestRegistry>,
+    config: web::Data<Config>,
+    query: web::Query<ReceiptQuery>,
+    body: Bytes,
+) -> HttpResponse {
+    let is_json = req
+        .headers()
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| value.starts_with("application/json"));
+
+    let (receipt_buf, request) = if is_json {
+        let pasted: PastedReceipt = match serde_json::from_slice(&body) {
+            Ok(pasted) => pasted,
+            Err(err) => {
+                return HttpResponse::BadRequest().body(format!("Invalid request: {}", err))
+            }
+        };
+        match hex::decode(pasted.receipt.trim().trim_start_matches("0x")) {
+            Ok(receipt_buf) => (receipt_buf, pasted.request),
+            Err(err) => {
+                return HttpResponse::BadRequest().body(format!("Invalid receipt hex: {}", err))
+            }
+        }
+    } else {
+        (body.to_vec(), query.into_inner().into())
+    };
+
+    match web::block(move || verify(&receipt_buf, request, &registry, &config)).await {
+        Ok(Ok(verification)) => HttpResponse::Ok().json(verification),
+        Ok(Err(err)) => HttpResponse::BadRequest().body(format!("{:#}", err)),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+/// Runs the `verify-receipt` subcommand and prints the result as JSON.
+pub async fn run_cli(args: VerifyReceiptArgs, config: Config) -> Result<()> {
+    let receipt_buf = std::fs::read(&args.receipt)
+        .with_context(|| format!("Could not read receipt {}", args.receipt.display()))?;
+    let registry = GuestRegistry::load(config.jobs.guest_registry.as_deref())?;
+    let request = ReceiptRequest {
+        image_id: None,
+        image_ids: args.image_ids,
+        network: args.network,
+        block_no: args.block_no,
+        cache: args.cache,
+        rpc_url: args.rpc_url,
+    };
+
+    let verification =
+        web::block(move || verify(&receipt_buf, request, &registry, &config)).await??;
+    println!("{}", serde_json::to_string_pretty(&verification)?);
+
+    Ok(())
+}
@@ -0,0 +1,136 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Graceful shutdown.
+//!
+//! On SIGTERM or Ctrl-C, the service stops accepting jobs and waits for the running
+//! jobs to finish, up to a deadline. Jobs polling a Bonsai session stop at the next poll,
+//! as the session keeps running and is resumed after the restart. Jobs that are still
+//! unfinished at the deadline are persisted as pending and requeued on restart.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use thiserror::Error;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+/// Error of a job that stopped at a checkpoint because the service is shutting down.
+///
+/// The job is left unfinished in the store, to be resumed on restart.
+#[derive(Debug, Error)]
+#[error("Interrupted by shutdown")]
+pub struct Interrupted;
+
+/// Tracks the running jobs and whether the service is shutting down.
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    draining: AtomicBool,
+    running: Mutex<usize>,
+    idle: Notify,
+}
+
+impl Shutdown {
+    /// Returns whether the service is shutting down.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Fails with [Interrupted] if the service is shutting down.
+    pub fn checkpoint(&self) -> Result<(), Interrupted> {
+        if self.is_draining() {
+            Err(Interrupted)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Registers a running job, unless the service is shutting down.
+    ///
+    /// The job counts as running until the returned guard is dropped.
+    pub fn start_job(self: &Arc<Self>) -> Option<RunningJob> {
+        let mut running = self.running.lock().unwrap();
+        if self.is_draining() {
+            return None;
+        }
+        *running += 1;
+        Some(RunningJob {
+            shutdown: self.clone(),
+        })
+    }
+
+    /// Stops accepting jobs and waits until all running jobs are done or the deadline
+    /// has passed. Returns whether all jobs are done.
+    pub async fn drain(&self, deadline: Duration) -> bool {
+        self.draining.store(true, Ordering::SeqCst);
+        let wait = async {
+            loop {
+                let idle = self.idle.notified();
+                let running = *self.running.lock().unwrap();
+                if running == 0 {
+                    return;
+                }
+                info!("Waiting for {} running job(s)", running);
+                idle.await;
+            }
+        };
+        match tokio::time::timeout(deadline, wait).await {
+            Ok(()) => true,
+            Err(_) => {
+                let running = *self.running.lock().unwrap();
+                warn!("Shutdown deadline passed with {} running job(s)", running);
+                false
+            }
+        }
+    }
+}
+
+/// A job registered with [Shutdown].
+#[derive(Debug)]
+pub struct RunningJob {
+    shutdown: Arc<Shutdown>,
+}
+
+impl Drop for RunningJob {
+    fn drop(&mut self) {
+        let mut running = self.shutdown.running.lock().unwrap();
+        *running = running.saturating_sub(1);
+        self.shutdown.idle.notify_waiters();
+    }
+}
+
+/// Waits for SIGTERM or Ctrl-C.
+pub async fn signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            _ = terminate.recv() => info!("Received SIGTERM"),
+            result = tokio::signal::ctrl_c() => {
+                result?;
+                info!("Received Ctrl-C");
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
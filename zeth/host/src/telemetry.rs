@@ -0,0 +1,77 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Structured logging and tracing.
+//!
+//! Logs are written to stderr as JSON lines, including the spans they were emitted in.
+//! Records of the `log` crate, as used by `zeth_lib`, are forwarded as well. With the
+//! `otel` feature, spans are additionally exported over OTLP to the collector given by
+//! `ZETH_OTLP_ENDPOINT`.
+
+use anyhow::Result;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+/// Environment variable containing the OTLP endpoint spans are exported to.
+#[cfg(feature = "otel")]
+const OTLP_ENDPOINT_VAR: &str = "ZETH_OTLP_ENDPOINT";
+
+/// Installs the global subscriber. The filter is read from `RUST_LOG` and defaults to
+/// `info`.
+pub fn init() -> Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let json = fmt::layer()
+        .json()
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_writer(std::io::stderr);
+    let registry = tracing_subscriber::registry().with(filter).with(json);
+
+    #[cfg(feature = "otel")]
+    if let Ok(endpoint) = dotenv::var(OTLP_ENDPOINT_VAR) {
+        registry.with(otel_layer(endpoint)?).try_init()?;
+        return Ok(());
+    }
+
+    registry.try_init()?;
+    Ok(())
+}
+
+/// Flushes all pending spans to the exporter.
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(feature = "otel")]
+fn otel_layer<S>(endpoint: String) -> Result<impl tracing_subscriber::Layer<S>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::{sdk, KeyValue};
+    use opentelemetry_otlp::WithExportConfig;
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(sdk::trace::config().with_resource(sdk::Resource::new(vec![
+            KeyValue::new("service.name", "zeth"),
+        ])))
+        .install_batch(opentelemetry::runtime::Tokio)?;
+
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
@@ -17,12 +17,23 @@
 use std::{path::PathBuf, str::FromStr};
 
 use assert_cmd::Command;
-use risc0_zkvm::{serde::to_vec, Executor, ExecutorEnv, FileSegmentRef};
+use risc0_zkvm::{
+    serde::{from_slice, to_vec},
+    Executor, ExecutorEnv, FileSegmentRef,
+};
 use rstest::rstest;
 use tempfile::tempdir;
-use zeth_guests::ETH_BLOCK_ELF;
-use zeth_lib::input::Input;
-use zeth_primitives::trie::MptNodeData;
+use zeth_guests::{ETH_BLOCK_ELF, ETH_BLOCK_TRACKED_ELF};
+use zeth_lib::{
+    consts::ETH_MAINNET_CHAIN_SPEC, cycle_tracker::CycleReport, host::witness, input::Input,
+};
+use zeth_primitives::{
+    ethers::{from_ethers_h256, from_ethers_u256},
+    keccak::keccak,
+    rlp::Decodable,
+    trie::{table, verify_proof, MptNodeData, StateAccount},
+    BlockHash, U256,
+};
 
 #[rstest]
 fn block_cli_ethereum(#[files("testdata/ethereum/*.json.gz")] path: PathBuf) {
@@ -70,3 +81,149 @@ fn empty_blocks(#[files("testdata/ethereum/*.json.gz")] path: PathBuf) {
     // Output segment count
     println!("Generated {} segments", session.segments.len());
 }
+
+#[rstest]
+fn minimized_input(#[files("testdata/ethereum/*.json.gz")] path: PathBuf) {
+    let block_no =
+        u64::from_str(&String::from(path.file_prefix().unwrap().to_str().unwrap())).unwrap();
+    let rpc_cache = Some(format!("testdata/ethereum/{}.json.gz", block_no));
+    let init = zeth_lib::host::get_initial_data(rpc_cache, None, block_no).expect("Could not init");
+    let input: Input = init.clone().into();
+    // Minimize the witness
+    let (input, report) = witness::minimize(&ETH_MAINNET_CHAIN_SPEC, input).unwrap();
+    assert!(report.after.total <= report.before.total);
+    // The guest must still output the same block
+    let env = ExecutorEnv::builder()
+        .session_limit(None)
+        .segment_limit_po2(20)
+        .add_input(&to_vec(&input).unwrap())
+        .build()
+        .unwrap();
+    let mut exec = Executor::from_elf(env, ETH_BLOCK_ELF).unwrap();
+    let segment_dir = tempdir().unwrap();
+    let session = exec
+        .run_with_callback(|segment| {
+            Ok(Box::new(FileSegmentRef::new(&segment, segment_dir.path())?))
+        })
+        .unwrap();
+    let found_hash: BlockHash = from_slice(&session.journal).unwrap();
+    assert_eq!(found_hash, init.fini_block.hash());
+}
+
+#[rstest]
+fn eip1186_proofs(#[files("testdata/ethereum/*.json.gz")] path: PathBuf) {
+    let block_no =
+        u64::from_str(&String::from(path.file_prefix().unwrap().to_str().unwrap())).unwrap();
+    let rpc_cache = Some(format!("testdata/ethereum/{}.json.gz", block_no));
+    let init = zeth_lib::host::get_initial_data(rpc_cache, None, block_no).expect("Could not init");
+    let input: Input = init.clone().into();
+
+    let proofs = [
+        (init.init_block.state_root, &init.init_proofs),
+        (init.fini_block.state_root, &init.fini_proofs),
+    ];
+    for (state_root, proofs) in proofs {
+        for proof in proofs.values() {
+            // verify the account against the state root
+            let key = keccak(proof.address);
+            let account = verify_proof(state_root, &key, &proof.account_proof)
+                .unwrap()
+                .map(|rlp| StateAccount::decode(&mut &rlp[..]).unwrap());
+            let storage_root = match account {
+                Some(account) => {
+                    assert_eq!(account.nonce, proof.nonce.as_u64());
+                    assert_eq!(account.balance, from_ethers_u256(proof.balance));
+                    account.storage_root
+                }
+                None => {
+                    assert!(proof.nonce.is_zero() && proof.balance.is_zero());
+                    assert!(proof.storage_proof.is_empty());
+                    continue;
+                }
+            };
+            assert_eq!(storage_root, from_ethers_h256(proof.storage_hash));
+
+            // verify each slot against the storage root
+            for storage_proof in &proof.storage_proof {
+                let key = keccak(storage_proof.key);
+                let value = verify_proof(storage_root, &key, &storage_proof.proof)
+                    .unwrap()
+                    .map(|rlp| U256::decode(&mut &rlp[..]).unwrap())
+                    .unwrap_or_default();
+                assert_eq!(value, from_ethers_u256(storage_proof.value));
+            }
+        }
+    }
+
+    // the tries built from the initial proofs reproduce their account proofs
+    for proof in init.init_proofs.values() {
+        let account_proof = input
+            .parent_state_trie
+            .prove(&keccak(proof.address))
+            .unwrap();
+        assert_eq!(
+            account_proof,
+            proof
+                .account_proof
+                .iter()
+                .map(|node| node.to_vec().into())
+                .collect::<Vec<zeth_primitives::Bytes>>()
+        );
+    }
+}
+
+/// Compares the size of the guest input, and the cycles the guest spends reading it,
+/// between the tree and the node-table encoding of the tries. Run it with `--ignored`.
+#[rstest]
+#[ignore = "benchmark, run with --ignored"]
+fn trie_encodings(#[files("testdata/ethereum/*.json.gz")] path: PathBuf) {
+    let block_no =
+        u64::from_str(&String::from(path.file_prefix().unwrap().to_str().unwrap())).unwrap();
+    let rpc_cache = Some(format!("testdata/ethereum/{}.json.gz", block_no));
+    let init = zeth_lib::host::get_initial_data(rpc_cache, None, block_no).expect("Could not init");
+    let (input, _) = witness::minimize(&ETH_MAINNET_CHAIN_SPEC, init.clone().into()).unwrap();
+
+    let table_input = to_vec(&input).unwrap();
+    let tree_input = table::with_tree_encoding(|| to_vec(&input)).unwrap();
+    assert!(table_input.len() <= tree_input.len());
+
+    for (encoding, words) in [("tree", tree_input), ("table", table_input)] {
+        let mut stdout = Vec::new();
+        let journal = {
+            let env = ExecutorEnv::builder()
+                .session_limit(None)
+                .segment_limit_po2(20)
+                .add_input(&words)
+                .stdout(&mut stdout)
+                .build()
+                .unwrap();
+            let mut exec = Executor::from_elf(env, ETH_BLOCK_TRACKED_ELF).unwrap();
+            let segment_dir = tempdir().unwrap();
+            exec.run_with_callback(|segment| {
+                Ok(Box::new(FileSegmentRef::new(&segment, segment_dir.path())?))
+            })
+            .unwrap()
+            .journal
+        };
+        let found_hash: BlockHash = from_slice(&journal).unwrap();
+        assert_eq!(found_hash, init.fini_block.hash());
+
+        let report_words: Vec<u32> = stdout
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+        let report: CycleReport = from_slice(&report_words).unwrap();
+        println!(
+            "Block {} with {} tries: {} input bytes, {} cycles to read the input, {} cycles \
+             to build the block",
+            block_no,
+            encoding,
+            words.len() * 4,
+            report.read_input,
+            report.initialize_database
+                + report.prepare_header
+                + report.execute_transactions
+                + report.build,
+        );
+    }
+}
@@ -17,7 +17,7 @@ use revm::{Database, DatabaseCommit};
 use zeth_primitives::block::Header;
 
 use crate::{
-    consts::ChainSpec, execution::TxExecStrategy, finalization::BlockBuildStrategy,
+    consts::ChainSpec, cycle_tracker, execution::TxExecStrategy, finalization::BlockBuildStrategy,
     initialization::DbInitStrategy, input::Input, preparation::HeaderPrepStrategy,
 };
 
@@ -51,23 +51,39 @@ where
     }
 
     /// Initializes the database from the input tries.
+    #[cfg_attr(not(target_os = "zkvm"), tracing::instrument(skip_all))]
     pub fn initialize_database<T: DbInitStrategy<Db = D>>(self) -> Result<Self> {
-        T::initialize_database(self)
+        let span = cycle_tracker::start();
+        let result = T::initialize_database(self);
+        span.record(|report, cycles| report.initialize_database += cycles);
+        result
     }
 
     /// Initializes the header. This must be called before executing transactions.
+    #[cfg_attr(not(target_os = "zkvm"), tracing::instrument(skip_all))]
     pub fn prepare_header<T: HeaderPrepStrategy>(self) -> Result<Self> {
-        T::prepare_header(self)
+        let span = cycle_tracker::start();
+        let result = T::prepare_header(self);
+        span.record(|report, cycles| report.prepare_header += cycles);
+        result
     }
 
     /// Executes the transactions.
+    #[cfg_attr(not(target_os = "zkvm"), tracing::instrument(skip_all))]
     pub fn execute_transactions<T: TxExecStrategy>(self) -> Result<Self> {
-        T::execute_transactions(self)
+        let span = cycle_tracker::start();
+        let result = T::execute_transactions(self);
+        span.record(|report, cycles| report.execute_transactions += cycles);
+        result
     }
 
     /// Builds the block and returns the header.
+    #[cfg_attr(not(target_os = "zkvm"), tracing::instrument(name = "build", skip_all))]
     pub fn build<T: BlockBuildStrategy<Db = D>>(self) -> Result<T::Output> {
-        T::build(self)
+        let span = cycle_tracker::start();
+        let result = T::build(self);
+        span.record(|report, cycles| report.build += cycles);
+        result
     }
 
     /// Returns a reference to the database.
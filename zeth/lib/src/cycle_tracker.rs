@@ -0,0 +1,170 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Accounting of the cycles spent in the stages of the [BlockBuilder].
+//!
+//! Tracking is disabled unless the guest enables it with the cycle counter of the zkVM.
+//! Spans started while tracking is disabled record nothing.
+//!
+//! [BlockBuilder]: crate::block_builder::BlockBuilder
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+/// Cycles spent in the stages of building a block.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CycleReport {
+    /// Cycles spent deserializing the input, including the rebuilding of its tries.
+    pub read_input: u64,
+    pub initialize_database: u64,
+    pub prepare_header: u64,
+    pub execute_transactions: u64,
+    pub build: u64,
+    /// Cycles spent recovering the senders of all transactions.
+    pub recover_from: u64,
+    /// Cycles spent on each transaction, including the recovery of its sender.
+    pub transactions: Vec<u64>,
+}
+
+struct Tracker {
+    counter: fn() -> usize,
+    report: CycleReport,
+}
+
+/// Accounting of cycles, disabled until [CycleTracker::enable] is called.
+pub struct CycleTracker {
+    tracker: Mutex<Option<Tracker>>,
+}
+
+impl CycleTracker {
+    /// Creates a disabled tracker.
+    pub const fn new() -> Self {
+        CycleTracker {
+            tracker: Mutex::new(None),
+        }
+    }
+
+    /// Enables tracking, reading cycles from the given counter.
+    pub fn enable(&self, counter: fn() -> usize) {
+        *self.tracker.lock().unwrap() = Some(Tracker {
+            counter,
+            report: CycleReport::default(),
+        });
+    }
+
+    /// Returns the cycles recorded so far and disables tracking, or `None` if tracking
+    /// is disabled.
+    pub fn take_report(&self) -> Option<CycleReport> {
+        self.tracker
+            .lock()
+            .unwrap()
+            .take()
+            .map(|tracker| tracker.report)
+    }
+
+    /// Starts a tracked section.
+    pub fn start(&self) -> Span<'_> {
+        let start = self
+            .tracker
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|tracker| (tracker.counter)());
+        Span {
+            tracker: self,
+            start,
+        }
+    }
+}
+
+impl Default for CycleTracker {
+    fn default() -> Self {
+        CycleTracker::new()
+    }
+}
+
+/// The tracker of the guest.
+static TRACKER: CycleTracker = CycleTracker::new();
+
+/// Enables tracking of the guest, reading cycles from the given counter.
+pub fn enable(counter: fn() -> usize) {
+    TRACKER.enable(counter);
+}
+
+/// Returns the cycles recorded so far and disables tracking, or `None` if tracking is
+/// disabled.
+pub fn take_report() -> Option<CycleReport> {
+    TRACKER.take_report()
+}
+
+/// Starts a tracked section of the guest.
+pub fn start() -> Span<'static> {
+    TRACKER.start()
+}
+
+/// Cycle count at the start of a tracked section.
+#[must_use]
+pub struct Span<'a> {
+    tracker: &'a CycleTracker,
+    start: Option<usize>,
+}
+
+impl Span<'_> {
+    /// Ends the section and adds the cycles spent in it to the report.
+    pub fn record(self, f: impl FnOnce(&mut CycleReport, u64)) {
+        let start = match self.start {
+            Some(start) => start,
+            None => return,
+        };
+        if let Some(tracker) = self.tracker.tracker.lock().unwrap().as_mut() {
+            let cycles = (tracker.counter)().saturating_sub(start) as u64;
+            f(&mut tracker.report, cycles);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    static CYCLES: AtomicUsize = AtomicUsize::new(0);
+
+    fn counter() -> usize {
+        CYCLES.fetch_add(10, Ordering::SeqCst)
+    }
+
+    #[test]
+    fn records_only_while_enabled() {
+        let tracker = CycleTracker::new();
+        tracker
+            .start()
+            .record(|report, cycles| report.build += cycles);
+        assert_eq!(tracker.take_report(), None);
+
+        tracker.enable(counter);
+        let span = tracker.start();
+        tracker
+            .start()
+            .record(|report, cycles| report.transactions.push(cycles));
+        span.record(|report, cycles| report.execute_transactions += cycles);
+
+        let report = tracker.take_report().unwrap();
+        assert_eq!(report.transactions, vec![10]);
+        assert_eq!(report.execute_transactions, 30);
+        assert_eq!(tracker.take_report(), None);
+    }
+}
@@ -36,7 +36,7 @@ use crate::{
     block_builder::BlockBuilder,
     consts,
     consts::{GWEI_TO_WEI, MIN_SPEC_ID},
-    guest_mem_forget,
+    cycle_tracker, guest_mem_forget,
 };
 
 pub trait TxExecStrategy {
@@ -112,16 +112,21 @@ impl TxExecStrategy for EthTxExecStrategy {
         let mut cumulative_gas_used = consts::ZERO;
 
         // process all the transactions
-        let mut tx_trie = MptNode::default();
-        let mut receipt_trie = MptNode::default();
+        let chain_id = block_builder.chain_spec.chain_id();
+        let mut tx_entries = Vec::new();
+        let mut receipt_entries = Vec::new();
         for (tx_no, tx) in take(&mut block_builder.input.transactions)
             .into_iter()
             .enumerate()
         {
+            let tx_span = cycle_tracker::start();
+
             // verify the transaction signature
+            let recover_span = cycle_tracker::start();
             let tx_from = tx
-                .recover_from()
-                .with_context(|| format!("Error recovering address for transaction {}", tx_no))?;
+                .verify_signature(chain_id)
+                .with_context(|| format!("Invalid signature of transaction {}", tx_no))?;
+            recover_span.record(|report, cycles| report.recover_from += cycles);
 
             #[cfg(not(target_os = "zkvm"))]
             {
@@ -162,14 +167,10 @@ impl TxExecStrategy for EthTxExecStrategy {
             // accumulate logs to the block bloom filter
             logs_bloom.accrue_bloom(receipt.payload.logs_bloom);
 
-            // Add receipt and tx to tries
+            // Collect receipt and tx for their tries
             let trie_key = tx_no.to_rlp();
-            tx_trie
-                .insert_rlp(&trie_key, tx)
-                .context("failed to insert transaction")?;
-            receipt_trie
-                .insert_rlp(&trie_key, receipt)
-                .context("failed to insert receipt")?;
+            tx_entries.push((trie_key.clone(), tx.to_rlp()));
+            receipt_entries.push((trie_key, receipt.to_rlp()));
 
             // update account states
             #[cfg(not(target_os = "zkvm"))]
@@ -201,12 +202,14 @@ impl TxExecStrategy for EthTxExecStrategy {
             }
 
             evm.db().unwrap().commit(state);
+
+            tx_span.record(|report, cycles| report.transactions.push(cycles));
         }
 
         let mut db = evm.take_db();
 
         // process withdrawals unconditionally after any transactions
-        let mut withdrawals_trie = MptNode::default();
+        let mut withdrawal_entries = Vec::new();
         for (i, withdrawal) in take(&mut block_builder.input.withdrawals)
             .into_iter()
             .enumerate()
@@ -238,12 +241,15 @@ impl TxExecStrategy for EthTxExecStrategy {
             withdrawal_account.is_touched = true;
             // Commit changes to database
             db.commit([(withdrawal_address, withdrawal_account)].into());
-            // Add withdrawal to trie
-            withdrawals_trie
-                .insert_rlp(&i.to_rlp(), withdrawal)
-                .context("failed to insert withdrawal")?;
+            // Collect withdrawal for its trie
+            withdrawal_entries.push((i.to_rlp(), withdrawal.to_rlp()));
         }
 
+        // Build the tries in bulk, which is faster than inserting one entry at a time
+        let tx_trie: MptNode = tx_entries.into_iter().collect();
+        let receipt_trie: MptNode = receipt_entries.into_iter().collect();
+        let withdrawals_trie: MptNode = withdrawal_entries.into_iter().collect();
+
         // Update result header with computed values
         header.transactions_root = tx_trie.hash();
         header.receipts_root = receipt_trie.hash();
@@ -14,13 +14,15 @@
 
 use anyhow::Result;
 use hashbrown::HashMap;
+#[cfg(not(target_os = "zkvm"))]
+use log::debug;
 use revm::primitives::Address;
 use zeth_primitives::{
     block::Header,
     keccak::keccak,
     revm::from_revm_b256,
     trie::{MptNode, StateAccount},
-    U256,
+    B256, U256,
 };
 
 use crate::{
@@ -99,7 +101,7 @@ impl BuildFromMemDbStrategy {
                     map.insert(*address, storage_trie.clone());
                 }
 
-                storage_trie.hash()
+                hash_trie(storage_trie)
             };
 
             let state_account = StateAccount {
@@ -116,7 +118,12 @@ impl BuildFromMemDbStrategy {
             .header
             .take()
             .expect("Header was not initialized");
-        header.state_root = state_trie.hash();
+        #[cfg(not(target_os = "zkvm"))]
+        debug!(
+            "Hashing {} invalidated nodes of the state trie",
+            state_trie.invalidated().len()
+        );
+        header.state_root = hash_trie(state_trie);
 
         // Leak memory, save cycles
         guest_mem_forget(block_builder);
@@ -125,6 +132,18 @@ impl BuildFromMemDbStrategy {
     }
 }
 
+/// Hashes the trie, in parallel with the `rayon` feature. Only the nodes invalidated
+/// since the last hash are visited.
+#[cfg(feature = "rayon")]
+fn hash_trie(trie: &mut MptNode) -> B256 {
+    trie.par_hash()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn hash_trie(trie: &mut MptNode) -> B256 {
+    trie.hash()
+}
+
 impl BlockBuildStrategy for BuildFromMemDbStrategy {
     type Db = MemDb;
     type Output = Header;
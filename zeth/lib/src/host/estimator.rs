@@ -0,0 +1,258 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Prediction of the guest cycles of an [Input], without executing it.
+//!
+//! The [CycleModel] is linear in the [InputFeatures]. Its coefficients are fitted with
+//! least squares to the user cycles of executed blocks, see [CycleModel::fit].
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::input::Input;
+
+/// Properties of an input that drive the cycles of the guest.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct InputFeatures {
+    pub transactions: u64,
+    /// Accounts touched by the block.
+    pub accounts: u64,
+    /// Storage slots touched by the block.
+    pub storage_slots: u64,
+    /// Nodes of the state trie and all storage tries.
+    pub trie_nodes: u64,
+    /// Total size of all contracts.
+    pub contract_bytes: u64,
+}
+
+impl InputFeatures {
+    /// Extracts the features of the given input.
+    pub fn of(input: &Input) -> Self {
+        InputFeatures {
+            transactions: input.transactions.len() as u64,
+            accounts: input.parent_storage.len() as u64,
+            storage_slots: input
+                .parent_storage
+                .values()
+                .map(|(_, slots)| slots.len() as u64)
+                .sum(),
+            trie_nodes: (input.parent_state_trie.size()
+                + input
+                    .parent_storage
+                    .values()
+                    .map(|(trie, _)| trie.size())
+                    .sum::<usize>()) as u64,
+            contract_bytes: input.contracts.iter().map(|code| code.len() as u64).sum(),
+        }
+    }
+
+    /// Returns the features as regressors, starting with the constant.
+    fn regressors(&self) -> [f64; PARAMETERS] {
+        [
+            1.0,
+            self.transactions as f64,
+            self.accounts as f64,
+            self.storage_slots as f64,
+            self.trie_nodes as f64,
+            self.contract_bytes as f64,
+        ]
+    }
+}
+
+const PARAMETERS: usize = 6;
+
+/// Linear model of the user cycles of the guest. There are no default coefficients, they
+/// depend on the guest and are fitted to its executions.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CycleModel {
+    pub base: f64,
+    pub per_transaction: f64,
+    pub per_account: f64,
+    pub per_storage_slot: f64,
+    pub per_trie_node: f64,
+    pub per_contract_byte: f64,
+}
+
+impl CycleModel {
+    /// Estimates the user cycles of an input with the given features.
+    pub fn estimate(&self, features: &InputFeatures) -> u64 {
+        let cycles: f64 = self
+            .coefficients()
+            .iter()
+            .zip(features.regressors())
+            .map(|(coefficient, regressor)| coefficient * regressor)
+            .sum();
+        cycles.max(0.0).round() as u64
+    }
+
+    /// Fits the model to the user cycles of the given inputs.
+    ///
+    /// At least as many samples as model parameters are needed, and the features must
+    /// not be linearly dependent.
+    pub fn fit(samples: &[(InputFeatures, u64)]) -> Result<Self> {
+        if samples.len() < PARAMETERS {
+            bail!(
+                "At least {} samples are needed, got {}",
+                PARAMETERS,
+                samples.len()
+            );
+        }
+
+        // scale every regressor to at most 1 to keep the normal equations well-conditioned
+        let mut scale = [0f64; PARAMETERS];
+        for (features, _) in samples {
+            for (scale, regressor) in scale.iter_mut().zip(features.regressors()) {
+                *scale = scale.max(regressor.abs());
+            }
+        }
+        if let Some(i) = scale.iter().position(|scale| *scale == 0.0) {
+            bail!("Feature {} is zero in all samples", i);
+        }
+
+        // normal equations: (XᵀX) β = Xᵀy, as an augmented matrix
+        let mut matrix = [[0f64; PARAMETERS + 1]; PARAMETERS];
+        for (features, cycles) in samples {
+            let mut x = features.regressors();
+            for (x, scale) in x.iter_mut().zip(scale) {
+                *x /= scale;
+            }
+            for (row, xi) in matrix.iter_mut().zip(x) {
+                for (cell, xj) in row.iter_mut().zip(x) {
+                    *cell += xi * xj;
+                }
+                row[PARAMETERS] += xi * *cycles as f64;
+            }
+        }
+
+        let mut coefficients = solve(matrix)?;
+        for (coefficient, scale) in coefficients.iter_mut().zip(scale) {
+            *coefficient /= scale;
+        }
+        Ok(Self::from_coefficients(coefficients))
+    }
+
+    fn coefficients(&self) -> [f64; PARAMETERS] {
+        [
+            self.base,
+            self.per_transaction,
+            self.per_account,
+            self.per_storage_slot,
+            self.per_trie_node,
+            self.per_contract_byte,
+        ]
+    }
+
+    fn from_coefficients(coefficients: [f64; PARAMETERS]) -> Self {
+        let [base, per_transaction, per_account, per_storage_slot, per_trie_node, per_contract_byte] =
+            coefficients;
+        CycleModel {
+            base,
+            per_transaction,
+            per_account,
+            per_storage_slot,
+            per_trie_node,
+            per_contract_byte,
+        }
+    }
+}
+
+/// Returns the number of segments needed for the given user cycles.
+///
+/// Segments also spend cycles outside of the guest code, so this is a lower bound.
+pub fn estimate_segments(user_cycles: u64, segment_limit_po2: u32) -> u64 {
+    let segment_cycles = 1u64 << segment_limit_po2;
+    ((user_cycles + segment_cycles - 1) / segment_cycles).max(1)
+}
+
+/// Solves the linear system given as augmented matrix, with Gaussian elimination.
+fn solve(mut matrix: [[f64; PARAMETERS + 1]; PARAMETERS]) -> Result<[f64; PARAMETERS]> {
+    for col in 0..PARAMETERS {
+        let pivot = (col..PARAMETERS)
+            .max_by(|&a, &b| matrix[a][col].abs().total_cmp(&matrix[b][col].abs()))
+            .unwrap();
+        if matrix[pivot][col].abs() < 1e-12 {
+            bail!("The features of the samples are linearly dependent");
+        }
+        matrix.swap(col, pivot);
+        let pivot_row = matrix[col];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            if i != col {
+                let factor = row[col] / pivot_row[col];
+                for (cell, pivot_cell) in row.iter_mut().zip(pivot_row).skip(col) {
+                    *cell -= factor * pivot_cell;
+                }
+            }
+        }
+    }
+
+    let mut solution = [0f64; PARAMETERS];
+    for (i, (value, row)) in solution.iter_mut().zip(matrix).enumerate() {
+        *value = row[PARAMETERS] / row[i];
+    }
+    Ok(solution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(i: u64) -> InputFeatures {
+        InputFeatures {
+            transactions: 10 + i * 7 % 13,
+            accounts: 40 + i * i % 17,
+            storage_slots: 100 + i * 11 % 29,
+            trie_nodes: 1_000 + i * i * 3 % 101,
+            contract_bytes: 50_000 + i * 997 % 4_099,
+        }
+    }
+
+    #[test]
+    fn fit_recovers_model() {
+        let model = CycleModel {
+            base: 20_000_000.0,
+            per_transaction: 8_000_000.0,
+            per_account: 500_000.0,
+            per_storage_slot: 200_000.0,
+            per_trie_node: 60_000.0,
+            per_contract_byte: 50.0,
+        };
+        let samples: Vec<_> = (0..20)
+            .map(|i| {
+                let features = features(i);
+                let cycles = model.estimate(&features);
+                (features, cycles)
+            })
+            .collect();
+
+        let fitted = CycleModel::fit(&samples).unwrap();
+        for (features, cycles) in &samples {
+            let estimate = fitted.estimate(features);
+            assert!(estimate.abs_diff(*cycles) <= cycles / 1_000_000);
+        }
+    }
+
+    #[test]
+    fn fit_needs_enough_samples() {
+        let samples: Vec<_> = (0..3).map(|i| (features(i), 1_000_000)).collect();
+        assert!(CycleModel::fit(&samples).is_err());
+    }
+
+    #[test]
+    fn segments() {
+        assert_eq!(estimate_segments(0, 20), 1);
+        assert_eq!(estimate_segments(1 << 20, 20), 1);
+        assert_eq!(estimate_segments((1 << 20) + 1, 20), 2);
+    }
+}
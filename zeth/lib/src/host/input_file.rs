@@ -0,0 +1,232 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Files containing a prepared [Input], so that a block can be proven without RPC access.
+//!
+//! A file consists of the magic bytes `ZETHINPT`, followed by a gzip-compressed bincode
+//! stream of the [InputHeader] and the [Input].
+//!
+//! Files may come from untrusted uploads, so readers are given the largest size the
+//! stream may decompress to.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use zeth_primitives::BlockHash;
+
+use crate::input::Input;
+
+/// Magic bytes at the start of every input file.
+pub const INPUT_FILE_MAGIC: [u8; 8] = *b"ZETHINPT";
+/// Version of the input file format. Files of other versions are rejected.
+pub const INPUT_FILE_VERSION: u32 = 2;
+
+/// Describes the block an input file was prepared for.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct InputHeader {
+    /// Version of the file format.
+    pub version: u32,
+    /// Name of the network of the block.
+    pub network: String,
+    /// Number of the block.
+    pub block_no: u64,
+    /// Hex-encoded image ID of the guest the input was prepared for.
+    pub image_id: String,
+    /// Hash of the block, as the guest must derive it from the input.
+    pub block_hash: BlockHash,
+}
+
+/// A prepared [Input], together with its header.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InputFile {
+    pub header: InputHeader,
+    pub input: Input,
+}
+
+impl InputFile {
+    /// Creates a file of the current version.
+    pub fn new(
+        network: String,
+        block_no: u64,
+        image_id: String,
+        block_hash: BlockHash,
+        input: Input,
+    ) -> Self {
+        InputFile {
+            header: InputHeader {
+                version: INPUT_FILE_VERSION,
+                network,
+                block_no,
+                image_id,
+                block_hash,
+            },
+            input,
+        }
+    }
+
+    /// Writes the file to the given writer.
+    pub fn write_to(&self, mut writer: impl Write) -> Result<()> {
+        writer.write_all(&INPUT_FILE_MAGIC)?;
+        let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+        bincode::serialize_into(&mut encoder, &self.header)?;
+        bincode::serialize_into(&mut encoder, &self.input)?;
+        encoder.finish()?.flush()?;
+
+        Ok(())
+    }
+
+    /// Reads a file from the given reader, failing if it is of another version or
+    /// decompresses to more than `max_size` bytes.
+    pub fn read_from(mut reader: impl Read, max_size: u64) -> Result<Self> {
+        let mut decoder = header_decoder(&mut reader)?.take(max_size);
+        let header = read_header(&mut decoder, max_size)?;
+        let input = options(max_size)
+            .deserialize_from(&mut decoder)
+            .context("Invalid input")?;
+
+        Ok(InputFile { header, input })
+    }
+
+    /// Writes the file to the given path.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("Could not create {}", path.display()))?;
+        self.write_to(BufWriter::new(file))
+    }
+
+    /// Reads the file at the given path, of any size.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+        Self::read_from(BufReader::new(file), u64::MAX)
+            .with_context(|| format!("Invalid input file {}", path.display()))
+    }
+}
+
+/// Reads only the header of an input file.
+pub fn read_header_from(mut reader: impl Read) -> Result<InputHeader> {
+    read_header(&mut header_decoder(&mut reader)?, u64::MAX)
+}
+
+/// Options of [bincode::deserialize_from], allocating at most `limit` bytes.
+fn options(limit: u64) -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit)
+}
+
+fn header_decoder<R: Read>(mut reader: R) -> Result<flate2::read::GzDecoder<R>> {
+    let mut magic = [0u8; INPUT_FILE_MAGIC.len()];
+    reader.read_exact(&mut magic).context("Not an input file")?;
+    if magic != INPUT_FILE_MAGIC {
+        bail!("Not an input file");
+    }
+    Ok(flate2::read::GzDecoder::new(reader))
+}
+
+fn read_header(decoder: &mut impl Read, max_size: u64) -> Result<InputHeader> {
+    // The version comes first, so that it can be checked before the rest is decoded
+    let version: u32 = options(max_size)
+        .deserialize_from(&mut *decoder)
+        .context("Invalid header")?;
+    if version != INPUT_FILE_VERSION {
+        bail!(
+            "Unsupported input file version {} (expected {})",
+            version,
+            INPUT_FILE_VERSION
+        );
+    }
+    let network = options(max_size)
+        .deserialize_from(&mut *decoder)
+        .context("Invalid header")?;
+    let block_no = options(max_size)
+        .deserialize_from(&mut *decoder)
+        .context("Invalid header")?;
+    let image_id = options(max_size)
+        .deserialize_from(&mut *decoder)
+        .context("Invalid header")?;
+    let block_hash = options(max_size)
+        .deserialize_from(&mut *decoder)
+        .context("Invalid header")?;
+
+    Ok(InputHeader {
+        version,
+        network,
+        block_no,
+        image_id,
+        block_hash,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input_file() -> InputFile {
+        InputFile::new(
+            "Ethereum".to_string(),
+            17034870,
+            "00".repeat(32),
+            BlockHash::repeat_byte(0xab),
+            Input::default(),
+        )
+    }
+
+    #[test]
+    fn roundtrip() {
+        let file = input_file();
+        let mut buf = Vec::new();
+        file.write_to(&mut buf).unwrap();
+
+        let decoded = InputFile::read_from(buf.as_slice(), u64::MAX).unwrap();
+        assert_eq!(decoded.header, file.header);
+        assert_eq!(read_header_from(buf.as_slice()).unwrap(), file.header);
+    }
+
+    #[test]
+    fn limits_the_decompressed_size() {
+        let mut file = input_file();
+        file.input.extra_data = vec![0; 1 << 20].into();
+        let mut buf = Vec::new();
+        file.write_to(&mut buf).unwrap();
+
+        assert!(buf.len() < 1 << 16);
+        assert!(InputFile::read_from(buf.as_slice(), 1 << 20).is_err());
+        assert!(InputFile::read_from(buf.as_slice(), 4 << 20).is_ok());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut file = input_file();
+        file.header.version = INPUT_FILE_VERSION + 1;
+        let mut buf = Vec::new();
+        file.write_to(&mut buf).unwrap();
+
+        assert!(InputFile::read_from(buf.as_slice(), u64::MAX).is_err());
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(InputFile::read_from(&b"not an input file"[..], u64::MAX).is_err());
+    }
+}
@@ -12,10 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{
-    collections::HashSet,
-    iter::{once, zip},
-};
+use std::{collections::HashSet, iter::once};
 
 use anyhow::{Context, Result};
 use ethers_core::types::{Bytes, EIP1186ProofResponse, H256};
@@ -26,12 +23,12 @@ use revm::{
     Database,
 };
 use zeth_primitives::{
-    block::Header,
-    ethers::{from_ethers_h160, from_ethers_h256, from_ethers_u256},
+    block::{Block, Header},
+    ethers::from_ethers_h256,
     keccak::keccak,
     revm::to_revm_b256,
     transaction::Transaction,
-    trie::{MptNode, MptNodeData, MptNodeReference, EMPTY_ROOT},
+    trie::{MptDiff, MptNode, MptNodeData, MptNodeReference, EMPTY_ROOT},
     withdrawal::Withdrawal,
 };
 
@@ -48,9 +45,12 @@ use crate::{
     preparation::EthHeaderPrepStrategy,
 };
 
+pub mod estimator;
+pub mod input_file;
 pub mod mpt;
 pub mod provider;
 pub mod provider_db;
+pub mod witness;
 
 #[derive(Clone)]
 pub struct Init {
@@ -64,6 +64,7 @@ pub struct Init {
     pub ancestor_headers: Vec<Header>,
 }
 
+#[tracing::instrument(skip(cache_path, rpc_url))]
 pub fn get_initial_data(
     cache_path: Option<String>,
     rpc_url: Option<String>,
@@ -92,30 +93,42 @@ pub fn get_initial_data(
     );
     info!("Transaction count: {:?}", fini_block.transactions.len());
 
+    // Check that the RPC returned a body matching the header before executing it
+    let block = Block {
+        header: fini_block.clone().try_into()?,
+        transactions: fini_block
+            .transactions
+            .into_iter()
+            .map(|tx| tx.try_into())
+            .collect::<Result<_>>()?,
+        ommers: vec![],
+        withdrawals: fini_block
+            .withdrawals
+            .map(|withdrawals| {
+                withdrawals
+                    .into_iter()
+                    .map(|w| w.try_into())
+                    .collect::<Result<_>>()
+            })
+            .transpose()?,
+    };
+    block
+        .validate_body()
+        .with_context(|| format!("inconsistent block {} returned by the RPC", block_no))?;
+
     // Create the provider DB
     let provider_db =
         crate::host::provider_db::ProviderDb::new(provider, init_block.number.unwrap().as_u64());
 
     // Create input
     let input = Input {
-        beneficiary: fini_block.author.map(from_ethers_h160).unwrap_or_default(),
-        gas_limit: from_ethers_u256(fini_block.gas_limit),
-        timestamp: from_ethers_u256(fini_block.timestamp),
-        extra_data: fini_block.extra_data.0.clone().into(),
-        mix_hash: from_ethers_h256(fini_block.mix_hash.unwrap()),
-        transactions: fini_block
-            .transactions
-            .clone()
-            .into_iter()
-            .map(|tx| tx.try_into().unwrap())
-            .collect(),
-        withdrawals: fini_block
-            .withdrawals
-            .clone()
-            .unwrap_or_default()
-            .into_iter()
-            .map(|w| w.try_into().unwrap())
-            .collect(),
+        beneficiary: block.header.beneficiary,
+        gas_limit: block.header.gas_limit,
+        timestamp: block.header.timestamp,
+        extra_data: block.header.extra_data.clone(),
+        mix_hash: block.header.mix_hash,
+        transactions: block.transactions.clone(),
+        withdrawals: block.withdrawals.clone().unwrap_or_default(),
         parent_header: init_block.clone().try_into()?,
         ..Default::default()
     };
@@ -143,27 +156,13 @@ pub fn get_initial_data(
 
     info!("Provider-backed execution is Done!");
 
-    let transactions = fini_block
-        .transactions
-        .clone()
-        .into_iter()
-        .map(|tx| tx.try_into().unwrap())
-        .collect();
-    let withdrawals = fini_block
-        .withdrawals
-        .clone()
-        .unwrap_or_default()
-        .into_iter()
-        .map(|w| w.try_into().unwrap())
-        .collect();
-
     Ok(Init {
         db: provider_db.get_initial_db().clone(),
         init_block: init_block.try_into()?,
         init_proofs,
-        fini_block: fini_block.try_into()?,
-        fini_transactions: transactions,
-        fini_withdrawals: withdrawals,
+        fini_block: block.header,
+        fini_transactions: block.transactions,
+        fini_withdrawals: block.withdrawals.unwrap_or_default(),
         fini_proofs,
         ancestor_headers,
     })
@@ -194,11 +193,25 @@ pub enum VerifyError {
         address: Address,
         rpc_value: B256,
         our_value: B256,
-        first_delta: Option<String>,
+        /// Slots whose values differ between the RPC and our storage trie.
+        slots: Vec<SlotDelta>,
+        /// Nibbles of differing subtries that could not be compared.
+        unresolved: Vec<Vec<u8>>,
         indices: usize,
     },
 }
 
+/// A storage slot with different values in the RPC's and our storage trie.
+#[derive(Debug)]
+pub struct SlotDelta {
+    /// Index of the slot, if it is one of the accessed slots.
+    pub index: Option<U256>,
+    /// Hash of the index, the key of the slot in the storage trie.
+    pub key: B256,
+    pub rpc_value: U256,
+    pub our_value: U256,
+}
+
 pub fn verify_state(
     mut fini_db: MemDb,
     fini_proofs: HashMap<B160, EIP1186ProofResponse>,
@@ -269,20 +282,39 @@ pub fn verify_state(
             let our_value = to_revm_b256(storage_root_node.hash());
             let rpc_value = account_proof.storage_hash.into();
             if rpc_value != our_value {
-                let expected = storage
-                    .get(&address)
-                    .unwrap()
-                    .0
-                    .debug_rlp::<zeth_primitives::U256>();
-                let found_pp = storage_root_node.debug_rlp::<zeth_primitives::U256>();
-                let first_delta = zip(expected, found_pp)
-                    .find(|(e, f)| e != f)
-                    .map(|(e, f)| format!("Storage trie delta!\nEXPECTED:\t{}FOUND:\t{}", e, f));
+                let expected = &storage.get(&address).unwrap().0;
+                let index_by_key: HashMap<_, _> = indices
+                    .iter()
+                    .map(|index| (B256::from(keccak(index.to_be_bytes::<32>())), *index))
+                    .collect();
+
+                let mut slots = Vec::new();
+                let mut unresolved = Vec::new();
+                for diff in expected.diff_rlp::<U256>(&storage_root_node)? {
+                    let (key, rpc_value, our_value) = match diff {
+                        MptDiff::Added { key, value } => (key, U256::ZERO, value),
+                        MptDiff::Removed { key, value } => (key, value, U256::ZERO),
+                        MptDiff::Changed { key, old, new } => (key, old, new),
+                        MptDiff::Unresolved { prefix } => {
+                            unresolved.push(prefix);
+                            continue;
+                        }
+                    };
+                    let key = B256::from_slice(&key);
+                    slots.push(SlotDelta {
+                        index: index_by_key.get(&key).copied(),
+                        key,
+                        rpc_value,
+                        our_value,
+                    });
+                }
+
                 address_errors.push(VerifyError::StorageRootMismatch {
                     address,
                     rpc_value,
                     our_value,
-                    first_delta,
+                    slots,
+                    unresolved,
                     indices: indices.len(),
                 });
             }
@@ -12,12 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+//! Assembly of partial tries from the Merkle proofs of a provider.
+//!
+//! Proofs themselves are built by [MptNode::prove]. These helpers stay on the host
+//! instead of next to it in `zeth_primitives`: they only rebuild the tries of the guest
+//! input from the proof nodes returned by RPC during the preflight, and are never
+//! needed by the guest, which receives the assembled tries.
+
 use hashbrown::HashMap;
 use zeth_primitives::trie::{to_encoded_path, MptNode, MptNodeData, MptNodeReference};
 
 /// Creates a new MPT trie where all the digests contained in `node_store` are resolved.
 pub fn resolve_digests(trie: &MptNode, node_store: &HashMap<MptNodeReference, MptNode>) -> MptNode {
-    let result: MptNode = match trie.as_data() {
+    let mut result = resolve_digests_internal(trie, node_store);
+    // checking the root suffices, as every resolved node must match its digest for it
+    #[cfg(feature = "rayon")]
+    let root = result.par_hash();
+    #[cfg(not(feature = "rayon"))]
+    let root = result.hash();
+    assert_eq!(trie.hash(), root);
+    result
+}
+
+fn resolve_digests_internal(
+    trie: &MptNode,
+    node_store: &HashMap<MptNodeReference, MptNode>,
+) -> MptNode {
+    match trie.as_data() {
         MptNodeData::Null | MptNodeData::Leaf(_, _) => trie.clone(),
         MptNodeData::Branch(children) => {
             let children: Vec<_> = children
@@ -25,26 +46,24 @@ pub fn resolve_digests(trie: &MptNode, node_store: &HashMap<MptNodeReference, Mp
                 .map(|child| {
                     child
                         .as_ref()
-                        .map(|node| Box::new(resolve_digests(node, node_store)))
+                        .map(|node| Box::new(resolve_digests_internal(node, node_store)))
                 })
                 .collect();
             MptNodeData::Branch(children.try_into().unwrap()).into()
         }
         MptNodeData::Extension(prefix, target) => MptNodeData::Extension(
             prefix.clone(),
-            Box::new(resolve_digests(target, node_store)),
+            Box::new(resolve_digests_internal(target, node_store)),
         )
         .into(),
         MptNodeData::Digest(digest) => {
             if let Some(node) = node_store.get(&MptNodeReference::Digest(*digest)) {
-                resolve_digests(node, node_store)
+                resolve_digests_internal(node, node_store)
             } else {
                 trie.clone()
             }
         }
-    };
-    assert_eq!(trie.hash(), result.hash());
-    result
+    }
 }
 
 /// Returns all orphaned digests in the trie.
@@ -14,6 +14,7 @@
 
 use anyhow::Result;
 use ethers_core::types::{Block, Bytes, EIP1186ProofResponse, Transaction, H256, U256};
+use metrics::increment_counter;
 
 use super::{
     file_provider::FileProvider, rpc_provider::RpcProvider, AccountQuery, BlockQuery, MutProvider,
@@ -37,6 +38,12 @@ impl CachedRpcProvider {
     }
 }
 
+/// Records whether a query was answered from the cache.
+fn record_lookup(method: &'static str, hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    increment_counter!("zeth_cache_lookups_total", "method" => method, "result" => result);
+}
+
 impl Provider for CachedRpcProvider {
     fn save(&self) -> Result<()> {
         self.cache.save()
@@ -44,6 +51,7 @@ impl Provider for CachedRpcProvider {
 
     fn get_full_block(&mut self, query: &BlockQuery) -> Result<Block<Transaction>> {
         let cache_out = self.cache.get_full_block(query);
+        record_lookup("get_full_block", cache_out.is_ok());
         if cache_out.is_ok() {
             return cache_out;
         }
@@ -56,6 +64,7 @@ impl Provider for CachedRpcProvider {
 
     fn get_partial_block(&mut self, query: &BlockQuery) -> Result<Block<H256>> {
         let cache_out = self.cache.get_partial_block(query);
+        record_lookup("get_partial_block", cache_out.is_ok());
         if cache_out.is_ok() {
             return cache_out;
         }
@@ -68,6 +77,7 @@ impl Provider for CachedRpcProvider {
 
     fn get_proof(&mut self, query: &ProofQuery) -> Result<EIP1186ProofResponse> {
         let cache_out = self.cache.get_proof(query);
+        record_lookup("get_proof", cache_out.is_ok());
         if cache_out.is_ok() {
             return cache_out;
         }
@@ -80,6 +90,7 @@ impl Provider for CachedRpcProvider {
 
     fn get_transaction_count(&mut self, query: &AccountQuery) -> Result<U256> {
         let cache_out = self.cache.get_transaction_count(query);
+        record_lookup("get_transaction_count", cache_out.is_ok());
         if cache_out.is_ok() {
             return cache_out;
         }
@@ -92,6 +103,7 @@ impl Provider for CachedRpcProvider {
 
     fn get_balance(&mut self, query: &AccountQuery) -> Result<U256> {
         let cache_out = self.cache.get_balance(query);
+        record_lookup("get_balance", cache_out.is_ok());
         if cache_out.is_ok() {
             return cache_out;
         }
@@ -104,6 +116,7 @@ impl Provider for CachedRpcProvider {
 
     fn get_code(&mut self, query: &AccountQuery) -> Result<Bytes> {
         let cache_out = self.cache.get_code(query);
+        record_lookup("get_code", cache_out.is_ok());
         if cache_out.is_ok() {
             return cache_out;
         }
@@ -116,6 +129,7 @@ impl Provider for CachedRpcProvider {
 
     fn get_storage(&mut self, query: &StorageQuery) -> Result<H256> {
         let cache_out = self.cache.get_storage(query);
+        record_lookup("get_storage", cache_out.is_ok());
         if cache_out.is_ok() {
             return cache_out;
         }
@@ -14,8 +14,9 @@
 
 use std::{
     collections::HashMap,
-    fs::File,
+    fs::{self, File},
     io::{Read, Write},
+    path::Path,
 };
 
 use anyhow::{anyhow, Result};
@@ -48,7 +49,31 @@ pub struct FileProvider {
     storage: HashMap<StorageQuery, H256>,
 }
 
+/// Number of cached responses of a [FileProvider], by query kind.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FileProviderStats {
+    pub full_blocks: usize,
+    pub partial_blocks: usize,
+    pub proofs: usize,
+    pub transaction_counts: usize,
+    pub balances: usize,
+    pub codes: usize,
+    pub storage_slots: usize,
+}
+
 impl FileProvider {
+    pub fn stats(&self) -> FileProviderStats {
+        FileProviderStats {
+            full_blocks: self.full_blocks.len(),
+            partial_blocks: self.partial_blocks.len(),
+            proofs: self.proofs.len(),
+            transaction_counts: self.transaction_count.len(),
+            balances: self.balance.len(),
+            codes: self.code.len(),
+            storage_slots: self.storage.len(),
+        }
+    }
+
     pub fn empty(file_path: String) -> Self {
         FileProvider {
             file_path,
@@ -77,6 +102,9 @@ impl FileProvider {
 
     pub fn save_to_file(&self, file_path: &String) -> Result<()> {
         if self.dirty {
+            if let Some(dir) = Path::new(file_path).parent() {
+                fs::create_dir_all(dir)?;
+            }
             let mut encoder = flate2::write::GzEncoder::new(
                 File::create(file_path)?,
                 flate2::Compression::best(),
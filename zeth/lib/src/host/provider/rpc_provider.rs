@@ -12,10 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{fmt::Debug, time::Instant};
+
 use anyhow::{anyhow, Result};
 use ethers_core::types::{Block, Bytes, EIP1186ProofResponse, Transaction, H256, U256};
 use ethers_providers::{Http, Middleware};
 use log::info;
+use metrics::{histogram, increment_counter};
+use tracing::info_span;
 
 use super::{AccountQuery, BlockQuery, ProofQuery, Provider, StorageQuery};
 
@@ -36,6 +40,25 @@ impl RpcProvider {
     }
 }
 
+/// Records the outcome and latency of an RPC call, in a span of its own.
+fn observe<T>(
+    method: &'static str,
+    query: &impl Debug,
+    call: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let _span = info_span!("provider_query", method, query = ?query).entered();
+    let start = Instant::now();
+    let out = call();
+    let outcome = if out.is_ok() { "ok" } else { "error" };
+    increment_counter!("zeth_rpc_requests_total", "method" => method, "outcome" => outcome);
+    histogram!(
+        "zeth_rpc_request_duration_seconds",
+        start.elapsed().as_secs_f64(),
+        "method" => method
+    );
+    out
+}
+
 impl Provider for RpcProvider {
     fn save(&self) -> Result<()> {
         Ok(())
@@ -44,9 +67,11 @@ impl Provider for RpcProvider {
     fn get_full_block(&mut self, query: &BlockQuery) -> Result<Block<Transaction>> {
         info!("Querying RPC for full block: {:?}", query);
 
-        let response = self
-            .tokio_handle
-            .block_on(async { self.http_client.get_block_with_txs(query.block_no).await })?;
+        let response = observe("eth_getBlockByNumber", query, || {
+            Ok(self
+                .tokio_handle
+                .block_on(async { self.http_client.get_block_with_txs(query.block_no).await })?)
+        })?;
 
         match response {
             Some(out) => Ok(out),
@@ -57,9 +82,11 @@ impl Provider for RpcProvider {
     fn get_partial_block(&mut self, query: &BlockQuery) -> Result<Block<H256>> {
         info!("Querying RPC for partial block: {:?}", query);
 
-        let response = self
-            .tokio_handle
-            .block_on(async { self.http_client.get_block(query.block_no).await })?;
+        let response = observe("eth_getBlockByNumber", query, || {
+            Ok(self
+                .tokio_handle
+                .block_on(async { self.http_client.get_block(query.block_no).await })?)
+        })?;
 
         match response {
             Some(out) => Ok(out),
@@ -70,14 +97,16 @@ impl Provider for RpcProvider {
     fn get_proof(&mut self, query: &ProofQuery) -> Result<EIP1186ProofResponse> {
         info!("Querying RPC for inclusion proof: {:?}", query);
 
-        let out = self.tokio_handle.block_on(async {
-            self.http_client
-                .get_proof(
-                    query.address,
-                    query.indices.iter().cloned().collect(),
-                    Some(query.block_no.into()),
-                )
-                .await
+        let out = observe("eth_getProof", query, || {
+            Ok(self.tokio_handle.block_on(async {
+                self.http_client
+                    .get_proof(
+                        query.address,
+                        query.indices.iter().cloned().collect(),
+                        Some(query.block_no.into()),
+                    )
+                    .await
+            })?)
         })?;
 
         Ok(out)
@@ -86,10 +115,12 @@ impl Provider for RpcProvider {
     fn get_transaction_count(&mut self, query: &AccountQuery) -> Result<U256> {
         info!("Querying RPC for transaction count: {:?}", query);
 
-        let out = self.tokio_handle.block_on(async {
-            self.http_client
-                .get_transaction_count(query.address, Some(query.block_no.into()))
-                .await
+        let out = observe("eth_getTransactionCount", query, || {
+            Ok(self.tokio_handle.block_on(async {
+                self.http_client
+                    .get_transaction_count(query.address, Some(query.block_no.into()))
+                    .await
+            })?)
         })?;
 
         Ok(out)
@@ -98,10 +129,12 @@ impl Provider for RpcProvider {
     fn get_balance(&mut self, query: &AccountQuery) -> Result<U256> {
         info!("Querying RPC for balance: {:?}", query);
 
-        let out = self.tokio_handle.block_on(async {
-            self.http_client
-                .get_balance(query.address, Some(query.block_no.into()))
-                .await
+        let out = observe("eth_getBalance", query, || {
+            Ok(self.tokio_handle.block_on(async {
+                self.http_client
+                    .get_balance(query.address, Some(query.block_no.into()))
+                    .await
+            })?)
         })?;
 
         Ok(out)
@@ -110,10 +143,12 @@ impl Provider for RpcProvider {
     fn get_code(&mut self, query: &AccountQuery) -> Result<Bytes> {
         info!("Querying RPC for code: {:?}", query);
 
-        let out = self.tokio_handle.block_on(async {
-            self.http_client
-                .get_code(query.address, Some(query.block_no.into()))
-                .await
+        let out = observe("eth_getCode", query, || {
+            Ok(self.tokio_handle.block_on(async {
+                self.http_client
+                    .get_code(query.address, Some(query.block_no.into()))
+                    .await
+            })?)
         })?;
 
         Ok(out)
@@ -122,10 +157,12 @@ impl Provider for RpcProvider {
     fn get_storage(&mut self, query: &StorageQuery) -> Result<H256> {
         info!("Querying RPC for storage: {:?}", query);
 
-        let out = self.tokio_handle.block_on(async {
-            self.http_client
-                .get_storage_at(query.address, query.index, Some(query.block_no.into()))
-                .await
+        let out = observe("eth_getStorageAt", query, || {
+            Ok(self.tokio_handle.block_on(async {
+                self.http_client
+                    .get_storage_at(query.address, query.index, Some(query.block_no.into()))
+                    .await
+            })?)
         })?;
 
         Ok(out)
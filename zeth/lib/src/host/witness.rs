@@ -0,0 +1,450 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Minimization of the witness contained in an [Input].
+//!
+//! The guest reads every byte of its input, so everything it does not need costs cycles.
+//! The minimization removes:
+//! - trie nodes that are neither on the path to an account or slot the block touches, nor
+//!   next to such a path (deleting a key may merge the sibling into its parent); they are
+//!   replaced by their digest,
+//! - duplicate storage slots and contracts, as well as contracts of untouched accounts,
+//! - ancestor headers older than the oldest block hash read by the block.
+//!
+//! Shared trie nodes, i.e. identical subtries occurring more than once in a trie, are not
+//! removed by this pass, since the guest needs every occurrence on its paths. Instead,
+//! the input stores each trie as a [NodeTable], which deduplicates them by writing every
+//! node once. [InputSize::duplicate_trie_nodes] counts the occurrences this saves.
+//!
+//! The minimized input is executed on the host and rejected unless it yields the same
+//! block as the original.
+
+use std::collections::HashSet;
+
+use anyhow::{ensure, Result};
+use log::info;
+use revm::{
+    primitives::{Account, AccountInfo, Bytecode, B160, B256, U256},
+    Database, DatabaseCommit,
+};
+use serde::{Deserialize, Serialize};
+use zeth_primitives::{
+    block::Header,
+    keccak::keccak,
+    trie::{
+        table::{NodeTable, TrieEncoding},
+        to_nibs, MptNode, MptNodeData, MptNodeReference, StateAccount,
+    },
+};
+
+use crate::{
+    block_builder::BlockBuilder,
+    consts::ChainSpec,
+    execution::EthTxExecStrategy,
+    finalization::BuildFromMemDbStrategy,
+    initialization::MemDbInitStrategy,
+    input::Input,
+    mem_db::{DbError, MemDb},
+    preparation::EthHeaderPrepStrategy,
+};
+
+/// Sizes of the parts of an [Input], in bytes of its bincode encoding.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct InputSize {
+    pub total: u64,
+    pub state_trie: u64,
+    pub storage_tries: u64,
+    pub contracts: u64,
+    pub ancestor_headers: u64,
+    /// Number of nodes in the state trie and all storage tries.
+    pub trie_nodes: usize,
+    /// Number of those nodes that are identical to another node of the same trie, and
+    /// therefore not stored again.
+    #[serde(default)]
+    pub duplicate_trie_nodes: usize,
+}
+
+impl InputSize {
+    /// Measures the given input.
+    pub fn of(input: &Input) -> Result<Self> {
+        Ok(InputSize {
+            total: bincode::serialized_size(input)?,
+            state_trie: table_size(&input.parent_state_trie)?,
+            storage_tries: input
+                .parent_storage
+                .values()
+                .map(|(trie, _)| table_size(trie))
+                .sum::<Result<u64>>()?,
+            contracts: bincode::serialized_size(&input.contracts)?,
+            ancestor_headers: bincode::serialized_size(&input.ancestor_headers)?,
+            trie_nodes: input.parent_state_trie.size()
+                + input
+                    .parent_storage
+                    .values()
+                    .map(|(trie, _)| trie.size())
+                    .sum::<usize>(),
+            duplicate_trie_nodes: duplicate_nodes(&input.parent_state_trie)
+                + input
+                    .parent_storage
+                    .values()
+                    .map(|(trie, _)| duplicate_nodes(trie))
+                    .sum::<usize>(),
+        })
+    }
+}
+
+/// Returns the number of nodes of the trie that are referenced by their digest and
+/// identical to a node visited before. Embedded nodes are part of their parent.
+fn duplicate_nodes(trie: &MptNode) -> usize {
+    fn visit(node: &MptNode, seen: &mut HashSet<MptNodeReference>) -> usize {
+        let duplicates = match node.as_data() {
+            MptNodeData::Null | MptNodeData::Leaf(_, _) | MptNodeData::Digest(_) => 0,
+            MptNodeData::Branch(children) => children
+                .iter()
+                .flatten()
+                .map(|child| visit(child, seen))
+                .sum(),
+            MptNodeData::Extension(_, child) => visit(child, seen),
+        };
+        let reference = node.reference();
+        let stored = !node.is_digest() && matches!(reference, MptNodeReference::Digest(_));
+        if stored && !seen.insert(reference) {
+            duplicates + 1
+        } else {
+            duplicates
+        }
+    }
+    visit(trie, &mut HashSet::new())
+}
+
+/// Returns the size of the trie as it is serialized in an [Input].
+fn table_size(trie: &MptNode) -> Result<u64> {
+    Ok(bincode::serialized_size(&TrieEncoding::Table(
+        NodeTable::encode(trie),
+    ))?)
+}
+
+/// Sizes of an input before and after its minimization.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MinimizeReport {
+    pub before: InputSize,
+    pub after: InputSize,
+}
+
+/// Minimizes the witness of the input and checks that it still builds the same block.
+pub fn minimize(chain_spec: &ChainSpec, input: Input) -> Result<(Input, MinimizeReport)> {
+    let before = InputSize::of(&input)?;
+    let (expected, oldest_block) = execute(chain_spec, input.clone())?;
+
+    let mut minimized = input;
+    // storage tries, only keeping the paths to the accessed slots
+    for (storage_trie, slots) in minimized.parent_storage.values_mut() {
+        slots.sort();
+        slots.dedup();
+        let paths: Vec<_> = slots
+            .iter()
+            .map(|slot| to_nibs(&keccak(slot.to_be_bytes::<32>())))
+            .collect();
+        *storage_trie = prune_checked(storage_trie, &paths)?;
+    }
+
+    // state trie, only keeping the paths to the accessed accounts
+    let paths: Vec<_> = minimized
+        .parent_storage
+        .keys()
+        .map(|address| to_nibs(&keccak(address)))
+        .collect();
+    minimized.parent_state_trie = prune_checked(&minimized.parent_state_trie, &paths)?;
+
+    // contracts of the accessed accounts
+    let mut code_hashes = HashSet::new();
+    for address in minimized.parent_storage.keys() {
+        let account: StateAccount = minimized
+            .parent_state_trie
+            .get_rlp(&keccak(address))?
+            .unwrap_or_default();
+        code_hashes.insert(account.code_hash);
+    }
+    minimized
+        .contracts
+        .retain(|code| code_hashes.remove(&zeth_primitives::B256::from(keccak(code))));
+
+    // ancestor headers up to the oldest one read
+    match oldest_block {
+        Some(oldest_block) => minimized
+            .ancestor_headers
+            .retain(|header| header.number >= oldest_block),
+        None => minimized.ancestor_headers.clear(),
+    }
+
+    let (header, _) = execute(chain_spec, minimized.clone())?;
+    ensure!(
+        header.hash() == expected.hash(),
+        "Minimized input builds block {} instead of {}",
+        header.hash(),
+        expected.hash()
+    );
+
+    let after = InputSize::of(&minimized)?;
+    info!(
+        "Minimized input from {} to {} bytes ({} to {} trie nodes, {} of them shared; {} to {} \
+         ancestor headers)",
+        before.total,
+        after.total,
+        before.trie_nodes,
+        after.trie_nodes,
+        after.duplicate_trie_nodes,
+        before.ancestor_headers,
+        after.ancestor_headers
+    );
+
+    Ok((minimized, MinimizeReport { before, after }))
+}
+
+/// Builds the block of the input like the guest does. Returns the header and the number
+/// of the oldest block whose hash was read.
+fn execute(chain_spec: &ChainSpec, input: Input) -> Result<(Header, Option<u64>)> {
+    let block_builder =
+        BlockBuilder::<MemDb>::new(chain_spec, input).initialize_database::<MemDbInitStrategy>()?;
+
+    let BlockBuilder {
+        chain_spec,
+        input,
+        db,
+        header,
+    } = block_builder;
+    let block_builder = BlockBuilder {
+        chain_spec,
+        input,
+        db: db.map(BlockHashRecorder::new),
+        header,
+    }
+    .prepare_header::<EthHeaderPrepStrategy>()?
+    .execute_transactions::<EthTxExecStrategy>()?;
+
+    let BlockBuilder {
+        chain_spec,
+        input,
+        db,
+        header,
+    } = block_builder;
+    let recorder = db.expect("Database is not initialized");
+    let header = BlockBuilder {
+        chain_spec,
+        input,
+        db: Some(recorder.db),
+        header,
+    }
+    .build::<BuildFromMemDbStrategy>()?;
+
+    Ok((header, recorder.oldest_block))
+}
+
+/// Prunes the trie, making sure its hash does not change.
+fn prune_checked(trie: &MptNode, paths: &[Vec<u8>]) -> Result<MptNode> {
+    let paths: Vec<_> = paths.iter().map(Vec::as_slice).collect();
+    let pruned = prune(trie, &paths);
+    ensure!(pruned.hash() == trie.hash(), "Pruning changed the trie");
+    Ok(pruned)
+}
+
+/// Returns a copy of the node, in which every subtrie not on one of the given nibble
+/// paths is replaced by its digest. Nodes next to a path are kept shallowly.
+pub fn prune(node: &MptNode, paths: &[&[u8]]) -> MptNode {
+    match node.as_data() {
+        MptNodeData::Null | MptNodeData::Leaf(_, _) | MptNodeData::Digest(_) => node.clone(),
+        MptNodeData::Branch(children) => {
+            let children: Vec<_> = children
+                .iter()
+                .enumerate()
+                .map(|(i, child)| {
+                    child.as_ref().map(|child| {
+                        let rest: Vec<_> = paths
+                            .iter()
+                            .filter(|path| path.first() == Some(&(i as u8)))
+                            .map(|path| &path[1..])
+                            .collect();
+                        if rest.is_empty() {
+                            Box::new(shallow(child))
+                        } else {
+                            Box::new(prune(child, &rest))
+                        }
+                    })
+                })
+                .collect();
+            MptNodeData::Branch(children.try_into().unwrap()).into()
+        }
+        MptNodeData::Extension(prefix, child) => {
+            let nibs = node.nibs();
+            let rest: Vec<_> = paths
+                .iter()
+                .filter_map(|path| path.strip_prefix(nibs.as_slice()))
+                .collect();
+            let child = if rest.is_empty() {
+                to_digest(child)
+            } else {
+                prune(child, &rest)
+            };
+            MptNodeData::Extension(prefix.clone(), Box::new(child)).into()
+        }
+    }
+}
+
+/// Keeps the node itself, as it may be merged into its parent, but replaces its children
+/// by their digests.
+///
+/// An orphaned branch is only referenced by the new extension, so its digest suffices.
+fn shallow(node: &MptNode) -> MptNode {
+    match node.as_data() {
+        MptNodeData::Branch(_) => to_digest(node),
+        MptNodeData::Extension(prefix, child) => {
+            MptNodeData::Extension(prefix.clone(), Box::new(to_digest(child))).into()
+        }
+        MptNodeData::Null | MptNodeData::Leaf(_, _) | MptNodeData::Digest(_) => node.clone(),
+    }
+}
+
+/// Replaces the node by its digest, unless it is short enough to be embedded into its
+/// parent.
+fn to_digest(node: &MptNode) -> MptNode {
+    match node.reference() {
+        MptNodeReference::Digest(digest) => MptNodeData::Digest(digest).into(),
+        MptNodeReference::Bytes(_) => node.clone(),
+    }
+}
+
+/// A [MemDb] recording the oldest block hash read.
+struct BlockHashRecorder {
+    db: MemDb,
+    oldest_block: Option<u64>,
+}
+
+impl BlockHashRecorder {
+    fn new(db: MemDb) -> Self {
+        BlockHashRecorder {
+            db,
+            oldest_block: None,
+        }
+    }
+}
+
+impl Database for BlockHashRecorder {
+    type Error = DbError;
+
+    fn basic(&mut self, address: B160) -> Result<Option<AccountInfo>, Self::Error> {
+        self.db.basic(address)
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        self.db.code_by_hash(code_hash)
+    }
+
+    fn storage(&mut self, address: B160, index: U256) -> Result<U256, Self::Error> {
+        self.db.storage(address, index)
+    }
+
+    fn block_hash(&mut self, number: U256) -> Result<B256, Self::Error> {
+        let block_hash = self.db.block_hash(number)?;
+        // the lookup only succeeds for valid block numbers
+        let block_no: u64 = number.try_into().unwrap();
+        self.oldest_block = Some(self.oldest_block.map_or(block_no, |b| b.min(block_no)));
+        Ok(block_hash)
+    }
+}
+
+impl DatabaseCommit for BlockHashRecorder {
+    fn commit(&mut self, changes: hashbrown::HashMap<B160, Account>) {
+        self.db.commit(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trie(keys: &[u64]) -> MptNode {
+        let mut trie = MptNode::default();
+        for key in keys {
+            trie.insert_rlp(&keccak(key.to_be_bytes()), *key).unwrap();
+        }
+        trie
+    }
+
+    fn path(key: u64) -> Vec<u8> {
+        to_nibs(&keccak(key.to_be_bytes()))
+    }
+
+    #[test]
+    fn prune_keeps_hash_and_paths() {
+        let keys: Vec<u64> = (0..256).collect();
+        let trie = trie(&keys);
+        let paths = [path(7), path(42)];
+        let pruned = prune_checked(&trie, &paths).unwrap();
+
+        assert_eq!(pruned.hash(), trie.hash());
+        assert!(pruned.size() < trie.size());
+        for key in [7u64, 42] {
+            assert_eq!(
+                pruned.get_rlp::<u64>(&keccak(key.to_be_bytes())).unwrap(),
+                Some(key)
+            );
+        }
+    }
+
+    #[test]
+    fn prune_supports_updates_on_paths() {
+        let keys: Vec<u64> = (0..256).collect();
+        let mut trie = trie(&keys);
+        let new_key = 1000u64;
+        let paths = [path(7), path(42), path(new_key)];
+        let mut pruned = prune_checked(&trie, &paths).unwrap();
+
+        for trie in [&mut trie, &mut pruned] {
+            assert!(trie.delete(&keccak(7u64.to_be_bytes())).unwrap());
+            trie.insert_rlp(&keccak(42u64.to_be_bytes()), 43u64)
+                .unwrap();
+            trie.insert_rlp(&keccak(new_key.to_be_bytes()), new_key)
+                .unwrap();
+        }
+        assert_eq!(pruned.hash(), trie.hash());
+    }
+
+    #[test]
+    fn counts_duplicate_nodes() {
+        // the subtries below the nibbles 1 and 2 are identical
+        let mut shared = MptNode::default();
+        for prefix in [0x10u8, 0x20] {
+            for suffix in [0x01u8, 0x02] {
+                shared.insert(&[prefix, suffix], vec![suffix; 200]).unwrap();
+            }
+        }
+        // extension, branch and two leaves
+        assert_eq!(duplicate_nodes(&shared), 4);
+        assert!(table_size(&shared).unwrap() < bincode::serialized_size(&shared).unwrap());
+
+        let unique = trie(&(0..16).collect::<Vec<_>>());
+        assert_eq!(duplicate_nodes(&unique), 0);
+    }
+
+    #[test]
+    fn prune_keeps_embedded_nodes() {
+        let mut trie = MptNode::default();
+        trie.insert(b"a", b"1".to_vec()).unwrap();
+        trie.insert(b"b", b"2".to_vec()).unwrap();
+        let pruned = prune_checked(&trie, &[]).unwrap();
+
+        assert_eq!(pruned.hash(), trie.hash());
+        assert_eq!(pruned.size(), trie.size());
+    }
+}
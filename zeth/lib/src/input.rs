@@ -44,8 +44,10 @@ pub struct Input {
     /// List of stake withdrawals for execution
     pub withdrawals: Vec<Withdrawal>,
     /// State trie of the parent block.
+    #[serde(with = "zeth_primitives::trie::table")]
     pub parent_state_trie: MptNode,
     /// Maps each address with its storage trie and the used storage slots.
+    #[serde(with = "storage_tables")]
     pub parent_storage: HashMap<RevmB160, StorageEntry, NoHashBuilder>,
     /// The code of all unique contracts.
     pub contracts: Vec<Bytes>,
@@ -55,8 +57,41 @@ pub struct Input {
 
 pub type StorageEntry = (MptNode, Vec<U256>);
 
+/// Serializes the storage tries as node tables, like the state trie.
+mod storage_tables {
+    use serde::{de, ser::SerializeMap, Deserializer, Serializer};
+    use zeth_primitives::trie::table::{self, TrieEncoding};
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        storage: &HashMap<RevmB160, StorageEntry, NoHashBuilder>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(storage.len()))?;
+        for (address, (trie, slots)) in storage {
+            map.serialize_entry(address, &(table::encode(trie), slots))?;
+        }
+        map.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<RevmB160, StorageEntry, NoHashBuilder>, D::Error> {
+        let encoded: HashMap<RevmB160, (TrieEncoding, Vec<U256>), NoHashBuilder> =
+            Deserialize::deserialize(deserializer)?;
+        encoded
+            .into_iter()
+            .map(|(address, (trie, slots))| Ok((address, (trie.into_trie()?, slots))))
+            .collect::<Result<_, _>>()
+            .map_err(de::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use zeth_primitives::keccak::keccak;
+
     use super::*;
 
     #[test]
@@ -64,4 +99,32 @@ mod tests {
         let input = Input::default();
         let _: Input = bincode::deserialize(&bincode::serialize(&input).unwrap()).unwrap();
     }
+
+    #[test]
+    fn input_tries_roundtrip() {
+        let mut input = Input::default();
+        let mut storage_trie = MptNode::default();
+        for i in 0..16u64 {
+            input
+                .parent_state_trie
+                .insert_rlp(&keccak(i.to_be_bytes()), i)
+                .unwrap();
+            storage_trie
+                .insert_rlp(&keccak((i + 16).to_be_bytes()), i)
+                .unwrap();
+        }
+        input.parent_storage.insert(
+            RevmB160::zero(),
+            (storage_trie.clone(), vec![U256::from(1)]),
+        );
+
+        let decoded: Input = bincode::deserialize(&bincode::serialize(&input).unwrap()).unwrap();
+        assert_eq!(
+            decoded.parent_state_trie.hash(),
+            input.parent_state_trie.hash()
+        );
+        let (trie, slots) = &decoded.parent_storage[&RevmB160::zero()];
+        assert_eq!(trie.hash(), storage_trie.hash());
+        assert_eq!(slots, &vec![U256::from(1)]);
+    }
 }
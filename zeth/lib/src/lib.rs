@@ -19,6 +19,7 @@ pub mod host;
 
 pub mod block_builder;
 pub mod consts;
+pub mod cycle_tracker;
 pub mod execution;
 pub mod finalization;
 pub mod initialization;
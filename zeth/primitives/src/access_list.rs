@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use alloy_primitives::{StorageKey, B160};
-use alloy_rlp_derive::{RlpEncodable, RlpEncodableWrapper};
+use alloy_rlp_derive::{RlpDecodable, RlpDecodableWrapper, RlpEncodable, RlpEncodableWrapper};
 use serde::{Deserialize, Serialize};
 
 /// Represents an access list as defined in EIP-2930.
@@ -21,7 +21,17 @@ use serde::{Deserialize, Serialize};
 /// An access list is a list of addresses and storage keys that a transaction will access,
 /// allowing for gas optimizations. This structure is introduced to improve the gas cost
 /// calculations by making certain accesses cheaper if they are declared in this list.
-#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, RlpEncodableWrapper)]
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Default,
+    Serialize,
+    Deserialize,
+    RlpEncodableWrapper,
+    RlpDecodableWrapper,
+)]
 pub struct AccessList(pub Vec<AccessListItem>);
 
 /// Represents an item in the [AccessList].
@@ -29,7 +39,9 @@ pub struct AccessList(pub Vec<AccessListItem>);
 /// Each item specifies an Ethereum address and a set of storage keys that the transaction
 /// will access. By providing this information up front, the transaction can benefit from
 /// gas cost optimizations.
-#[derive(Debug, Clone, PartialEq, Eq, Default, RlpEncodable, Serialize, Deserialize)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, Default, RlpEncodable, RlpDecodable, Serialize, Deserialize,
+)]
 pub struct AccessListItem {
     /// The Ethereum address that the transaction will access.
     pub address: B160,
@@ -13,16 +13,24 @@
 // limitations under the License.
 
 use alloy_primitives::{b256, BlockHash, BlockNumber, Bloom, Bytes, B160, B256, B64, U256};
-use alloy_rlp_derive::RlpEncodable;
+use alloy_rlp::{Buf, Decodable, Encodable, EMPTY_LIST_CODE};
+use alloy_rlp_derive::{RlpDecodable, RlpEncodable};
+use anyhow::{ensure, Context};
 use serde::{Deserialize, Serialize};
 
-use crate::{keccak::keccak, trie::EMPTY_ROOT};
+use crate::{
+    keccak::keccak,
+    transaction::{rlp_list_payload, Transaction},
+    trie::{MptNode, EMPTY_ROOT},
+    withdrawal::Withdrawal,
+    RlpBytes,
+};
 
 /// Keccak-256 hash of the RLP of an empty list.
 pub const EMPTY_LIST_HASH: B256 =
     b256!("1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347");
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, RlpEncodable)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, RlpEncodable, RlpDecodable)]
 #[rlp(trailing)]
 pub struct Header {
     /// Hash of the parent block's header.
@@ -95,11 +103,205 @@ impl Header {
     }
 }
 
+/// Represents a complete Ethereum block: its header together with its body.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Block {
+    /// Header of the block.
+    pub header: Header,
+    /// Transactions included in the block, in order of execution.
+    pub transactions: Vec<Transaction>,
+    /// Headers of the ommers of the block, always empty after the Paris update.
+    pub ommers: Vec<Header>,
+    /// Withdrawals processed in the block. Present after the Shanghai update.
+    #[serde(default)]
+    pub withdrawals: Option<Vec<Withdrawal>>,
+}
+
+impl Block {
+    /// Computes the hash of the block, which is the hash of its header.
+    pub fn hash(&self) -> BlockHash {
+        self.header.hash()
+    }
+
+    /// Verifies that the body of the block matches the commitments in its header.
+    ///
+    /// This recomputes the `transactions_root`, the `withdrawals_root` and the
+    /// `ommers_hash` and returns an error if any of them differs from the header.
+    pub fn validate_body(&self) -> anyhow::Result<()> {
+        let mut tx_trie = MptNode::default();
+        for (tx_no, tx) in self.transactions.iter().enumerate() {
+            tx_trie
+                .insert_rlp(&tx_no.to_rlp(), tx)
+                .context("failed to insert transaction")?;
+        }
+        ensure!(
+            tx_trie.hash() == self.header.transactions_root,
+            "transactions_root mismatch: header {}, body {}",
+            self.header.transactions_root,
+            tx_trie.hash()
+        );
+
+        let withdrawals_root = match &self.withdrawals {
+            Some(withdrawals) => {
+                let mut withdrawals_trie = MptNode::default();
+                for (i, withdrawal) in withdrawals.iter().enumerate() {
+                    withdrawals_trie
+                        .insert_rlp(&i.to_rlp(), withdrawal)
+                        .context("failed to insert withdrawal")?;
+                }
+                Some(withdrawals_trie.hash())
+            }
+            None => None,
+        };
+        ensure!(
+            withdrawals_root == self.header.withdrawals_root,
+            "withdrawals_root mismatch: header {:?}, body {:?}",
+            self.header.withdrawals_root,
+            withdrawals_root
+        );
+
+        let ommers_hash: B256 = keccak(alloy_rlp::encode(&self.ommers)).into();
+        ensure!(
+            ommers_hash == self.header.ommers_hash,
+            "ommers_hash mismatch: header {}, body {}",
+            self.header.ommers_hash,
+            ommers_hash
+        );
+
+        Ok(())
+    }
+
+    /// Computes the length of the RLP-encoded list of transactions.
+    fn transactions_payload_length(&self) -> usize {
+        self.transactions.iter().map(body_tx_length).sum()
+    }
+
+    /// Computes the length of the RLP-encoded payload of the block.
+    fn payload_length(&self) -> usize {
+        let transactions_length = self.transactions_payload_length();
+        let mut payload_length = self.header.length()
+            + alloy_rlp::length_of_length(transactions_length)
+            + transactions_length
+            + self.ommers.length();
+        if let Some(withdrawals) = &self.withdrawals {
+            payload_length += withdrawals.length();
+        }
+        payload_length
+    }
+}
+
+/// Provides RLP encoding functionality for the [Block] struct.
+///
+/// The block is encoded as the list of its header, transactions, ommers and, if present,
+/// withdrawals. Inside the body, EIP-2718 typed transactions are wrapped in an RLP
+/// string.
+impl Encodable for Block {
+    fn encode(&self, out: &mut dyn alloy_rlp::BufMut) {
+        alloy_rlp::Header {
+            list: true,
+            payload_length: self.payload_length(),
+        }
+        .encode(out);
+        self.header.encode(out);
+        alloy_rlp::Header {
+            list: true,
+            payload_length: self.transactions_payload_length(),
+        }
+        .encode(out);
+        for tx in &self.transactions {
+            if tx.tx_type() != 0 {
+                alloy_rlp::Header {
+                    list: false,
+                    payload_length: tx.length(),
+                }
+                .encode(out);
+            }
+            tx.encode(out);
+        }
+        self.ommers.encode(out);
+        if let Some(withdrawals) = &self.withdrawals {
+            withdrawals.encode(out);
+        }
+    }
+
+    fn length(&self) -> usize {
+        let payload_length = self.payload_length();
+        alloy_rlp::length_of_length(payload_length) + payload_length
+    }
+}
+
+/// Provides RLP decoding functionality for the [Block] struct, as encoded by
+/// [Block::encode].
+impl Decodable for Block {
+    fn decode(buf: &mut &[u8]) -> alloy_rlp::Result<Self> {
+        let mut payload = rlp_list_payload(buf)?;
+        let buf = &mut payload;
+
+        let header = Header::decode(buf)?;
+        let mut transactions_payload = rlp_list_payload(buf)?;
+        let mut transactions = Vec::new();
+        while !transactions_payload.is_empty() {
+            transactions.push(decode_body_tx(&mut transactions_payload)?);
+        }
+        let ommers = Decodable::decode(buf)?;
+        let withdrawals = if buf.is_empty() {
+            None
+        } else {
+            Some(Decodable::decode(buf)?)
+        };
+        if !buf.is_empty() {
+            return Err(alloy_rlp::Error::UnexpectedLength);
+        }
+
+        Ok(Block {
+            header,
+            transactions,
+            ommers,
+            withdrawals,
+        })
+    }
+}
+
+/// Computes the length of a transaction encoded inside a block body.
+fn body_tx_length(tx: &Transaction) -> usize {
+    let length = tx.length();
+    if tx.tx_type() != 0 {
+        alloy_rlp::length_of_length(length) + length
+    } else {
+        length
+    }
+}
+
+/// Decodes a transaction inside a block body, unwrapping typed transactions from their
+/// RLP string.
+fn decode_body_tx(buf: &mut &[u8]) -> alloy_rlp::Result<Transaction> {
+    match buf.first() {
+        Some(&first) if first < EMPTY_LIST_CODE => {
+            let header = alloy_rlp::Header::decode(buf)?;
+            if buf.len() < header.payload_length {
+                return Err(alloy_rlp::Error::InputTooShort);
+            }
+            let mut envelope = &buf[..header.payload_length];
+            buf.advance(header.payload_length);
+            let tx = Transaction::decode(&mut envelope)?;
+            if !envelope.is_empty() {
+                return Err(alloy_rlp::Error::UnexpectedLength);
+            }
+            Ok(tx)
+        }
+        _ => Transaction::decode(buf),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json::json;
 
     use super::*;
+    use crate::{
+        signature::TxSignature,
+        transaction::{TxEssence, TxEssenceEip1559, TxEssenceLegacy},
+    };
 
     #[test]
     fn paris() {
@@ -126,6 +328,9 @@ mod tests {
 
         // verify that bincode serialization works
         let _: Header = bincode::deserialize(&bincode::serialize(&header).unwrap()).unwrap();
+        // verify that RLP decoding restores the header
+        let encoded = alloy_rlp::encode(&header);
+        assert_eq!(Header::decode(&mut &encoded[..]).unwrap(), header);
 
         assert_eq!(
             "0x56a9bb0302da44b8c0b3df540781424684c3af04d0b7a38d72842b762076a664",
@@ -159,10 +364,93 @@ mod tests {
 
         // verify that bincode serialization works
         let _: Header = bincode::deserialize(&bincode::serialize(&header).unwrap()).unwrap();
+        // verify that RLP decoding restores the header
+        let encoded = alloy_rlp::encode(&header);
+        assert_eq!(Header::decode(&mut &encoded[..]).unwrap(), header);
 
         assert_eq!(
             "0xe22c56f211f03baadcc91e4eb9a24344e6848c5df4473988f893b58223f5216c",
             header.hash().to_string()
         )
     }
+
+    fn test_block() -> Block {
+        let legacy = Transaction {
+            essence: TxEssence::Legacy(TxEssenceLegacy {
+                chain_id: Some(1),
+                nonce: 1,
+                gas_limit: U256::from(21_000),
+                ..Default::default()
+            }),
+            signature: TxSignature {
+                v: 37,
+                r: U256::from(1),
+                s: U256::from(2),
+            },
+        };
+        let eip1559 = Transaction {
+            essence: TxEssence::Eip1559(TxEssenceEip1559 {
+                chain_id: 1,
+                nonce: 2,
+                data: Bytes::from_static(&[0xde, 0xad]),
+                ..Default::default()
+            }),
+            signature: TxSignature {
+                v: 1,
+                r: U256::from(3),
+                s: U256::from(4),
+            },
+        };
+        let withdrawal = Withdrawal {
+            index: 7,
+            validator_index: 8,
+            address: B160::from([0xff; 20]),
+            amount: 1_000,
+        };
+
+        let mut block = Block {
+            header: Header::default(),
+            transactions: vec![legacy, eip1559],
+            ommers: vec![],
+            withdrawals: Some(vec![withdrawal]),
+        };
+        let mut tx_trie = MptNode::default();
+        for (tx_no, tx) in block.transactions.iter().enumerate() {
+            tx_trie.insert_rlp(&tx_no.to_rlp(), tx).unwrap();
+        }
+        block.header.transactions_root = tx_trie.hash();
+        let mut withdrawals_trie = MptNode::default();
+        withdrawals_trie
+            .insert_rlp(&0usize.to_rlp(), &block.withdrawals.as_ref().unwrap()[0])
+            .unwrap();
+        block.header.withdrawals_root = Some(withdrawals_trie.hash());
+        block
+    }
+
+    #[test]
+    fn block_rlp() {
+        let block = test_block();
+
+        let encoded = alloy_rlp::encode(&block);
+        assert_eq!(encoded.len(), block.length());
+        assert_eq!(Block::decode(&mut &encoded[..]).unwrap(), block);
+    }
+
+    #[test]
+    fn validate_body() {
+        let block = test_block();
+        block.validate_body().unwrap();
+
+        let mut tampered = block.clone();
+        tampered.transactions.swap(0, 1);
+        assert!(tampered.validate_body().is_err());
+
+        let mut tampered = block.clone();
+        tampered.withdrawals = None;
+        assert!(tampered.validate_body().is_err());
+
+        let mut tampered = block;
+        tampered.ommers.push(Header::default());
+        assert!(tampered.validate_body().is_err());
+    }
 }
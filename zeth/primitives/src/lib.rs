@@ -20,6 +20,7 @@ pub mod keccak;
 pub mod receipt;
 pub mod signature;
 pub mod transaction;
+pub mod transaction_builder;
 pub mod trie;
 pub mod withdrawal;
 
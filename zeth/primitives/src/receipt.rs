@@ -13,12 +13,14 @@
 // limitations under the License.
 
 use alloy_primitives::{Bloom, BloomInput, Bytes, B160, B256, U256};
-use alloy_rlp::Encodable;
-use alloy_rlp_derive::RlpEncodable;
+use alloy_rlp::{Buf, Decodable, Encodable, EMPTY_LIST_CODE};
+use alloy_rlp_derive::{RlpDecodable, RlpEncodable};
 use serde::{Deserialize, Serialize};
 
 /// Represents an Ethereum log entry.
-#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, RlpEncodable)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, RlpEncodable, RlpDecodable,
+)]
 pub struct Log {
     /// Contract that emitted this log.
     pub address: B160,
@@ -29,7 +31,9 @@ pub struct Log {
 }
 
 /// Payload of a [Receipt].
-#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, RlpEncodable)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, RlpEncodable, RlpDecodable,
+)]
 pub struct ReceiptPayload {
     /// Indicates whether the transaction was executed successfully.
     pub success: bool,
@@ -78,6 +82,26 @@ impl Encodable for Receipt {
     }
 }
 
+impl Decodable for Receipt {
+    /// Decodes a receipt from the `buf` buffer, as encoded by [Receipt::encode].
+    fn decode(buf: &mut &[u8]) -> alloy_rlp::Result<Self> {
+        let tx_type = match buf.first() {
+            None => return Err(alloy_rlp::Error::InputTooShort),
+            // legacy receipts are a plain RLP list
+            Some(&first) if first >= EMPTY_LIST_CODE => 0,
+            // EIP-2718 typed receipts are prefixed with the transaction type
+            Some(&tx_type) if tx_type == 1 || tx_type == 2 => {
+                buf.advance(1);
+                tx_type
+            }
+            Some(_) => return Err(alloy_rlp::Error::Custom("unsupported receipt type")),
+        };
+        let payload = ReceiptPayload::decode(buf)?;
+
+        Ok(Receipt { tx_type, payload })
+    }
+}
+
 impl Receipt {
     /// Constructs a new [Receipt].
     ///
@@ -103,6 +127,124 @@ impl Receipt {
     }
 }
 
+/// Returns whether the address may have emitted a log accrued into the bloom filter.
+///
+/// Bloom filters have false positives but no false negatives: `false` means that none
+/// of the logs were emitted by the address.
+pub fn bloom_contains_address(bloom: &Bloom, address: &B160) -> bool {
+    bloom.contains_input(BloomInput::Raw(address.as_slice()))
+}
+
+/// Returns whether the topic may be part of a log accrued into the bloom filter.
+///
+/// Like [bloom_contains_address], this can return false positives.
+pub fn bloom_contains_topic(bloom: &Bloom, topic: &B256) -> bool {
+    bloom.contains_input(BloomInput::Raw(topic.as_slice()))
+}
+
+/// A filter selecting logs by their address and topics, with the semantics of
+/// `eth_getLogs`.
+///
+/// A log matches if it was emitted by one of the addresses, and if for every topic
+/// position its topic at that position is one of the given topics. An empty set of
+/// addresses, or an empty set of topics at a position, matches anything. A log with
+/// fewer topics than the filter has positions never matches.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct LogFilter {
+    /// Contracts whose logs match.
+    pub addresses: Vec<B160>,
+    /// Alternative topics for each position.
+    pub topics: Vec<Vec<B256>>,
+}
+
+/// A log selected by a [LogFilter] from the receipts of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilteredLog<'a> {
+    /// Index of the transaction that emitted the log.
+    pub transaction_index: usize,
+    /// Index of the log within the block.
+    pub log_index: usize,
+    /// The log itself.
+    pub log: &'a Log,
+}
+
+impl LogFilter {
+    /// Adds an address to the set of matching addresses.
+    pub fn address(mut self, address: B160) -> Self {
+        self.addresses.push(address);
+        self
+    }
+
+    /// Adds a topic to the set of matching topics at the given position.
+    pub fn topic(mut self, position: usize, topic: B256) -> Self {
+        if self.topics.len() <= position {
+            self.topics.resize(position + 1, Vec::new());
+        }
+        self.topics[position].push(topic);
+        self
+    }
+
+    /// Returns whether the log matches the filter.
+    pub fn matches(&self, log: &Log) -> bool {
+        if !self.addresses.is_empty() && !self.addresses.contains(&log.address) {
+            return false;
+        }
+        if self.topics.len() > log.topics.len() {
+            return false;
+        }
+        self.topics
+            .iter()
+            .zip(&log.topics)
+            .all(|(topics, topic)| topics.is_empty() || topics.contains(topic))
+    }
+
+    /// Returns whether any log accrued into the bloom filter may match the filter.
+    ///
+    /// This can be used to skip receipts or entire blocks, but a `true` result must be
+    /// confirmed with [LogFilter::matches] on the logs themselves.
+    pub fn matches_bloom(&self, bloom: &Bloom) -> bool {
+        let address_match = self.addresses.is_empty()
+            || self
+                .addresses
+                .iter()
+                .any(|address| bloom_contains_address(bloom, address));
+        address_match
+            && self.topics.iter().all(|topics| {
+                topics.is_empty()
+                    || topics
+                        .iter()
+                        .any(|topic| bloom_contains_topic(bloom, topic))
+            })
+    }
+
+    /// Returns the matching logs from the receipts of a block, in the order of the
+    /// transactions.
+    pub fn filter_receipts<'a>(
+        &self,
+        receipts: impl IntoIterator<Item = &'a Receipt>,
+    ) -> Vec<FilteredLog<'a>> {
+        let mut result = Vec::new();
+        let mut log_index = 0;
+        for (transaction_index, receipt) in receipts.into_iter().enumerate() {
+            let logs = &receipt.payload.logs;
+            // the log indices still have to count the logs of skipped receipts
+            if self.matches_bloom(&receipt.payload.logs_bloom) {
+                for (i, log) in logs.iter().enumerate() {
+                    if self.matches(log) {
+                        result.push(FilteredLog {
+                            transaction_index,
+                            log_index: log_index + i,
+                            log,
+                        });
+                    }
+                }
+            }
+            log_index += logs.len();
+        }
+        result
+    }
+}
+
 // test vectors from https://github.com/ethereum/go-ethereum/blob/c40ab6af72ce282020d03c33e8273ea9b03d58f6/core/types/receipt_test.go
 #[cfg(test)]
 mod tests {
@@ -142,6 +284,13 @@ mod tests {
         receipt.encode(&mut data);
 
         assert_eq!(data, expected);
+        assert_eq!(Receipt::decode(&mut &expected[..]).unwrap(), receipt);
+    }
+
+    #[test]
+    fn decode_unsupported_type() {
+        assert!(Receipt::decode(&mut &hex!("03c0")[..]).is_err());
+        assert!(Receipt::decode(&mut &[][..]).is_err());
     }
 
     #[test]
@@ -175,6 +324,7 @@ mod tests {
         receipt.encode(&mut data);
 
         assert_eq!(data, expected);
+        assert_eq!(Receipt::decode(&mut &expected[..]).unwrap(), receipt);
     }
 
     #[test]
@@ -208,5 +358,92 @@ mod tests {
         receipt.encode(&mut data);
 
         assert_eq!(data, expected);
+        assert_eq!(Receipt::decode(&mut &expected[..]).unwrap(), receipt);
+    }
+
+    fn log(address: u8, topics: &[u8]) -> Log {
+        Log {
+            address: B160::repeat_byte(address),
+            topics: topics.iter().map(|t| B256::repeat_byte(*t)).collect(),
+            data: Bytes::new(),
+        }
+    }
+
+    #[test]
+    fn bloom() {
+        let receipt = Receipt::new(0, true, U256::from(1), vec![log(0x11, &[0xaa, 0xbb])]);
+        let bloom = &receipt.payload.logs_bloom;
+
+        assert!(bloom_contains_address(bloom, &B160::repeat_byte(0x11)));
+        assert!(bloom_contains_topic(bloom, &B256::repeat_byte(0xaa)));
+        assert!(bloom_contains_topic(bloom, &B256::repeat_byte(0xbb)));
+        assert!(!bloom_contains_address(bloom, &B160::repeat_byte(0x22)));
+        assert!(!bloom_contains_topic(bloom, &B256::repeat_byte(0xcc)));
+        assert!(!bloom_contains_address(
+            &Bloom::default(),
+            &B160::repeat_byte(0x11)
+        ));
+    }
+
+    #[test]
+    fn log_filter_matches() {
+        let log = log(0x11, &[0xaa, 0xbb]);
+
+        assert!(LogFilter::default().matches(&log));
+        assert!(LogFilter::default()
+            .address(B160::repeat_byte(0x22))
+            .address(B160::repeat_byte(0x11))
+            .matches(&log));
+        assert!(!LogFilter::default()
+            .address(B160::repeat_byte(0x22))
+            .matches(&log));
+
+        // topics are matched by position, with empty positions as wildcards
+        assert!(LogFilter::default()
+            .topic(1, B256::repeat_byte(0xbb))
+            .matches(&log));
+        assert!(LogFilter::default()
+            .topic(0, B256::repeat_byte(0xcc))
+            .topic(0, B256::repeat_byte(0xaa))
+            .matches(&log));
+        assert!(!LogFilter::default()
+            .topic(0, B256::repeat_byte(0xbb))
+            .matches(&log));
+        // the log must have at least as many topics as the filter has positions
+        assert!(!LogFilter {
+            addresses: vec![],
+            topics: vec![vec![], vec![], vec![]],
+        }
+        .matches(&log));
+    }
+
+    #[test]
+    fn filter_receipts() {
+        let receipts = [
+            Receipt::new(0, true, U256::from(1), vec![log(0x11, &[0xaa])]),
+            Receipt::new(2, true, U256::from(2), vec![]),
+            Receipt::new(
+                2,
+                true,
+                U256::from(3),
+                vec![log(0x22, &[0xaa]), log(0x11, &[0xbb]), log(0x11, &[0xaa])],
+            ),
+        ];
+
+        let filter = LogFilter::default()
+            .address(B160::repeat_byte(0x11))
+            .topic(0, B256::repeat_byte(0xaa));
+        let logs = filter.filter_receipts(&receipts);
+        assert_eq!(
+            logs.iter()
+                .map(|log| (log.transaction_index, log.log_index))
+                .collect::<Vec<_>>(),
+            vec![(0, 0), (2, 3)]
+        );
+        assert!(logs.iter().all(|log| filter.matches(log.log)));
+
+        let filter = LogFilter::default().address(B160::repeat_byte(0x33));
+        assert!(!filter.matches_bloom(&receipts[0].payload.logs_bloom));
+        assert!(filter.filter_receipts(&receipts).is_empty());
     }
 }
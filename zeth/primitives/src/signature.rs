@@ -12,27 +12,46 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use alloy_primitives::{B160, U256};
-use alloy_rlp_derive::{RlpEncodable, RlpMaxEncodedLen};
-use anyhow::Context;
+use alloy_primitives::{uint, ChainId, B160, U256};
+use alloy_rlp_derive::{RlpDecodable, RlpEncodable, RlpMaxEncodedLen};
+use anyhow::{bail, Context};
+pub use k256::ecdsa::SigningKey;
 use k256::{
     ecdsa::{RecoveryId, Signature as K256Signature, VerifyingKey as K256VerifyingKey},
     elliptic_curve::sec1::ToEncodedPoint,
     PublicKey as K256PublicKey,
 };
 use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
 
 use crate::{
     keccak::keccak,
     transaction::{Transaction, TxEssence, TxEssenceLegacy},
 };
 
+/// Order of the secp256k1 curve.
+const SECP256K1N: U256 =
+    uint!(0xfffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141_U256);
+/// Half the order of the secp256k1 curve, the largest `s` allowed by EIP-2.
+const SECP256K1N_HALF: U256 =
+    uint!(0x7fffffffffffffffffffffffffffffff5d576e7357a4501ddfe92f46681b20a0_U256);
+
 /// Represents a cryptographic signature associated with a transaction.
 ///
 /// The `TxSignature` struct encapsulates the components of an ECDSA signature: `v`, `r`,
 /// and `s`. This signature can be used to recover the public key of the signer, ensuring
 /// the authenticity of the transaction.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, RlpEncodable, RlpMaxEncodedLen)]
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    RlpEncodable,
+    RlpDecodable,
+    RlpMaxEncodedLen,
+)]
 pub struct TxSignature {
     pub v: u64,
     pub r: U256,
@@ -44,28 +63,143 @@ impl TxSignature {
     pub(crate) fn payload_length(&self) -> usize {
         self._alloy_rlp_payload_length()
     }
+
+    /// Checks that `r` and `s` are valid scalars and that `s` is in the lower half of the
+    /// curve order, as required by [EIP-2](https://eips.ethereum.org/EIPS/eip-2).
+    pub fn validate(&self) -> Result<(), SignatureError> {
+        self.check_range()?;
+        if self.s > SECP256K1N_HALF {
+            return Err(SignatureError::HighS);
+        }
+        Ok(())
+    }
+
+    /// Checks that `r` and `s` are neither zero nor at least the curve order.
+    fn check_range(&self) -> Result<(), SignatureError> {
+        if self.r == U256::ZERO || self.r >= SECP256K1N {
+            return Err(SignatureError::InvalidR);
+        }
+        if self.s == U256::ZERO || self.s >= SECP256K1N {
+            return Err(SignatureError::InvalidS);
+        }
+        Ok(())
+    }
+}
+
+/// Errors of the validation of a transaction signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ThisError)]
+pub enum SignatureError {
+    /// The `v` value does not match the transaction type or its chain ID.
+    #[error("invalid v: {0}")]
+    InvalidV(u64),
+    /// The `r` value is zero or not below the curve order.
+    #[error("invalid r")]
+    InvalidR,
+    /// The `s` value is zero or not below the curve order.
+    #[error("invalid s")]
+    InvalidS,
+    /// The `s` value is in the upper half of the curve order.
+    #[error("s in the upper half of the curve order")]
+    HighS,
+    /// The transaction was signed for another chain.
+    #[error("wrong chain ID: expected {expected}, got {actual}")]
+    WrongChainId { expected: ChainId, actual: ChainId },
+    /// No public key could be recovered from the signature.
+    #[error("public key recovery failed")]
+    RecoveryFailed,
+}
+
+impl TxEssence {
+    /// Signs the transaction essence with the given secret key.
+    ///
+    /// The `v` of the signature encodes the parity of the public key's y-coordinate: as
+    /// 27 or 28 for legacy transactions without a chain ID, as `35 + 2 * chain_id` plus
+    /// the parity for legacy transactions following
+    /// [EIP-155](https://eips.ethereum.org/EIPS/eip-155), and as the parity itself for
+    /// typed transactions. The `s` value is always normalized to the lower half of the
+    /// curve order.
+    pub fn sign(self, key: &SigningKey) -> anyhow::Result<Transaction> {
+        let (signature, recovery_id) = key
+            .sign_prehash_recoverable(self.signing_hash().as_slice())
+            .context("signing failed")?;
+        let (signature, is_y_odd) = match signature.normalize_s() {
+            Some(normalized) => (normalized, !recovery_id.is_y_odd()),
+            None => (signature, recovery_id.is_y_odd()),
+        };
+        if recovery_id.is_x_reduced() {
+            bail!("signature cannot be encoded with a reduced x-coordinate");
+        }
+
+        let parity = is_y_odd as u64;
+        let v = match &self {
+            TxEssence::Legacy(TxEssenceLegacy { chain_id: None, .. }) => 27 + parity,
+            TxEssence::Legacy(TxEssenceLegacy {
+                chain_id: Some(chain_id),
+                ..
+            }) => 35 + 2 * chain_id + parity,
+            _ => parity,
+        };
+        let (r, s) = signature.split_bytes();
+        Ok(Transaction {
+            essence: self,
+            signature: TxSignature {
+                v,
+                r: U256::from_be_slice(r.as_slice()),
+                s: U256::from_be_slice(s.as_slice()),
+            },
+        })
+    }
 }
 
 impl Transaction {
+    /// Validates the signature of the transaction for the given chain, and recovers the
+    /// Ethereum address of the sender.
+    ///
+    /// Unlike [Transaction::recover_from], this rejects signatures whose `s` is in the
+    /// upper half of the curve order, as required by
+    /// [EIP-2](https://eips.ethereum.org/EIPS/eip-2), and transactions signed for another
+    /// chain. Legacy transactions without a chain ID are valid on every chain.
+    pub fn verify_signature(&self, chain_id: ChainId) -> Result<B160, SignatureError> {
+        if let Some(tx_chain_id) = self.chain_id() {
+            if tx_chain_id != chain_id {
+                return Err(SignatureError::WrongChainId {
+                    expected: chain_id,
+                    actual: tx_chain_id,
+                });
+            }
+        }
+        self.signature.validate()?;
+        self.recover()
+    }
+
     /// Recovers the Ethereum address of the sender from the transaction's signature.
     ///
     /// This method uses the ECDSA recovery mechanism to derive the sender's public key
     /// and subsequently their Ethereum address. If the recovery is unsuccessful, an
     /// error is returned.
     pub fn recover_from(&self) -> anyhow::Result<B160> {
-        let is_y_odd = self.is_y_odd().context("v invalid")?;
+        Ok(self.recover()?)
+    }
+
+    /// Recovers the address of the sender, without checking the malleability of the
+    /// signature.
+    fn recover(&self) -> Result<B160, SignatureError> {
+        let is_y_odd = self
+            .is_y_odd()
+            .ok_or(SignatureError::InvalidV(self.signature.v))?;
+        self.signature.check_range()?;
         let signature = K256Signature::from_scalars(
             self.signature.r.to_be_bytes(),
             self.signature.s.to_be_bytes(),
         )
-        .context("r, s invalid")?;
+        .map_err(|_| SignatureError::RecoveryFailed)?;
 
         let verify_key = K256VerifyingKey::recover_from_prehash(
             self.essence.signing_hash().as_slice(),
             &signature,
             RecoveryId::new(is_y_odd, false),
         )
-        .context("invalid signature")?;
+        .map_err(|_| SignatureError::RecoveryFailed)?;
 
         let public_key = K256PublicKey::from(&verify_key);
         let public_key = public_key.to_encoded_point(false);
@@ -82,16 +216,18 @@ impl Transaction {
     /// This information is derived from the `v` component of the signature and is used
     /// during public key recovery.
     fn is_y_odd(&self) -> Option<bool> {
-        match &self.essence {
-            TxEssence::Legacy(TxEssenceLegacy { chain_id: None, .. }) => {
-                checked_bool(self.signature.v - 27)
-            }
+        let v = self.signature.v;
+        let parity = match &self.essence {
+            TxEssence::Legacy(TxEssenceLegacy { chain_id: None, .. }) => v.checked_sub(27),
             TxEssence::Legacy(TxEssenceLegacy {
                 chain_id: Some(chain_id),
                 ..
-            }) => checked_bool(self.signature.v - 35 - 2 * chain_id),
-            _ => checked_bool(self.signature.v),
-        }
+            }) => v
+                .checked_sub(35)
+                .and_then(|v| v.checked_sub(chain_id.checked_mul(2)?)),
+            _ => Some(v),
+        };
+        parity.and_then(checked_bool)
     }
 }
 
@@ -109,3 +245,97 @@ fn checked_bool(v: u64) -> Option<bool> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction_builder::TransactionBuilder;
+
+    fn signed(builder: TransactionBuilder) -> Transaction {
+        builder
+            .gas_limit(U256::from(21_000))
+            .sign(&SigningKey::from_slice(&[0x46; 32]).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn verify_signature() {
+        let builders = [
+            TransactionBuilder::legacy(),
+            TransactionBuilder::legacy().chain_id(1),
+            TransactionBuilder::eip2930().chain_id(1),
+            TransactionBuilder::eip1559().chain_id(1),
+        ];
+        for builder in builders {
+            let tx = signed(builder);
+            let sender = tx.recover_from().unwrap();
+            assert_eq!(tx.verify_signature(1), Ok(sender));
+
+            // the malleable twin of the signature recovers the same sender
+            let mut high_s = tx.clone();
+            high_s.signature.s = SECP256K1N - tx.signature.s;
+            high_s.signature.v = match tx.signature.v {
+                0 => 1,
+                1 => 0,
+                27 => 28,
+                28 => 27,
+                37 => 38,
+                38 => 37,
+                v => unreachable!("unexpected v: {}", v),
+            };
+            assert_eq!(high_s.recover_from().unwrap(), sender);
+            assert_eq!(high_s.verify_signature(1), Err(SignatureError::HighS));
+        }
+    }
+
+    #[test]
+    fn wrong_chain_id() {
+        // legacy transactions without chain ID are valid everywhere
+        assert!(signed(TransactionBuilder::legacy())
+            .verify_signature(5)
+            .is_ok());
+        for builder in [
+            TransactionBuilder::legacy().chain_id(1),
+            TransactionBuilder::eip2930().chain_id(1),
+            TransactionBuilder::eip1559().chain_id(1),
+        ] {
+            assert_eq!(
+                signed(builder).verify_signature(5),
+                Err(SignatureError::WrongChainId {
+                    expected: 5,
+                    actual: 1
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_v() {
+        let mut tx = signed(TransactionBuilder::legacy().chain_id(1));
+        tx.signature.v = 27;
+        assert_eq!(tx.verify_signature(1), Err(SignatureError::InvalidV(27)));
+        tx.signature.v = 0;
+        assert_eq!(tx.verify_signature(1), Err(SignatureError::InvalidV(0)));
+
+        let mut tx = signed(TransactionBuilder::eip1559().chain_id(1));
+        tx.signature.v = 27;
+        assert_eq!(tx.verify_signature(1), Err(SignatureError::InvalidV(27)));
+    }
+
+    #[test]
+    fn invalid_r_s() {
+        let tx = signed(TransactionBuilder::eip1559().chain_id(1));
+        for r in [U256::ZERO, SECP256K1N] {
+            let mut tx = tx.clone();
+            tx.signature.r = r;
+            assert_eq!(tx.verify_signature(1), Err(SignatureError::InvalidR));
+            assert!(tx.recover_from().is_err());
+        }
+        for s in [U256::ZERO, SECP256K1N, U256::MAX] {
+            let mut tx = tx.clone();
+            tx.signature.s = s;
+            assert_eq!(tx.verify_signature(1), Err(SignatureError::InvalidS));
+            assert!(tx.recover_from().is_err());
+        }
+    }
+}
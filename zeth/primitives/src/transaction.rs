@@ -13,8 +13,8 @@
 // limitations under the License.
 
 use alloy_primitives::{Bytes, ChainId, TxHash, TxNumber, B160, B256, U256};
-use alloy_rlp::{Encodable, EMPTY_STRING_CODE};
-use alloy_rlp_derive::RlpEncodable;
+use alloy_rlp::{Buf, Decodable, Encodable, EMPTY_LIST_CODE, EMPTY_STRING_CODE};
+use alloy_rlp_derive::{RlpDecodable, RlpEncodable};
 use serde::{Deserialize, Serialize};
 
 use crate::{access_list::AccessList, keccak::keccak, signature::TxSignature};
@@ -141,7 +141,9 @@ impl Encodable for TxEssenceLegacy {
 /// transaction that includes an access list. Access lists are a feature introduced in
 /// EIP-2930 to specify a list of addresses and storage keys that the transaction will
 /// access, allowing for more predictable gas costs.
-#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, RlpEncodable)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, RlpEncodable, RlpDecodable,
+)]
 pub struct TxEssenceEip2930 {
     /// The network's chain ID, ensuring the transaction is valid on the intended chain.
     pub chain_id: ChainId,
@@ -171,7 +173,9 @@ pub struct TxEssenceEip2930 {
 /// transaction that incorporates the priority fee mechanism introduced in EIP-1559. This
 /// mechanism aims to improve the predictability of gas fees and enhance the overall user
 /// experience.
-#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, RlpEncodable)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, RlpEncodable, RlpDecodable,
+)]
 pub struct TxEssenceEip1559 {
     /// The network's chain ID, ensuring the transaction is valid on the intended chain,
     /// as introduced in EIP-155.
@@ -333,6 +337,24 @@ impl From<TransactionKind> for Option<B160> {
     }
 }
 
+/// Provides a conversion from `Option<B160>` to [TransactionKind], the inverse of the
+/// conversion above: `None` is a `Create` and an address is a `Call` to it.
+impl From<Option<B160>> for TransactionKind {
+    fn from(value: Option<B160>) -> Self {
+        match value {
+            None => TransactionKind::Create,
+            Some(addr) => TransactionKind::Call(addr),
+        }
+    }
+}
+
+/// Provides a conversion from an address to a [TransactionKind] calling it.
+impl From<B160> for TransactionKind {
+    fn from(value: B160) -> Self {
+        TransactionKind::Call(value)
+    }
+}
+
 /// Provides RLP encoding functionality for the [TransactionKind] enum.
 ///
 /// This implementation ensures that each variant of the [TransactionKind] enum can be
@@ -366,6 +388,22 @@ impl Encodable for TransactionKind {
     }
 }
 
+/// Provides RLP decoding functionality for the [TransactionKind] enum.
+///
+/// An empty string is decoded as `Create`, everything else must be an address.
+impl Decodable for TransactionKind {
+    #[inline]
+    fn decode(buf: &mut &[u8]) -> alloy_rlp::Result<Self> {
+        match buf.first() {
+            Some(&EMPTY_STRING_CODE) => {
+                buf.advance(1);
+                Ok(TransactionKind::Create)
+            }
+            _ => Ok(TransactionKind::Call(B160::decode(buf)?)),
+        }
+    }
+}
+
 /// Represents a complete Ethereum transaction, encompassing its core essence and the
 /// associated signature.
 ///
@@ -423,6 +461,86 @@ impl Encodable for Transaction {
     }
 }
 
+/// Provides RLP decoding functionality for the [Transaction] struct.
+///
+/// This is the inverse of [Transaction::encode]: EIP-2718 typed transactions start with
+/// their transaction type, legacy transactions are a plain RLP list.
+impl Decodable for Transaction {
+    /// Decodes a [Transaction] from the `buf` buffer.
+    ///
+    /// The fields of the essence and the signature are read from one joined list. For
+    /// legacy transactions, the chain ID is derived from `v` as in [EIP-155](https://eips.ethereum.org/EIPS/eip-155).
+    fn decode(buf: &mut &[u8]) -> alloy_rlp::Result<Self> {
+        let tx_type = match buf.first() {
+            None => return Err(alloy_rlp::Error::InputTooShort),
+            Some(&first) if first >= EMPTY_LIST_CODE => 0x00,
+            Some(&tx_type) => {
+                buf.advance(1);
+                tx_type
+            }
+        };
+
+        let mut payload = rlp_list_payload(buf)?;
+        let buf = &mut payload;
+        let transaction = match tx_type {
+            0x00 => {
+                let mut essence = TxEssenceLegacy {
+                    chain_id: None,
+                    nonce: Decodable::decode(buf)?,
+                    gas_price: Decodable::decode(buf)?,
+                    gas_limit: Decodable::decode(buf)?,
+                    to: Decodable::decode(buf)?,
+                    value: Decodable::decode(buf)?,
+                    data: Decodable::decode(buf)?,
+                };
+                let signature = decode_signature(buf)?;
+                essence.chain_id = match signature.v {
+                    27 | 28 => None,
+                    v if v >= 35 => Some((v - 35) / 2),
+                    _ => return Err(alloy_rlp::Error::Custom("invalid legacy signature v")),
+                };
+                Transaction {
+                    essence: TxEssence::Legacy(essence),
+                    signature,
+                }
+            }
+            0x01 => Transaction {
+                essence: TxEssence::Eip2930(TxEssenceEip2930 {
+                    chain_id: Decodable::decode(buf)?,
+                    nonce: Decodable::decode(buf)?,
+                    gas_price: Decodable::decode(buf)?,
+                    gas_limit: Decodable::decode(buf)?,
+                    to: Decodable::decode(buf)?,
+                    value: Decodable::decode(buf)?,
+                    data: Decodable::decode(buf)?,
+                    access_list: Decodable::decode(buf)?,
+                }),
+                signature: decode_signature(buf)?,
+            },
+            0x02 => Transaction {
+                essence: TxEssence::Eip1559(TxEssenceEip1559 {
+                    chain_id: Decodable::decode(buf)?,
+                    nonce: Decodable::decode(buf)?,
+                    max_priority_fee_per_gas: Decodable::decode(buf)?,
+                    max_fee_per_gas: Decodable::decode(buf)?,
+                    gas_limit: Decodable::decode(buf)?,
+                    to: Decodable::decode(buf)?,
+                    value: Decodable::decode(buf)?,
+                    data: Decodable::decode(buf)?,
+                    access_list: Decodable::decode(buf)?,
+                }),
+                signature: decode_signature(buf)?,
+            },
+            _ => return Err(alloy_rlp::Error::Custom("unsupported transaction type")),
+        };
+        if !buf.is_empty() {
+            return Err(alloy_rlp::Error::UnexpectedLength);
+        }
+
+        Ok(transaction)
+    }
+}
+
 impl Transaction {
     /// Calculates the Keccak hash of the RLP-encoded transaction.
     ///
@@ -457,6 +575,16 @@ impl Transaction {
         }
     }
 
+    /// Retrieves the chain ID the transaction is signed for, which is `None` for legacy
+    /// transactions predating [EIP-155](https://eips.ethereum.org/EIPS/eip-155).
+    pub fn chain_id(&self) -> Option<ChainId> {
+        match &self.essence {
+            TxEssence::Legacy(tx) => tx.chain_id,
+            TxEssence::Eip2930(tx) => Some(tx.chain_id),
+            TxEssence::Eip1559(tx) => Some(tx.chain_id),
+        }
+    }
+
     /// Retrieves the recipient address of the transaction, if available.
     ///
     /// For contract creation transactions, this method returns `None` as there's no
@@ -511,6 +639,31 @@ fn rlp_join_lists(a: impl Encodable, b: impl Encodable, out: &mut dyn alloy_rlp:
     out.put_slice(&b_buf[b_head_length..]); // skip the header
 }
 
+/// Reads the header of an RLP list from `buf` and returns its payload.
+///
+/// The buffer is advanced past the entire list.
+pub(crate) fn rlp_list_payload<'a>(buf: &mut &'a [u8]) -> alloy_rlp::Result<&'a [u8]> {
+    let header = alloy_rlp::Header::decode(buf)?;
+    if !header.list {
+        return Err(alloy_rlp::Error::UnexpectedString);
+    }
+    if buf.len() < header.payload_length {
+        return Err(alloy_rlp::Error::InputTooShort);
+    }
+    let (payload, rest) = buf.split_at(header.payload_length);
+    *buf = rest;
+    Ok(payload)
+}
+
+/// Decodes the fields of a [TxSignature] that trail the fields of the essence.
+fn decode_signature(buf: &mut &[u8]) -> alloy_rlp::Result<TxSignature> {
+    Ok(TxSignature {
+        v: Decodable::decode(buf)?,
+        r: Decodable::decode(buf)?,
+        s: Decodable::decode(buf)?,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json::json;
@@ -543,6 +696,9 @@ mod tests {
         // verify that bincode serialization works
         let _: Transaction =
             bincode::deserialize(&bincode::serialize(&transaction).unwrap()).unwrap();
+        // verify that RLP decoding restores the transaction
+        let encoded = alloy_rlp::encode(&transaction);
+        assert_eq!(Transaction::decode(&mut &encoded[..]).unwrap(), transaction);
 
         assert_eq!(
             "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060",
@@ -582,6 +738,9 @@ mod tests {
         // verify that bincode serialization works
         let _: Transaction =
             bincode::deserialize(&bincode::serialize(&transaction).unwrap()).unwrap();
+        // verify that RLP decoding restores the transaction
+        let encoded = alloy_rlp::encode(&transaction);
+        assert_eq!(Transaction::decode(&mut &encoded[..]).unwrap(), transaction);
 
         assert_eq!(
             "0x4540eb9c46b1654c26353ac3c65e56451f711926982ce1b02f15c50e7459caf7",
@@ -658,6 +817,9 @@ mod tests {
         // verify that bincode serialization works
         let _: Transaction =
             bincode::deserialize(&bincode::serialize(&transaction).unwrap()).unwrap();
+        // verify that RLP decoding restores the transaction
+        let encoded = alloy_rlp::encode(&transaction);
+        assert_eq!(Transaction::decode(&mut &encoded[..]).unwrap(), transaction);
 
         assert_eq!(
             "0xbe4ef1a2244e99b1ef518aec10763b61360be22e3b649dcdf804103719b1faef",
@@ -699,6 +861,9 @@ mod tests {
         // verify that bincode serialization works
         let _: Transaction =
             bincode::deserialize(&bincode::serialize(&transaction).unwrap()).unwrap();
+        // verify that RLP decoding restores the transaction
+        let encoded = alloy_rlp::encode(&transaction);
+        assert_eq!(Transaction::decode(&mut &encoded[..]).unwrap(), transaction);
 
         assert_eq!(
             "0x2bcdc03343ca9c050f8dfd3c87f32db718c762ae889f56762d8d8bdb7c5d69ff",
@@ -754,6 +919,7 @@ mod tests {
 
         let encoded = alloy_rlp::encode(&transaction);
         assert_eq!(encoded.len(), transaction.length());
+        assert_eq!(Transaction::decode(&mut &encoded[..]).unwrap(), transaction);
 
         assert_eq!(
             "0x275631a3549307b2e8c93b18dfcc0fe8aedf0276bb650c28eaa0a8a011d18867",
@@ -0,0 +1,317 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Construction of transaction essences, and of signed transactions.
+
+use alloy_primitives::{Bytes, ChainId, TxNumber, U256};
+use anyhow::{ensure, Context};
+
+use crate::{
+    access_list::AccessList,
+    signature::SigningKey,
+    transaction::{
+        Transaction, TransactionKind, TxEssence, TxEssenceEip1559, TxEssenceEip2930,
+        TxEssenceLegacy,
+    },
+};
+
+/// The type of transaction a [TransactionBuilder] builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TxType {
+    Legacy,
+    Eip2930,
+    Eip1559,
+}
+
+/// Builds a [TxEssence] of a given type, or a [Transaction] signed with a secret key.
+///
+/// Fields that are not set default to zero, empty or contract creation. Setting a field
+/// the transaction type does not have, like the access list of a legacy transaction,
+/// makes [TransactionBuilder::build] fail.
+#[derive(Debug, Clone)]
+pub struct TransactionBuilder {
+    tx_type: TxType,
+    chain_id: Option<ChainId>,
+    nonce: TxNumber,
+    gas_price: Option<U256>,
+    max_priority_fee_per_gas: Option<U256>,
+    max_fee_per_gas: Option<U256>,
+    gas_limit: U256,
+    to: TransactionKind,
+    value: U256,
+    data: Bytes,
+    access_list: Option<AccessList>,
+}
+
+impl TransactionBuilder {
+    fn new(tx_type: TxType) -> Self {
+        TransactionBuilder {
+            tx_type,
+            chain_id: None,
+            nonce: 0,
+            gas_price: None,
+            max_priority_fee_per_gas: None,
+            max_fee_per_gas: None,
+            gas_limit: U256::ZERO,
+            to: TransactionKind::Create,
+            value: U256::ZERO,
+            data: Bytes::new(),
+            access_list: None,
+        }
+    }
+
+    /// Starts a legacy transaction, which is protected by
+    /// [EIP-155](https://eips.ethereum.org/EIPS/eip-155) if a chain ID is set.
+    pub fn legacy() -> Self {
+        Self::new(TxType::Legacy)
+    }
+
+    /// Starts an [EIP-2930](https://eips.ethereum.org/EIPS/eip-2930) transaction, which
+    /// requires a chain ID.
+    pub fn eip2930() -> Self {
+        Self::new(TxType::Eip2930)
+    }
+
+    /// Starts an [EIP-1559](https://eips.ethereum.org/EIPS/eip-1559) transaction, which
+    /// requires a chain ID.
+    pub fn eip1559() -> Self {
+        Self::new(TxType::Eip1559)
+    }
+
+    pub fn chain_id(mut self, chain_id: ChainId) -> Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+
+    pub fn nonce(mut self, nonce: TxNumber) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    /// Sets the gas price of a legacy or EIP-2930 transaction.
+    pub fn gas_price(mut self, gas_price: U256) -> Self {
+        self.gas_price = Some(gas_price);
+        self
+    }
+
+    /// Sets the maximum priority fee per gas of an EIP-1559 transaction.
+    pub fn max_priority_fee_per_gas(mut self, max_priority_fee_per_gas: U256) -> Self {
+        self.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+        self
+    }
+
+    /// Sets the maximum fee per gas of an EIP-1559 transaction.
+    pub fn max_fee_per_gas(mut self, max_fee_per_gas: U256) -> Self {
+        self.max_fee_per_gas = Some(max_fee_per_gas);
+        self
+    }
+
+    pub fn gas_limit(mut self, gas_limit: U256) -> Self {
+        self.gas_limit = gas_limit;
+        self
+    }
+
+    /// Sets the recipient, either an address or [TransactionKind::Create].
+    pub fn to(mut self, to: impl Into<TransactionKind>) -> Self {
+        self.to = to.into();
+        self
+    }
+
+    pub fn value(mut self, value: U256) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn data(mut self, data: impl Into<Bytes>) -> Self {
+        self.data = data.into();
+        self
+    }
+
+    /// Sets the access list of an EIP-2930 or EIP-1559 transaction.
+    pub fn access_list(mut self, access_list: AccessList) -> Self {
+        self.access_list = Some(access_list);
+        self
+    }
+
+    /// Builds the transaction essence, failing if a field was set that the transaction
+    /// type does not have, or if a required chain ID is missing.
+    pub fn build(self) -> anyhow::Result<TxEssence> {
+        let essence = match self.tx_type {
+            TxType::Legacy => {
+                ensure!(
+                    self.max_priority_fee_per_gas.is_none() && self.max_fee_per_gas.is_none(),
+                    "legacy transactions have no EIP-1559 fees"
+                );
+                ensure!(
+                    self.access_list.is_none(),
+                    "legacy transactions have no access list"
+                );
+                TxEssence::Legacy(TxEssenceLegacy {
+                    chain_id: self.chain_id,
+                    nonce: self.nonce,
+                    gas_price: self.gas_price.unwrap_or_default(),
+                    gas_limit: self.gas_limit,
+                    to: self.to,
+                    value: self.value,
+                    data: self.data,
+                })
+            }
+            TxType::Eip2930 => {
+                ensure!(
+                    self.max_priority_fee_per_gas.is_none() && self.max_fee_per_gas.is_none(),
+                    "EIP-2930 transactions have no EIP-1559 fees"
+                );
+                TxEssence::Eip2930(TxEssenceEip2930 {
+                    chain_id: self
+                        .chain_id
+                        .context("EIP-2930 transactions require a chain ID")?,
+                    nonce: self.nonce,
+                    gas_price: self.gas_price.unwrap_or_default(),
+                    gas_limit: self.gas_limit,
+                    to: self.to,
+                    value: self.value,
+                    data: self.data,
+                    access_list: self.access_list.unwrap_or_default(),
+                })
+            }
+            TxType::Eip1559 => {
+                ensure!(
+                    self.gas_price.is_none(),
+                    "EIP-1559 transactions have no gas price"
+                );
+                TxEssence::Eip1559(TxEssenceEip1559 {
+                    chain_id: self
+                        .chain_id
+                        .context("EIP-1559 transactions require a chain ID")?,
+                    nonce: self.nonce,
+                    max_priority_fee_per_gas: self.max_priority_fee_per_gas.unwrap_or_default(),
+                    max_fee_per_gas: self.max_fee_per_gas.unwrap_or_default(),
+                    gas_limit: self.gas_limit,
+                    to: self.to,
+                    value: self.value,
+                    data: self.data,
+                    access_list: self.access_list.unwrap_or_default(),
+                })
+            }
+        };
+        Ok(essence)
+    }
+
+    /// Builds the transaction essence and signs it with the given secret key, see
+    /// [TxEssence::sign].
+    pub fn sign(self, key: &SigningKey) -> anyhow::Result<Transaction> {
+        self.build()?.sign(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{b256, B160, B256};
+    use alloy_rlp::Decodable;
+    use hex_literal::hex;
+
+    use super::*;
+    use crate::access_list::AccessListItem;
+
+    fn key() -> SigningKey {
+        SigningKey::from_slice(&[0x46; 32]).unwrap()
+    }
+
+    /// Address belonging to [key].
+    const SENDER: [u8; 20] = hex!("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f");
+
+    #[test]
+    fn eip155() {
+        // the example of EIP-155
+        let tx = TransactionBuilder::legacy()
+            .chain_id(1)
+            .nonce(9)
+            .gas_price(U256::from(20_000_000_000u64))
+            .gas_limit(U256::from(21_000))
+            .to(B160::repeat_byte(0x35))
+            .value(U256::from(1_000_000_000_000_000_000u64))
+            .sign(&key())
+            .unwrap();
+
+        assert_eq!(tx.signature.v, 37);
+        assert_eq!(
+            alloy_rlp::encode(&tx),
+            hex!("f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83")
+        );
+        assert_eq!(
+            tx.hash(),
+            b256!("33469b22e9f636356c4160a87eb19df52b7412e8eac32a4a55ffe88ea8350788")
+        );
+        assert_eq!(tx.recover_from().unwrap(), B160::from(SENDER));
+    }
+
+    #[test]
+    fn sign_recover() {
+        let access_list = AccessList(vec![AccessListItem {
+            address: B160::repeat_byte(0x01),
+            storage_keys: vec![B256::repeat_byte(0x02)],
+        }]);
+        let builders = [
+            TransactionBuilder::legacy().gas_price(U256::from(1)),
+            TransactionBuilder::eip2930()
+                .chain_id(5)
+                .gas_price(U256::from(1))
+                .access_list(access_list.clone()),
+            TransactionBuilder::eip1559()
+                .chain_id(5)
+                .max_priority_fee_per_gas(U256::from(1))
+                .max_fee_per_gas(U256::from(2))
+                .access_list(access_list),
+        ];
+        for builder in builders {
+            let tx = builder
+                .nonce(1)
+                .gas_limit(U256::from(100_000))
+                .data(vec![0xde, 0xad])
+                .sign(&key())
+                .unwrap();
+            assert_eq!(tx.recover_from().unwrap(), B160::from(SENDER));
+
+            match &tx.essence {
+                TxEssence::Legacy(_) => assert!(tx.signature.v == 27 || tx.signature.v == 28),
+                _ => assert!(tx.signature.v <= 1),
+            }
+
+            // the signed transaction round-trips through its encoding and hash
+            let encoded = alloy_rlp::encode(&tx);
+            let decoded = Transaction::decode(&mut &encoded[..]).unwrap();
+            assert_eq!(decoded, tx);
+            assert_eq!(decoded.hash(), tx.hash());
+        }
+    }
+
+    #[test]
+    fn build_errors() {
+        assert!(TransactionBuilder::legacy()
+            .access_list(AccessList::default())
+            .build()
+            .is_err());
+        assert!(TransactionBuilder::legacy()
+            .max_fee_per_gas(U256::from(1))
+            .build()
+            .is_err());
+        assert!(TransactionBuilder::eip2930().build().is_err());
+        assert!(TransactionBuilder::eip1559().build().is_err());
+        assert!(TransactionBuilder::eip1559()
+            .chain_id(1)
+            .gas_price(U256::from(1))
+            .build()
+            .is_err());
+    }
+}
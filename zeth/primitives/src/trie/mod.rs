@@ -14,6 +14,7 @@
 
 pub mod account;
 pub mod mpt;
+pub mod table;
 
 use alloy_primitives::{b256, B256};
 
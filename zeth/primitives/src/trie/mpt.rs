@@ -15,9 +15,15 @@
 extern crate alloc;
 
 use alloc::boxed::Box;
-use core::{cell::RefCell, cmp, fmt::Debug, iter, mem};
-
-use alloy_primitives::B256;
+use core::{
+    cell::RefCell,
+    cmp,
+    fmt::Debug,
+    iter, mem,
+    ops::{Bound, RangeBounds},
+};
+
+use alloy_primitives::{Bytes, B256};
 use alloy_rlp::Encodable;
 use rlp::{Decodable, DecoderError, Prototype, Rlp};
 use serde::{Deserialize, Serialize};
@@ -42,6 +48,11 @@ pub struct MptNode {
     cached_reference: RefCell<Option<MptNodeReference>>,
 }
 
+/// Number of branch levels whose children are hashed in parallel by
+/// [MptNode::par_hash], which yields up to 256 tasks.
+#[cfg(feature = "rayon")]
+const PAR_HASH_DEPTH: usize = 2;
+
 /// Represents custom error types for the sparse Merkle Patricia Trie (MPT).
 ///
 /// These errors cover various scenarios that can occur during trie operations, such as
@@ -56,6 +67,12 @@ pub enum Error {
     /// Occurs when a value is unexpectedly found in a branch node.
     #[error("branch node with value")]
     ValueInBranch,
+    /// Occurs when a node of a proof does not match the hash it is referenced by.
+    #[error("proof node does not match the hash {0:#}")]
+    ProofMismatch(B256),
+    /// Occurs when a [NodeTable](super::table::NodeTable) is malformed.
+    #[error("invalid node table")]
+    InvalidNodeTable,
     /// Represents errors related to the RLP encoding and decoding using the `alloy_rlp`
     /// library.
     #[error("RLP error")]
@@ -266,6 +283,11 @@ impl MptNode {
         &self.data
     }
 
+    /// Consumes the node and returns its data.
+    pub fn into_data(self) -> MptNodeData {
+        self.data
+    }
+
     /// Computes and returns the 256-bit hash of the node.
     ///
     /// This method provides a unique identifier for the node based on its content.
@@ -279,6 +301,39 @@ impl MptNode {
         }
     }
 
+    /// Computes and returns the 256-bit hash of the node, like [MptNode::hash], but
+    /// hashes the subtries below the top branches in parallel.
+    ///
+    /// As with [MptNode::hash], only the nodes invalidated since the last hash are
+    /// visited.
+    #[cfg(feature = "rayon")]
+    pub fn par_hash(&mut self) -> B256 {
+        self.par_cache_reference(PAR_HASH_DEPTH);
+        self.hash()
+    }
+
+    /// Caches the reference of the node, computing the references of the children in
+    /// parallel for the given number of branch levels.
+    #[cfg(feature = "rayon")]
+    fn par_cache_reference(&mut self, depth: usize) {
+        use rayon::prelude::*;
+
+        if self.cached_reference.get_mut().is_some() {
+            return;
+        }
+        if depth > 0 {
+            match &mut self.data {
+                MptNodeData::Branch(children) => children
+                    .par_iter_mut()
+                    .flatten()
+                    .for_each(|child| child.par_cache_reference(depth - 1)),
+                MptNodeData::Extension(_, child) => child.par_cache_reference(depth),
+                _ => {}
+            }
+        }
+        self.reference();
+    }
+
     /// Retrieves the [MptNodeReference] reference of the node when it's referenced inside
     /// another node.
     ///
@@ -394,6 +449,48 @@ impl MptNode {
         }
     }
 
+    /// Returns the Merkle proof for the given key.
+    ///
+    /// The proof follows [EIP-1186](https://eips.ethereum.org/EIPS/eip-1186): it contains
+    /// the RLP encoding of each node along the path of the key, starting with the root.
+    /// Nodes shorter than 32 bytes are embedded in their parent and not listed
+    /// separately. If the key is not in the trie, the proof ends with the node where its
+    /// path diverges, making it an exclusion proof. All nodes along the path must be
+    /// resolved.
+    pub fn prove(&self, key: &[u8]) -> Result<Vec<Bytes>, Error> {
+        let mut proof = Vec::new();
+        self.prove_internal(&to_nibs(key), true, &mut proof)?;
+        Ok(proof)
+    }
+
+    fn prove_internal(
+        &self,
+        key_nibs: &[u8],
+        is_root: bool,
+        proof: &mut Vec<Bytes>,
+    ) -> Result<(), Error> {
+        let child = match &self.data {
+            MptNodeData::Null => return Ok(()),
+            MptNodeData::Branch(children) => key_nibs
+                .split_first()
+                .and_then(|(nib, tail)| Some((children[*nib as usize].as_ref()?, tail))),
+            MptNodeData::Leaf(_, _) => None,
+            MptNodeData::Extension(_, child) => key_nibs
+                .strip_prefix(self.nibs().as_slice())
+                .map(|tail| (child, tail)),
+            MptNodeData::Digest(digest) => return Err(Error::NodeNotResolved(*digest)),
+        };
+
+        // only the root and nodes referenced by their hash are part of the proof
+        if is_root || matches!(self.reference(), MptNodeReference::Digest(_)) {
+            proof.push(self.to_rlp().into());
+        }
+        match child {
+            Some((child, tail)) => child.prove_internal(tail, false, proof),
+            None => Ok(()),
+        }
+    }
+
     fn get_internal(&self, key_nibs: &[u8]) -> Result<Option<&[u8]>, Error> {
         match &self.data {
             MptNodeData::Null => Ok(None),
@@ -679,6 +776,71 @@ impl MptNode {
         self.cached_reference.borrow_mut().take();
     }
 
+    /// Returns an iterator over the entries of the trie, in ascending order of their
+    /// keys.
+    ///
+    /// Unresolved subtries are returned as [MptEntry::Digest], as their keys are not
+    /// known.
+    pub fn iter(&self) -> MptIter<'_> {
+        MptIter {
+            stack: vec![(Vec::new(), self)],
+            start: Bound::Unbounded,
+            end: Bound::Unbounded,
+        }
+    }
+
+    /// Returns an iterator over the entries of the trie whose keys are in the given
+    /// range, in ascending order of their keys.
+    ///
+    /// Unresolved subtries that may contain keys of the range are returned as
+    /// [MptEntry::Digest]. Subtries outside of the range are not traversed.
+    pub fn range<K: AsRef<[u8]>>(&self, range: impl RangeBounds<K>) -> MptIter<'_> {
+        MptIter {
+            stack: vec![(Vec::new(), self)],
+            start: to_nibs_bound(range.start_bound()),
+            end: to_nibs_bound(range.end_bound()),
+        }
+    }
+
+    /// Returns the differences from this trie to the `other` trie.
+    ///
+    /// Subtries that differ but are unresolved in either trie are returned as
+    /// [MptDiff::Unresolved], as their keys are not known.
+    pub fn diff(&self, other: &MptNode) -> Vec<MptDiff<Vec<u8>>> {
+        let mut diffs = Vec::new();
+        diff_internal(self, other, Vec::new(), &mut diffs);
+        diffs
+    }
+
+    /// Returns the differences from this trie to the `other` trie, with RLP-decoded
+    /// values.
+    pub fn diff_rlp<T: alloy_rlp::Decodable>(
+        &self,
+        other: &MptNode,
+    ) -> Result<Vec<MptDiff<T>>, Error> {
+        let decode = |value: Vec<u8>| T::decode(&mut value.as_slice());
+        let mut diffs = Vec::new();
+        for diff in self.diff(other) {
+            diffs.push(match diff {
+                MptDiff::Added { key, value } => MptDiff::Added {
+                    key,
+                    value: decode(value)?,
+                },
+                MptDiff::Removed { key, value } => MptDiff::Removed {
+                    key,
+                    value: decode(value)?,
+                },
+                MptDiff::Changed { key, old, new } => MptDiff::Changed {
+                    key,
+                    old: decode(old)?,
+                    new: decode(new)?,
+                },
+                MptDiff::Unresolved { prefix } => MptDiff::Unresolved { prefix },
+            });
+        }
+        Ok(diffs)
+    }
+
     /// Returns the number of traversable nodes in the trie.
     ///
     /// This method provides a count of all the nodes that can be traversed within the
@@ -695,6 +857,42 @@ impl MptNode {
         }
     }
 
+    /// Returns the nibble paths of the nodes whose reference has been invalidated since
+    /// it was last computed, parents before their children.
+    ///
+    /// These are the nodes the next call to [MptNode::hash] encodes again; all other
+    /// subtries keep their cached references. Unresolved digests are never reported.
+    pub fn invalidated(&self) -> Vec<Vec<u8>> {
+        let mut paths = Vec::new();
+        self.invalidated_internal(&mut Vec::new(), &mut paths);
+        paths
+    }
+
+    fn invalidated_internal(&self, path: &mut Vec<u8>, paths: &mut Vec<Vec<u8>>) {
+        if self.is_digest() || self.cached_reference.borrow().is_some() {
+            return;
+        }
+        paths.push(path.clone());
+        match &self.data {
+            MptNodeData::Branch(children) => {
+                for (i, child) in children.iter().enumerate() {
+                    if let Some(child) = child {
+                        path.push(i as u8);
+                        child.invalidated_internal(path, paths);
+                        path.pop();
+                    }
+                }
+            }
+            MptNodeData::Extension(_, child) => {
+                let len = path.len();
+                path.extend(self.nibs());
+                child.invalidated_internal(path, paths);
+                path.truncate(len);
+            }
+            _ => {}
+        }
+    }
+
     /// Formats the trie as a string list, where each line corresponds to a trie leaf.
     ///
     /// This method is primarily used for debugging purposes, providing a visual
@@ -752,6 +950,364 @@ impl MptNode {
     }
 }
 
+/// Builds a trie from key-value pairs, which is much faster than inserting them one by
+/// one.
+///
+/// The pairs are sorted by key first, which is cheap if they are already sorted. If a
+/// key occurs more than once, its last value is used.
+///
+/// # Panics
+///
+/// Panics if a value is empty or if a key is a prefix of another key, as
+/// [MptNode::insert] would fail for these.
+impl<K: AsRef<[u8]>> FromIterator<(K, Vec<u8>)> for MptNode {
+    fn from_iter<T: IntoIterator<Item = (K, Vec<u8>)>>(iter: T) -> Self {
+        let mut entries: Vec<_> = iter
+            .into_iter()
+            .map(|(key, value)| (to_nibs(key.as_ref()), value))
+            .collect();
+        // the sort is stable, so the last value of each key is the last of its run
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut unique: Vec<(Vec<u8>, Vec<u8>)> = Vec::with_capacity(entries.len());
+        for (key_nibs, value) in entries {
+            assert!(!value.is_empty(), "value must not be empty");
+            match unique.last_mut() {
+                Some(last) if last.0 == key_nibs => last.1 = value,
+                _ => unique.push((key_nibs, value)),
+            }
+        }
+
+        build_sorted(&mut unique, 0)
+    }
+}
+
+/// Builds the trie of entries sorted by their unique key nibbles, ignoring the first
+/// `depth` nibbles of each key.
+fn build_sorted(entries: &mut [(Vec<u8>, Vec<u8>)], depth: usize) -> MptNode {
+    let common_len = match entries {
+        [] => return MptNodeData::Null.into(),
+        [(key_nibs, value)] => {
+            return MptNodeData::Leaf(to_encoded_path(&key_nibs[depth..], true), mem::take(value))
+                .into()
+        }
+        // as the keys are sorted, all of them share the prefix of the first and the last
+        [(first, _), .., (last, _)] => lcp(&first[depth..], &last[depth..]),
+    };
+    if common_len > 0 {
+        let prefix = to_encoded_path(&entries[0].0[depth..depth + common_len], false);
+        let branch = build_sorted_branch(entries, depth + common_len);
+        MptNodeData::Extension(prefix, Box::new(branch)).into()
+    } else {
+        build_sorted_branch(entries, depth)
+    }
+}
+
+/// Builds a branch of entries that differ in the nibble at `depth`.
+fn build_sorted_branch(entries: &mut [(Vec<u8>, Vec<u8>)], depth: usize) -> MptNode {
+    let mut children: [Option<Box<MptNode>>; 16] = Default::default();
+    let mut entries = entries;
+    while let Some((key_nibs, _)) = entries.first() {
+        // the shortest key comes first, it must not end at the branch
+        assert!(key_nibs.len() > depth, "branch node with value");
+        let nib = key_nibs[depth];
+        let len = entries
+            .iter()
+            .position(|(key_nibs, _)| key_nibs[depth] != nib)
+            .unwrap_or(entries.len());
+        let (group, rest) = mem::take(&mut entries).split_at_mut(len);
+        children[nib as usize] = Some(Box::new(build_sorted(group, depth + 1)));
+        entries = rest;
+    }
+    MptNodeData::Branch(children).into()
+}
+
+/// A difference between two tries, see [MptNode::diff].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MptDiff<T> {
+    /// The key is only in the other trie.
+    Added { key: Vec<u8>, value: T },
+    /// The key is only in this trie.
+    Removed { key: Vec<u8>, value: T },
+    /// The key is in both tries, with different values.
+    Changed { key: Vec<u8>, old: T, new: T },
+    /// The subtries containing all keys that start with the nibbles of `prefix` differ,
+    /// but at least one of them is unresolved.
+    Unresolved { prefix: Vec<u8> },
+}
+
+/// Collects the differences between the subtries `old` and `new` at the nibbles `path`.
+fn diff_internal(old: &MptNode, new: &MptNode, path: Vec<u8>, diffs: &mut Vec<MptDiff<Vec<u8>>>) {
+    if old.hash() == new.hash() {
+        return;
+    }
+    match (&old.data, &new.data) {
+        (MptNodeData::Digest(_), _) | (_, MptNodeData::Digest(_)) => {
+            diffs.push(MptDiff::Unresolved { prefix: path });
+        }
+        // descend into matching structures, comparing only the subtries that differ
+        (MptNodeData::Branch(old_children), MptNodeData::Branch(new_children)) => {
+            let null = MptNode::default();
+            for (i, (old_child, new_child)) in iter::zip(old_children, new_children).enumerate() {
+                diff_internal(
+                    old_child.as_deref().unwrap_or(&null),
+                    new_child.as_deref().unwrap_or(&null),
+                    path.iter().copied().chain(iter::once(i as u8)).collect(),
+                    diffs,
+                );
+            }
+        }
+        (MptNodeData::Extension(_, old_child), MptNodeData::Extension(_, new_child))
+            if old.nibs() == new.nibs() =>
+        {
+            let mut child_path = path;
+            child_path.extend(old.nibs());
+            diff_internal(old_child, new_child, child_path, diffs);
+        }
+        // otherwise, compare all the entries of both subtries
+        _ => {
+            let (old_leaves, old_digests) = collect_entries(old, path.clone());
+            let (new_leaves, new_digests) = collect_entries(new, path);
+            let mut unresolved: Vec<_> = old_digests.into_iter().chain(new_digests).collect();
+            unresolved.sort();
+            unresolved.dedup();
+            let is_resolved = |(key, _): &(Vec<u8>, &[u8])| {
+                !unresolved.iter().any(|p| to_nibs(key).starts_with(p))
+            };
+
+            let mut old_leaves = old_leaves.into_iter().filter(is_resolved).peekable();
+            let mut new_leaves = new_leaves.into_iter().filter(is_resolved).peekable();
+            loop {
+                let ordering = match (old_leaves.peek(), new_leaves.peek()) {
+                    (Some((old_key, _)), Some((new_key, _))) => old_key.cmp(new_key),
+                    (Some(_), None) => cmp::Ordering::Less,
+                    (None, Some(_)) => cmp::Ordering::Greater,
+                    (None, None) => break,
+                };
+                match ordering {
+                    cmp::Ordering::Less => {
+                        let (key, value) = old_leaves.next().unwrap();
+                        diffs.push(MptDiff::Removed {
+                            key,
+                            value: value.to_vec(),
+                        });
+                    }
+                    cmp::Ordering::Greater => {
+                        let (key, value) = new_leaves.next().unwrap();
+                        diffs.push(MptDiff::Added {
+                            key,
+                            value: value.to_vec(),
+                        });
+                    }
+                    cmp::Ordering::Equal => {
+                        let (key, old) = old_leaves.next().unwrap();
+                        let (_, new) = new_leaves.next().unwrap();
+                        if old != new {
+                            diffs.push(MptDiff::Changed {
+                                key,
+                                old: old.to_vec(),
+                                new: new.to_vec(),
+                            });
+                        }
+                    }
+                }
+            }
+
+            diffs.extend(unresolved.iter().map(|prefix| MptDiff::Unresolved {
+                prefix: prefix.clone(),
+            }));
+        }
+    }
+}
+
+/// Returns the leaves and the prefixes of the unresolved subtries of the node at the
+/// nibbles `path`.
+fn collect_entries(node: &MptNode, path: Vec<u8>) -> (Vec<(Vec<u8>, &[u8])>, Vec<Vec<u8>>) {
+    let iter = MptIter {
+        stack: vec![(path, node)],
+        start: Bound::Unbounded,
+        end: Bound::Unbounded,
+    };
+    let mut leaves = Vec::new();
+    let mut digests = Vec::new();
+    for entry in iter {
+        match entry {
+            MptEntry::Leaf { key, value } => leaves.push((key, value)),
+            MptEntry::Digest { prefix, .. } => digests.push(prefix),
+        }
+    }
+    (leaves, digests)
+}
+
+/// An entry returned when iterating over an [MptNode].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MptEntry<'a> {
+    /// A key of the trie and its value.
+    Leaf { key: Vec<u8>, value: &'a [u8] },
+    /// An unresolved subtrie, containing all keys that start with the nibbles of
+    /// `prefix`.
+    Digest { prefix: Vec<u8>, digest: B256 },
+}
+
+/// An iterator over the entries of an [MptNode], see [MptNode::iter] and
+/// [MptNode::range].
+pub struct MptIter<'a> {
+    /// Nodes still to visit together with the nibbles of their path, the next on top.
+    stack: Vec<(Vec<u8>, &'a MptNode)>,
+    /// Nibbles of the start of the range.
+    start: Bound<Vec<u8>>,
+    /// Nibbles of the end of the range.
+    end: Bound<Vec<u8>>,
+}
+
+impl<'a> MptIter<'a> {
+    /// Returns whether all keys starting with the nibbles `prefix` are before the range.
+    fn before_start(&self, prefix: &[u8]) -> bool {
+        match &self.start {
+            Bound::Unbounded => false,
+            Bound::Included(start) | Bound::Excluded(start) => {
+                let len = cmp::min(prefix.len(), start.len());
+                prefix[..len] < start[..len]
+            }
+        }
+    }
+
+    /// Returns whether all keys starting with the nibbles `prefix` are after the range.
+    fn after_end(&self, prefix: &[u8]) -> bool {
+        match &self.end {
+            Bound::Unbounded => false,
+            Bound::Included(end) => {
+                let len = cmp::min(prefix.len(), end.len());
+                prefix[..len] > end[..len] || (prefix.len() > end.len() && prefix.starts_with(end))
+            }
+            Bound::Excluded(end) => {
+                let len = cmp::min(prefix.len(), end.len());
+                prefix[..len] > end[..len] || prefix.starts_with(end)
+            }
+        }
+    }
+
+    /// Returns whether the key with the given nibbles is in the range.
+    fn contains(&self, key_nibs: &[u8]) -> bool {
+        let after_start = match &self.start {
+            Bound::Unbounded => true,
+            Bound::Included(start) => key_nibs >= start.as_slice(),
+            Bound::Excluded(start) => key_nibs > start.as_slice(),
+        };
+        let before_end = match &self.end {
+            Bound::Unbounded => true,
+            Bound::Included(end) => key_nibs <= end.as_slice(),
+            Bound::Excluded(end) => key_nibs < end.as_slice(),
+        };
+        after_start && before_end
+    }
+}
+
+impl<'a> Iterator for MptIter<'a> {
+    type Item = MptEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((prefix, node)) = self.stack.pop() {
+            if self.before_start(&prefix) {
+                continue;
+            }
+            // nodes are visited in order, so all remaining nodes are after the range
+            if self.after_end(&prefix) {
+                self.stack.clear();
+                return None;
+            }
+
+            match &node.data {
+                MptNodeData::Null => {}
+                MptNodeData::Branch(children) => {
+                    for (i, child) in children.iter().enumerate().rev() {
+                        if let Some(child) = child {
+                            let child_prefix = prefix.iter().copied().chain(iter::once(i as u8));
+                            self.stack.push((child_prefix.collect(), child));
+                        }
+                    }
+                }
+                MptNodeData::Leaf(_, value) => {
+                    let mut key_nibs = prefix;
+                    key_nibs.extend(node.nibs());
+                    if self.contains(&key_nibs) {
+                        return Some(MptEntry::Leaf {
+                            key: from_nibs(&key_nibs),
+                            value,
+                        });
+                    }
+                }
+                MptNodeData::Extension(_, child) => {
+                    let mut child_prefix = prefix;
+                    child_prefix.extend(node.nibs());
+                    self.stack.push((child_prefix, child));
+                }
+                MptNodeData::Digest(digest) => {
+                    return Some(MptEntry::Digest {
+                        prefix,
+                        digest: *digest,
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Verifies a Merkle proof of `key` against the root hash of a trie.
+///
+/// The proof is expected in the format returned by [MptNode::prove], i.e. as in
+/// [EIP-1186](https://eips.ethereum.org/EIPS/eip-1186). On success, this returns the
+/// value of the key, or [None] if the proof shows that the key is not in the trie. As
+/// with [MptNode::get], the key is not hashed.
+pub fn verify_proof(
+    root: B256,
+    key: &[u8],
+    proof: &[impl AsRef<[u8]>],
+) -> Result<Option<Vec<u8>>, Error> {
+    if root == EMPTY_ROOT {
+        return Ok(None);
+    }
+
+    let key_nibs = to_nibs(key);
+    let mut key_nibs = key_nibs.as_slice();
+    let mut proof = proof.iter();
+    let mut node: MptNode = MptNodeData::Digest(root).into();
+    loop {
+        let node_nibs = node.nibs();
+        node = match node.data {
+            MptNodeData::Null => return Ok(None),
+            MptNodeData::Branch(mut children) => match key_nibs.split_first() {
+                Some((nib, tail)) => match children[*nib as usize].take() {
+                    Some(child) => {
+                        key_nibs = tail;
+                        *child
+                    }
+                    None => return Ok(None),
+                },
+                None => return Ok(None),
+            },
+            MptNodeData::Leaf(_, value) => {
+                return Ok((node_nibs == key_nibs).then_some(value));
+            }
+            MptNodeData::Extension(_, child) => match key_nibs.strip_prefix(node_nibs.as_slice()) {
+                Some(tail) => {
+                    key_nibs = tail;
+                    *child
+                }
+                None => return Ok(None),
+            },
+            // the next node must be resolved by the proof
+            MptNodeData::Digest(digest) => {
+                let bytes = proof.next().ok_or(Error::NodeNotResolved(digest))?;
+                if B256::from(keccak(bytes)) != digest {
+                    return Err(Error::ProofMismatch(digest));
+                }
+                MptNode::decode(bytes)?
+            }
+        };
+    }
+}
+
 /// Converts a byte slice into a vector of nibbles.
 ///
 /// A nibble is 4 bits or half of an 8-bit byte. This function takes each byte from the
@@ -765,6 +1321,25 @@ pub fn to_nibs(slice: &[u8]) -> Vec<u8> {
     result
 }
 
+/// Converts a slice of nibbles back into bytes, the inverse of [to_nibs].
+///
+/// The number of nibbles must be even.
+fn from_nibs(nibs: &[u8]) -> Vec<u8> {
+    debug_assert!(nibs.len() % 2 == 0);
+    nibs.chunks_exact(2)
+        .map(|byte| (byte[0] << 4) + byte[1])
+        .collect()
+}
+
+/// Converts a bound on byte keys into a bound on their nibbles.
+fn to_nibs_bound<K: AsRef<[u8]>>(bound: Bound<&K>) -> Bound<Vec<u8>> {
+    match bound {
+        Bound::Included(key) => Bound::Included(to_nibs(key.as_ref())),
+        Bound::Excluded(key) => Bound::Excluded(to_nibs(key.as_ref())),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
 /// Encodes a slice of nibbles into a vector of bytes, with an additional prefix to
 /// indicate the type of node (leaf or extension).
 ///
@@ -1051,4 +1626,324 @@ mod tests {
         }
         assert!(trie.is_empty());
     }
+
+    #[test]
+    pub fn test_proof() {
+        const N: usize = 512;
+
+        let mut trie = MptNode::default();
+        for i in 0..N {
+            trie.insert_rlp(&keccak(i.to_be_bytes()), i).unwrap();
+        }
+        let root = trie.hash();
+
+        for i in 0..N {
+            // inclusion proof
+            let key = keccak(i.to_be_bytes());
+            let proof = trie.prove(&key).unwrap();
+            assert_eq!(proof[0].as_ref(), trie.to_rlp());
+            let value = verify_proof(root, &key, &proof).unwrap();
+            assert_eq!(value, Some(i.to_rlp()));
+
+            // exclusion proof
+            let key = keccak((i + N).to_be_bytes());
+            let proof = trie.prove(&key).unwrap();
+            assert_eq!(verify_proof(root, &key, &proof).unwrap(), None);
+        }
+
+        // a proof does not verify against a different root or with a tampered node
+        let key = keccak(0usize.to_be_bytes());
+        let mut proof = trie.prove(&key).unwrap();
+        verify_proof(B256::ZERO, &key, &proof).unwrap_err();
+        let last = proof.pop().unwrap();
+        verify_proof(root, &key, &proof).unwrap_err();
+        let mut tampered = last.to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        proof.push(tampered.into());
+        verify_proof(root, &key, &proof).unwrap_err();
+    }
+
+    #[test]
+    pub fn test_proof_embedded_nodes() {
+        // short keys and values lead to leaves embedded in the root branch
+        let mut trie = MptNode::default();
+        for i in 0..16u8 {
+            trie.insert(&[i << 4], vec![i + 1]).unwrap();
+        }
+        let root = trie.hash();
+
+        for i in 0..16u8 {
+            let proof = trie.prove(&[i << 4]).unwrap();
+            assert_eq!(proof.len(), 1);
+            assert_eq!(
+                verify_proof(root, &[i << 4], &proof).unwrap(),
+                Some(vec![i + 1])
+            );
+        }
+        let proof = trie.prove(&[0x01]).unwrap();
+        assert_eq!(verify_proof(root, &[0x01], &proof).unwrap(), None);
+    }
+
+    #[test]
+    pub fn test_proof_empty() {
+        let trie = MptNode::default();
+        let proof = trie.prove(b"dog").unwrap();
+        assert!(proof.is_empty());
+        assert_eq!(verify_proof(EMPTY_ROOT, b"dog", &proof).unwrap(), None);
+
+        // unresolved nodes cannot be proven
+        let trie: MptNode = MptNodeData::Digest(B256::ZERO).into();
+        trie.prove(b"dog").unwrap_err();
+    }
+
+    #[test]
+    pub fn test_from_iter() {
+        const N: usize = 512;
+
+        // keys of a transaction trie, in order of the transactions
+        let mut reference = MptNode::default();
+        for i in 0..N {
+            reference.insert_rlp(&i.to_rlp(), i).unwrap();
+        }
+        let trie: MptNode = (0..N).map(|i| (i.to_rlp(), i.to_rlp())).collect();
+        assert_eq!(trie.hash(), reference.hash());
+
+        // sorted keys, where the last of duplicate values is used
+        let mut reference = MptNode::default();
+        let mut entries = Vec::new();
+        for i in 0..N {
+            let key = keccak(i.to_be_bytes());
+            reference.insert_rlp(&key, i).unwrap();
+            entries.push((key, (i + 1).to_rlp()));
+            entries.push((key, i.to_rlp()));
+        }
+        entries.sort_by_key(|(key, _)| *key);
+        let trie: MptNode = entries.into_iter().collect();
+        assert_eq!(trie.hash(), reference.hash());
+
+        let trie: MptNode = iter::empty::<(Vec<u8>, Vec<u8>)>().collect();
+        assert_eq!(trie.hash(), EMPTY_ROOT);
+    }
+
+    #[test]
+    pub fn test_iter() {
+        const N: usize = 512;
+
+        let trie: MptNode = (0..N)
+            .map(|i| (keccak(i.to_be_bytes()), i.to_rlp()))
+            .collect();
+        let mut expected: Vec<_> = (0..N)
+            .map(|i| (keccak(i.to_be_bytes()).to_vec(), i.to_rlp()))
+            .collect();
+        expected.sort();
+
+        let entries: Vec<_> = trie
+            .iter()
+            .map(|entry| match entry {
+                MptEntry::Leaf { key, value } => (key, value.to_vec()),
+                MptEntry::Digest { .. } => panic!("unexpected digest"),
+            })
+            .collect();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    pub fn test_iter_digest() {
+        let mut trie = MptNode::default();
+        for key in [0x00, 0x10, 0x11, 0x20] {
+            trie.insert(&[key], vec![key + 1]).unwrap();
+        }
+        // replace the subtrie of the keys starting with nibble 1 by its digest
+        let digest = match &mut trie.data {
+            MptNodeData::Branch(children) => {
+                let child = children[1].as_mut().unwrap();
+                let digest = child.hash();
+                **child = MptNodeData::Digest(digest).into();
+                digest
+            }
+            _ => unreachable!(),
+        };
+        trie.invalidate_ref_cache();
+
+        let entries: Vec<_> = trie.iter().collect();
+        assert_eq!(
+            entries,
+            vec![
+                MptEntry::Leaf {
+                    key: vec![0x00],
+                    value: &[0x01]
+                },
+                MptEntry::Digest {
+                    prefix: vec![0x1],
+                    digest
+                },
+                MptEntry::Leaf {
+                    key: vec![0x20],
+                    value: &[0x21]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_range() {
+        let trie: MptNode = (0..=255u8).map(|i| ([i], vec![i, 1])).collect();
+        let keys = |iter: MptIter| -> Vec<u8> {
+            iter.map(|entry| match entry {
+                MptEntry::Leaf { key, .. } => key[0],
+                MptEntry::Digest { .. } => panic!("unexpected digest"),
+            })
+            .collect()
+        };
+
+        assert_eq!(keys(trie.range([16]..[32])), (16..32).collect::<Vec<_>>());
+        assert_eq!(keys(trie.range([16]..=[32])), (16..=32).collect::<Vec<_>>());
+        assert_eq!(keys(trie.range(..[8])), (0..8).collect::<Vec<_>>());
+        assert_eq!(keys(trie.range([250]..)), (250..=255).collect::<Vec<_>>());
+        assert!(keys(trie.range([32]..[32])).is_empty());
+        assert_eq!(keys(trie.iter()), (0..=255).collect::<Vec<_>>());
+
+        // the bounds do not need to be keys of the trie
+        assert_eq!(keys(trie.range([16, 0]..[17, 0])), vec![17]);
+    }
+
+    #[test]
+    pub fn test_diff() {
+        const N: usize = 512;
+
+        let old: MptNode = (0..N)
+            .map(|i| (keccak(i.to_be_bytes()), i.to_rlp()))
+            .collect();
+        assert!(old.diff(&old).is_empty());
+
+        let mut new = old.clone();
+        new.delete(&keccak(0usize.to_be_bytes())).unwrap();
+        new.insert_rlp(&keccak(1usize.to_be_bytes()), N + 1)
+            .unwrap();
+        new.insert_rlp(&keccak(N.to_be_bytes()), N).unwrap();
+
+        let mut diffs = old.diff_rlp::<usize>(&new).unwrap();
+        assert_eq!(diffs.len(), 3);
+        for expected in [
+            MptDiff::Removed {
+                key: keccak(0usize.to_be_bytes()).to_vec(),
+                value: 0,
+            },
+            MptDiff::Changed {
+                key: keccak(1usize.to_be_bytes()).to_vec(),
+                old: 1,
+                new: N + 1,
+            },
+            MptDiff::Added {
+                key: keccak(N.to_be_bytes()).to_vec(),
+                value: N,
+            },
+        ] {
+            assert!(diffs.contains(&expected), "missing {:?}", expected);
+        }
+
+        // the reverse diff swaps added and removed keys
+        diffs = new.diff_rlp::<usize>(&old).unwrap();
+        assert!(diffs.contains(&MptDiff::Added {
+            key: keccak(0usize.to_be_bytes()).to_vec(),
+            value: 0,
+        }));
+
+        // against an empty trie, every key is removed
+        let diffs = old.diff(&MptNode::default());
+        assert_eq!(diffs.len(), N);
+        assert!(diffs
+            .iter()
+            .all(|diff| matches!(diff, MptDiff::Removed { .. })));
+    }
+
+    #[test]
+    pub fn test_diff_unresolved() {
+        let value = |i: u8| vec![i; 32];
+        let old: MptNode = [0x00, 0x10, 0x11, 0x20]
+            .into_iter()
+            .map(|key| ([key], value(key)))
+            .collect();
+        let mut new: MptNode = [0x00, 0x10, 0x11, 0x21]
+            .into_iter()
+            .map(|key| ([key], value(key)))
+            .collect();
+
+        // replace the subtrie of the keys starting with nibble 1 by its digest
+        match &mut new.data {
+            MptNodeData::Branch(children) => {
+                let child = children[1].as_mut().unwrap();
+                **child = MptNodeData::Digest(child.hash()).into();
+            }
+            _ => unreachable!(),
+        };
+        new.invalidate_ref_cache();
+        // unchanged subtries are skipped, even if they are unresolved
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                MptDiff::Removed {
+                    key: vec![0x20],
+                    value: value(0x20)
+                },
+                MptDiff::Added {
+                    key: vec![0x21],
+                    value: value(0x21)
+                },
+            ]
+        );
+
+        // changed subtries behind a digest cannot be compared
+        let mut changed = old.clone();
+        changed.insert(&[0x11], value(0xff)).unwrap();
+        assert_eq!(
+            changed.diff(&new)[0],
+            MptDiff::Unresolved { prefix: vec![0x1] }
+        );
+    }
+
+    #[test]
+    pub fn test_invalidated() {
+        let mut trie = MptNode::default();
+        for i in 0..16u8 {
+            trie.insert(&[i << 4, 0x00], vec![i; 40]).unwrap();
+            trie.insert(&[i << 4, 0x01], vec![i; 40]).unwrap();
+        }
+        // every node is invalidated before the first hash
+        assert_eq!(trie.invalidated().len(), trie.size());
+        trie.hash();
+        assert!(trie.invalidated().is_empty());
+
+        // only the path to the updated key is hashed again
+        trie.insert(&[0x30, 0x01], vec![0xff; 40]).unwrap();
+        assert_eq!(
+            trie.invalidated(),
+            vec![
+                vec![],
+                vec![0x3],
+                vec![0x3, 0x0, 0x0],
+                vec![0x3, 0x0, 0x0, 0x1]
+            ]
+        );
+        trie.hash();
+        assert!(trie.invalidated().is_empty());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    pub fn test_par_hash() {
+        let mut trie = MptNode::default();
+        for i in 0..512usize {
+            trie.insert_rlp(&keccak(i.to_be_bytes()), i).unwrap();
+        }
+        let expected = trie.clone().hash();
+        assert_eq!(trie.par_hash(), expected);
+        assert!(trie.invalidated().is_empty());
+
+        // after an update, the parallel hash matches the sequential one again
+        trie.insert_rlp(&keccak(0usize.to_be_bytes()), 512usize)
+            .unwrap();
+        let expected = trie.clone().hash();
+        assert_eq!(trie.par_hash(), expected);
+    }
 }
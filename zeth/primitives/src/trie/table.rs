@@ -0,0 +1,515 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Flat node-table encoding of an [MptNode].
+//!
+//! The derived serialization of [MptNode] writes the trie as a tree of enums, so every
+//! node costs a tag per child and identical subtries are written once per occurrence. A
+//! [NodeTable] instead stores every node referenced by its digest once, in its RLP form,
+//! and replaces the references to it by an index into the table. Embedded nodes stay
+//! inside the RLP of their parent.
+//!
+//! The table is the RLP list of the links, followed by the RLP list of the nodes. Every
+//! node comes after all nodes it links to, so the root is the last one. For each node,
+//! in order, and for each child referenced by its digest, in order, the links contain
+//! the index of that child plus one, or zero if the child is left unresolved.
+//!
+//! The digests inside the nodes are not checked when decoding, as they are replaced by
+//! the linked nodes anyway: the hash of the decoded trie is always computed from its
+//! content.
+//!
+//! Nodes linked more than once are copied for every further link. As a few links can
+//! expand to exponentially many nodes, the decoded trie is limited to `MAX_EXPANSION`
+//! linked nodes per byte of the table.
+//!
+//! [with_tree_encoding] switches the serialization back to the tree, to compare both
+//! encodings of the same value.
+
+use std::{cell::Cell, collections::HashMap};
+
+use alloy_primitives::B256;
+use alloy_rlp::{Decodable, Encodable};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{Error, MptNode, MptNodeData, MptNodeReference};
+
+/// Link of a child that is left unresolved.
+const UNRESOLVED: u32 = 0;
+
+/// Linked nodes a table may expand to, per byte. Every node in the table is referenced by
+/// its digest and therefore at least 32 bytes long, so this allows each node to be shared
+/// 32 times on average.
+const MAX_EXPANSION: usize = 1;
+
+/// An [MptNode] in the flat node-table encoding.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodeTable(Vec<u8>);
+
+impl NodeTable {
+    /// Encodes the given trie.
+    pub fn encode(trie: &MptNode) -> Self {
+        let mut builder = TableBuilder::default();
+        builder.add(trie);
+
+        let mut out = Vec::new();
+        builder.links.encode(&mut out);
+        alloy_rlp::Header {
+            list: true,
+            payload_length: builder.nodes.len(),
+        }
+        .encode(&mut out);
+        out.extend_from_slice(&builder.nodes);
+        NodeTable(out)
+    }
+
+    /// Rebuilds the encoded trie.
+    pub fn decode(&self) -> Result<MptNode, Error> {
+        let mut buf = self.0.as_slice();
+        let links = Vec::<u32>::decode(&mut buf)?;
+        let header = alloy_rlp::Header::decode(&mut buf)?;
+        if !header.list || header.payload_length != buf.len() {
+            return Err(Error::InvalidNodeTable);
+        }
+
+        // count the uses of every node, so that the last use can take it
+        let max_link = links.iter().copied().max().unwrap_or(UNRESOLVED);
+        if max_link as usize > buf.len() {
+            return Err(Error::InvalidNodeTable);
+        }
+        let mut uses = vec![0u32; max_link as usize];
+        for &link in &links {
+            if link != UNRESOLVED {
+                uses[link as usize - 1] += 1;
+            }
+        }
+
+        // linked nodes in the subtrie of every node, and all nodes allocated so far
+        let max_expanded = MAX_EXPANSION.saturating_mul(self.0.len());
+        let mut sizes: Vec<usize> = Vec::new();
+        let mut expanded = 0usize;
+
+        let mut links = links.into_iter();
+        let mut nodes: Vec<MptNode> = Vec::new();
+        while !buf.is_empty() {
+            let node = MptNode::decode(next_item(&mut buf)?)?;
+            let mut size = 1usize;
+            let mut resolve = |child: &mut Box<MptNode>| -> Result<(), Error> {
+                if !child.is_digest() {
+                    return Ok(());
+                }
+                let link = links.next().ok_or(Error::InvalidNodeTable)?;
+                if link == UNRESOLVED {
+                    return Ok(());
+                }
+                // only nodes before the current one can be linked
+                let index = link as usize - 1;
+                let linked = nodes.get_mut(index).ok_or(Error::InvalidNodeTable)?;
+                size = size.saturating_add(sizes[index]);
+                if uses[index] > 1 {
+                    expanded = expanded.saturating_add(sizes[index]);
+                }
+                if size > max_expanded || expanded > max_expanded {
+                    return Err(Error::InvalidNodeTable);
+                }
+                uses[index] -= 1;
+                **child = if uses[index] == 0 {
+                    core::mem::take(linked)
+                } else {
+                    linked.clone()
+                };
+                Ok(())
+            };
+            let node = match node.into_data() {
+                MptNodeData::Branch(mut children) => {
+                    for child in children.iter_mut().flatten() {
+                        resolve(child)?;
+                    }
+                    MptNodeData::Branch(children)
+                }
+                MptNodeData::Extension(prefix, mut child) => {
+                    resolve(&mut child)?;
+                    MptNodeData::Extension(prefix, child)
+                }
+                data => data,
+            };
+            nodes.push(node.into());
+            sizes.push(size);
+            expanded = expanded.saturating_add(1);
+            if expanded > max_expanded {
+                return Err(Error::InvalidNodeTable);
+            }
+        }
+
+        if links.next().is_some() {
+            return Err(Error::InvalidNodeTable);
+        }
+        nodes.pop().ok_or(Error::InvalidNodeTable)
+    }
+
+    /// Returns the encoded bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns the length of the encoding in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns whether the encoding is empty, which is never the case for a valid table.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<u8>> for NodeTable {
+    fn from(bytes: Vec<u8>) -> Self {
+        NodeTable(bytes)
+    }
+}
+
+impl Serialize for NodeTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for NodeTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BytesVisitor;
+
+        impl<'de> de::Visitor<'de> for BytesVisitor {
+            type Value = NodeTable;
+
+            fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+                formatter.write_str("a node table")
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(NodeTable(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(NodeTable(v))
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut bytes = Vec::new();
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(NodeTable(bytes))
+            }
+        }
+
+        deserializer.deserialize_bytes(BytesVisitor)
+    }
+}
+
+/// The formats a trie can be serialized in.
+///
+/// Readers accept both, so that the formats can be compared on the same guest.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TrieEncoding {
+    /// The derived serialization of [MptNode].
+    Tree(MptNode),
+    /// The flat node-table encoding.
+    Table(NodeTable),
+}
+
+impl TrieEncoding {
+    /// Returns the encoded trie.
+    pub fn into_trie(self) -> Result<MptNode, Error> {
+        match self {
+            TrieEncoding::Tree(trie) => Ok(trie),
+            TrieEncoding::Table(table) => table.decode(),
+        }
+    }
+}
+
+thread_local! {
+    /// Whether [encode] returns the tree instead of the table on this thread.
+    static TREE_ENCODING: Cell<bool> = Cell::new(false);
+}
+
+/// Calls `f` with every trie serialized through this module encoded as a
+/// [TrieEncoding::Tree] instead of a table.
+pub fn with_tree_encoding<T>(f: impl FnOnce() -> T) -> T {
+    struct Reset(bool);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            TREE_ENCODING.with(|tree| tree.set(self.0));
+        }
+    }
+    let _reset = Reset(TREE_ENCODING.with(|tree| tree.replace(true)));
+    f()
+}
+
+/// Encodes a trie as a [NodeTable], or as a tree within [with_tree_encoding].
+pub fn encode(trie: &MptNode) -> TrieEncoding {
+    if TREE_ENCODING.with(Cell::get) {
+        TrieEncoding::Tree(trie.clone())
+    } else {
+        TrieEncoding::Table(NodeTable::encode(trie))
+    }
+}
+
+/// Serializes a trie as a [NodeTable], for use with `#[serde(with = "...")]`.
+pub fn serialize<S: Serializer>(trie: &MptNode, serializer: S) -> Result<S::Ok, S::Error> {
+    encode(trie).serialize(serializer)
+}
+
+/// Deserializes a trie in any [TrieEncoding], for use with `#[serde(with = "...")]`.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<MptNode, D::Error> {
+    TrieEncoding::deserialize(deserializer)?
+        .into_trie()
+        .map_err(de::Error::custom)
+}
+
+/// Collects the nodes of a trie and the links between them.
+#[derive(Default)]
+struct TableBuilder {
+    /// Concatenated RLP of the nodes added so far.
+    nodes: Vec<u8>,
+    /// Number of nodes added so far.
+    count: u32,
+    /// Index of every node added so far, by its digest.
+    indices: HashMap<B256, u32>,
+    links: Vec<u32>,
+}
+
+impl TableBuilder {
+    /// Adds the node after all nodes it references by digest, and returns its index.
+    fn add(&mut self, node: &MptNode) -> u32 {
+        let children: Vec<&MptNode> = match node.as_data() {
+            MptNodeData::Branch(children) => children.iter().flatten().map(|c| &**c).collect(),
+            MptNodeData::Extension(_, child) => vec![&**child],
+            _ => vec![],
+        };
+
+        let mut links = Vec::new();
+        for child in children {
+            let digest = match child.reference() {
+                MptNodeReference::Bytes(_) => continue,
+                MptNodeReference::Digest(digest) => digest,
+            };
+            let link = if child.is_digest() {
+                UNRESOLVED
+            } else if let Some(index) = self.indices.get(&digest) {
+                index + 1
+            } else {
+                let index = self.add(child);
+                self.indices.insert(digest, index);
+                index + 1
+            };
+            links.push(link);
+        }
+
+        node.encode(&mut self.nodes);
+        self.links.extend(links);
+        self.count += 1;
+        self.count - 1
+    }
+}
+
+/// Splits the next RLP item off the buffer.
+fn next_item<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let mut payload = *buf;
+    let header = alloy_rlp::Header::decode(&mut payload)?;
+    let length = buf.len() - payload.len() + header.payload_length;
+    if length > buf.len() {
+        return Err(alloy_rlp::Error::InputTooShort.into());
+    }
+    let (item, rest) = buf.split_at(length);
+    *buf = rest;
+    Ok(item)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{keccak::keccak, trie::MptEntry};
+
+    fn assert_same(trie: &MptNode, decoded: &MptNode) {
+        assert_eq!(trie.hash(), decoded.hash());
+        assert_eq!(
+            trie.iter().collect::<Vec<MptEntry>>(),
+            decoded.iter().collect::<Vec<MptEntry>>()
+        );
+    }
+
+    #[test]
+    pub fn test_roundtrip() {
+        let mut trie = MptNode::default();
+        assert_same(&trie, &NodeTable::encode(&trie).decode().unwrap());
+
+        for i in 0..256u64 {
+            trie.insert_rlp(&keccak(i.to_be_bytes()), i).unwrap();
+        }
+        assert_same(&trie, &NodeTable::encode(&trie).decode().unwrap());
+    }
+
+    #[test]
+    pub fn test_roundtrip_digest() {
+        let mut trie = MptNode::default();
+        for i in 0..16u8 {
+            trie.insert(&[i << 4, 0], vec![i; 40]).unwrap();
+        }
+        // replace one hashed child of the root by its digest
+        let mut children = match trie.as_data() {
+            MptNodeData::Branch(children) => children.clone(),
+            _ => unreachable!(),
+        };
+        let digest = children[3].as_ref().unwrap().hash();
+        children[3] = Some(Box::new(MptNodeData::Digest(digest).into()));
+        let trie: MptNode = MptNodeData::Branch(children).into();
+
+        let decoded = NodeTable::encode(&trie).decode().unwrap();
+        assert_same(&trie, &decoded);
+
+        let digest: MptNode = MptNodeData::Digest(trie.hash()).into();
+        assert_same(&digest, &NodeTable::encode(&digest).decode().unwrap());
+    }
+
+    #[test]
+    pub fn test_shared_nodes() {
+        // identical leaves below different branches are stored once
+        let mut trie = MptNode::default();
+        for i in 0..4u8 {
+            trie.insert(&[i << 4, 0xaa], vec![0xff; 40]).unwrap();
+            trie.insert(&[i << 4, 0xbb], vec![0xee; 40]).unwrap();
+        }
+        let table = NodeTable::encode(&trie);
+        let value_count = table
+            .as_bytes()
+            .windows(40)
+            .filter(|w| w.iter().all(|b| *b == 0xff))
+            .count();
+        assert_eq!(value_count, 1);
+        assert_same(&trie, &table.decode().unwrap());
+    }
+
+    #[test]
+    pub fn test_invalid() {
+        let mut trie = MptNode::default();
+        for i in 0..16u64 {
+            trie.insert_rlp(&keccak(i.to_be_bytes()), i).unwrap();
+        }
+        let bytes = NodeTable::encode(&trie).as_bytes().to_vec();
+        assert!(NodeTable::from(bytes[..bytes.len() - 1].to_vec())
+            .decode()
+            .is_err());
+        assert!(NodeTable::from(vec![]).decode().is_err());
+
+        // links to the root, which comes after all other nodes, are rejected
+        let mut buf = &bytes[..];
+        let links = Vec::<u32>::decode(&mut buf).unwrap();
+        let root_link = links.len() as u32 + 1;
+        let mut table = Vec::new();
+        links
+            .iter()
+            .map(|link| {
+                if *link == UNRESOLVED {
+                    *link
+                } else {
+                    root_link
+                }
+            })
+            .collect::<Vec<_>>()
+            .encode(&mut table);
+        table.extend_from_slice(buf);
+        assert!(NodeTable::from(table).decode().is_err());
+
+        // links beyond the table are rejected without allocating for them
+        let mut table = Vec::new();
+        vec![u32::MAX].encode(&mut table);
+        table.extend_from_slice(buf);
+        assert!(NodeTable::from(table).decode().is_err());
+    }
+
+    #[test]
+    pub fn test_expansion() {
+        // a leaf, and branches linking all their children to the node before them
+        let leaf: MptNode = MptNodeData::Leaf(vec![0x20], vec![0xff; 40]).into();
+        let digest = Some(Box::new(MptNodeData::Digest(leaf.hash()).into()));
+        let branch: MptNode = MptNodeData::Branch(core::array::from_fn(|_| digest.clone())).into();
+        let branches = 24u32;
+
+        let mut links = Vec::new();
+        let mut nodes = Vec::new();
+        leaf.encode(&mut nodes);
+        for i in 0..branches {
+            links.extend([i + 1; 16]);
+            branch.encode(&mut nodes);
+        }
+        let mut table = Vec::new();
+        links.encode(&mut table);
+        alloy_rlp::Header {
+            list: true,
+            payload_length: nodes.len(),
+        }
+        .encode(&mut table);
+        table.extend_from_slice(&nodes);
+
+        // about 16^24 nodes from a table of a few kilobytes
+        assert!(table.len() < 32 * 1024);
+        assert!(matches!(
+            NodeTable::from(table).decode(),
+            Err(Error::InvalidNodeTable)
+        ));
+
+        // a single level of sharing is fine
+        let mut table = Vec::new();
+        vec![1u32; 16].encode(&mut table);
+        let mut nodes = Vec::new();
+        leaf.encode(&mut nodes);
+        branch.encode(&mut nodes);
+        alloy_rlp::Header {
+            list: true,
+            payload_length: nodes.len(),
+        }
+        .encode(&mut table);
+        table.extend_from_slice(&nodes);
+        let decoded = NodeTable::from(table).decode().unwrap();
+        assert_eq!(decoded.size(), 17);
+    }
+
+    #[test]
+    pub fn test_serde() {
+        let mut trie = MptNode::default();
+        for i in 0..64u64 {
+            trie.insert_rlp(&keccak(i.to_be_bytes()), i).unwrap();
+        }
+        #[derive(Serialize, Deserialize)]
+        struct Wrapper(#[serde(with = "super")] MptNode);
+
+        let bytes = bincode::serialize(&Wrapper(trie.clone())).unwrap();
+        let decoded: Wrapper = bincode::deserialize(&bytes).unwrap();
+        assert_same(&trie, &decoded.0);
+        let json = serde_json::to_string(&Wrapper(trie.clone())).unwrap();
+        let decoded: Wrapper = serde_json::from_str(&json).unwrap();
+        assert_same(&trie, &decoded.0);
+
+        // the tree format is accepted as well
+        let bytes = bincode::serialize(&TrieEncoding::Tree(trie.clone())).unwrap();
+        let decoded: Wrapper = bincode::deserialize(&bytes).unwrap();
+        assert_same(&trie, &decoded.0);
+
+        // and written within with_tree_encoding, only there
+        let tree_bytes = with_tree_encoding(|| bincode::serialize(&Wrapper(trie.clone())).unwrap());
+        assert_eq!(tree_bytes, bytes);
+        let table_bytes = bincode::serialize(&Wrapper(trie.clone())).unwrap();
+        assert_ne!(table_bytes, bytes);
+    }
+}
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use alloy_primitives::B160;
-use alloy_rlp_derive::{RlpEncodable, RlpMaxEncodedLen};
+use alloy_rlp_derive::{RlpDecodable, RlpEncodable, RlpMaxEncodedLen};
 use serde::{Deserialize, Serialize};
 
 /// Represents a validator's withdrawal from the Ethereum consensus layer.
@@ -24,7 +24,16 @@ use serde::{Deserialize, Serialize};
 /// specific identifiers and target details to ensure the accurate and secure transfer of
 /// ether.
 #[derive(
-    Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, RlpEncodable, RlpMaxEncodedLen,
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    RlpEncodable,
+    RlpDecodable,
+    RlpMaxEncodedLen,
 )]
 pub struct Withdrawal {
     /// A unique, monotonically increasing identifier assigned by the consensus layer to
@@ -38,3 +47,31 @@ pub struct Withdrawal {
     /// The total withdrawal amount, denominated in gwei.
     pub amount: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use alloy_rlp::{Decodable, Encodable};
+    use hex_literal::hex;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn rlp() {
+        let withdrawal: Withdrawal = serde_json::from_value(json!({
+            "index": 1,
+            "validator_index": 2,
+            "address": "0x00000000000000000000000000000000000000ff",
+            "amount": 1234567
+        }))
+        .unwrap();
+
+        let encoded = alloy_rlp::encode(&withdrawal);
+        assert_eq!(encoded.len(), withdrawal.length());
+        assert_eq!(
+            encoded,
+            hex!("db01029400000000000000000000000000000000000000ff8312d687")
+        );
+        assert_eq!(Withdrawal::decode(&mut &encoded[..]).unwrap(), withdrawal);
+    }
+}
@@ -127,33 +127,27 @@ impl Provider for TestProvider {
 }
 
 fn build_tries(state: &TestState) -> (MptNode, HashMap<B160, MptNode>) {
-    let mut state_trie = MptNode::default();
+    let mut state_entries = Vec::with_capacity(state.0.len());
     let mut storage_tries = HashMap::new();
     for (address, account) in &state.0 {
-        let mut storage_trie = MptNode::default();
-        for (slot, value) in &account.storage {
-            if *value != LibU256::ZERO {
-                storage_trie
-                    .insert_rlp(&keccak(slot.to_be_bytes::<32>()), *value)
-                    .unwrap();
-            }
-        }
-
-        state_trie
-            .insert_rlp(
-                &keccak(address),
-                StateAccount {
-                    nonce: account.nonce.try_into().unwrap(),
-                    balance: account.balance,
-                    storage_root: storage_trie.hash(),
-                    code_hash: keccak(account.code.clone()).into(),
-                },
-            )
-            .unwrap();
+        let storage_trie: MptNode = account
+            .storage
+            .iter()
+            .filter(|(_, value)| **value != LibU256::ZERO)
+            .map(|(slot, value)| (keccak(slot.to_be_bytes::<32>()), value.to_rlp()))
+            .collect();
+
+        let state_account = StateAccount {
+            nonce: account.nonce.try_into().unwrap(),
+            balance: account.balance,
+            storage_root: storage_trie.hash(),
+            code_hash: keccak(account.code.clone()).into(),
+        };
+        state_entries.push((keccak(address), state_account.to_rlp()));
         storage_tries.insert(*address, storage_trie);
     }
 
-    (state_trie, storage_tries)
+    (state_entries.into_iter().collect(), storage_tries)
 }
 
 fn get_proof(
@@ -165,17 +159,19 @@ fn get_proof(
     let (state_trie, mut storage_tries) = build_tries(state);
     let storage_trie = storage_tries.remove(&address).unwrap_or_default();
 
-    let account_proof = mpt_proof(&state_trie, keccak(address))?
+    let account_proof = state_trie
+        .prove(&keccak(address))?
         .into_iter()
-        .map(|p| p.into())
+        .map(|p| p.to_vec().into())
         .collect();
     let mut storage_proof = vec![];
     for index in indices {
         let proof = StorageProof {
             key: index.to_be_bytes().into(),
-            proof: mpt_proof(&storage_trie, keccak(index.to_be_bytes::<32>()))?
+            proof: storage_trie
+                .prove(&keccak(index.to_be_bytes::<32>()))?
                 .into_iter()
-                .map(|p| p.into())
+                .map(|p| p.to_vec().into())
                 .collect(),
             value: account
                 .storage
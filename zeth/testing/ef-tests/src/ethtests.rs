@@ -12,12 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{fs::File, io::BufReader, path::PathBuf};
+use std::{collections::BTreeMap, fs::File, io::BufReader, path::PathBuf};
 
 use revm::primitives::SpecId;
+use serde::Deserialize;
 use serde_json::Value;
 use zeth_lib::consts::ChainSpec;
-use zeth_primitives::block::Header;
+use zeth_primitives::{block::Header, Bytes, B160, B256, U256};
 
 use crate::TestJson;
 
@@ -59,3 +60,56 @@ pub fn read_eth_test(path: PathBuf) -> Vec<EthTestCase> {
         .flatten()
         .collect()
 }
+
+/// A test of the `TransactionTests` suite: an encoded transaction and the expected
+/// result of validating it in each fork.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransactionTest {
+    pub txbytes: Bytes,
+    pub result: BTreeMap<String, TransactionTestResult>,
+}
+
+/// The expected result of a [TransactionTest] in one fork: either an exception, or the
+/// hash, intrinsic gas and sender of the valid transaction.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionTestResult {
+    pub exception: Option<String>,
+    pub hash: Option<B256>,
+    pub intrinsic_gas: Option<U256>,
+    pub sender: Option<B160>,
+}
+
+/// A [TransactionTest] with the expected result in a single supported fork.
+pub struct TransactionTestCase {
+    pub name: String,
+    pub spec: SpecId,
+    pub txbytes: Bytes,
+    pub result: TransactionTestResult,
+}
+
+pub fn read_transaction_test(path: PathBuf) -> Vec<TransactionTestCase> {
+    println!("Using file: {}", path.display());
+    let f = File::open(path).unwrap();
+    let tests: BTreeMap<String, TransactionTest> =
+        serde_json::from_reader(BufReader::new(f)).unwrap();
+
+    let mut cases = Vec::new();
+    for (name, test) in tests {
+        for (fork, result) in test.result {
+            // the transaction tests name the merge fork "Paris"
+            let spec = match fork.as_str() {
+                "Paris" | "Merge" => SpecId::MERGE,
+                "Shanghai" => SpecId::SHANGHAI,
+                _ => continue,
+            };
+            cases.push(TransactionTestCase {
+                name: name.clone(),
+                spec,
+                txbytes: test.txbytes.clone(),
+                result,
+            });
+        }
+    }
+    cases
+}
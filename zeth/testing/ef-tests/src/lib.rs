@@ -38,11 +38,9 @@ use zeth_primitives::{
     keccak::keccak,
     revm::from_revm_b160,
     signature::TxSignature,
-    transaction::{
-        Transaction, TransactionKind, TxEssence, TxEssenceEip1559, TxEssenceEip2930,
-        TxEssenceLegacy,
-    },
-    trie::{self, MptNode, MptNodeData, StateAccount},
+    transaction::Transaction,
+    transaction_builder::TransactionBuilder,
+    trie::{MptNode, StateAccount},
     withdrawal::Withdrawal,
     Bloom, Bytes, RlpBytes, StorageKey, B160, B256, B64, U256, U64,
 };
@@ -198,49 +196,26 @@ impl From<TestTransaction> for Transaction {
             r: tx.r,
             s: tx.s,
         };
-        let essence = if tx.access_list.is_none() {
-            TxEssence::Legacy(TxEssenceLegacy {
-                chain_id: None,
-                nonce: tx.nonce.try_into().unwrap(),
-                gas_price: tx.gas_price.unwrap(),
-                gas_limit: tx.gas_limit,
-                to: match tx.to {
-                    Some(addr) => TransactionKind::Call(addr),
-                    None => TransactionKind::Create,
-                },
-                value: tx.value,
-                data: tx.data,
-            })
-        } else if tx.max_fee_per_gas.is_none() {
-            TxEssence::Eip2930(TxEssenceEip2930 {
-                chain_id: 1,
-                nonce: tx.nonce.try_into().unwrap(),
-                gas_price: tx.gas_price.unwrap(),
-                gas_limit: tx.gas_limit,
-                to: match tx.to {
-                    Some(addr) => TransactionKind::Call(addr),
-                    None => TransactionKind::Create,
-                },
-                value: tx.value,
-                data: tx.data,
-                access_list: tx.access_list.unwrap().into(),
-            })
-        } else {
-            TxEssence::Eip1559(TxEssenceEip1559 {
-                chain_id: 1,
-                nonce: tx.nonce.try_into().unwrap(),
-                max_priority_fee_per_gas: tx.max_priority_fee_per_gas.unwrap(),
-                max_fee_per_gas: tx.max_fee_per_gas.unwrap(),
-                gas_limit: tx.gas_limit,
-                to: match tx.to {
-                    Some(addr) => TransactionKind::Call(addr),
-                    None => TransactionKind::Create,
-                },
-                value: tx.value,
-                data: tx.data,
-                access_list: tx.access_list.unwrap().into(),
-            })
+        let builder = match (tx.access_list, tx.max_fee_per_gas) {
+            (None, _) => TransactionBuilder::legacy().gas_price(tx.gas_price.unwrap()),
+            (Some(access_list), None) => TransactionBuilder::eip2930()
+                .chain_id(1)
+                .gas_price(tx.gas_price.unwrap())
+                .access_list(access_list.into()),
+            (Some(access_list), Some(max_fee_per_gas)) => TransactionBuilder::eip1559()
+                .chain_id(1)
+                .max_priority_fee_per_gas(tx.max_priority_fee_per_gas.unwrap())
+                .max_fee_per_gas(max_fee_per_gas)
+                .access_list(access_list.into()),
         };
+        let essence = builder
+            .nonce(tx.nonce.try_into().unwrap())
+            .gas_limit(tx.gas_limit)
+            .to(tx.to)
+            .value(tx.value)
+            .data(tx.data)
+            .build()
+            .unwrap();
         Transaction { essence, signature }
     }
 }
@@ -270,43 +245,6 @@ impl From<TestAccessList> for AccessList {
     }
 }
 
-/// Computes the Merkle proof for the given key in the trie.
-pub fn mpt_proof(root: &MptNode, key: impl AsRef<[u8]>) -> Result<Vec<Vec<u8>>, anyhow::Error> {
-    let mut path = proof_internal(root, &trie::to_nibs(key.as_ref()))?;
-    path.reverse();
-    Ok(path)
-}
-
-fn proof_internal(node: &MptNode, key_nibs: &[u8]) -> Result<Vec<Vec<u8>>, anyhow::Error> {
-    if key_nibs.is_empty() {
-        return Ok(vec![node.to_rlp()]);
-    }
-
-    let mut path: Vec<Vec<u8>> = match node.as_data() {
-        MptNodeData::Null | MptNodeData::Leaf(_, _) => vec![],
-        MptNodeData::Branch(children) => {
-            let mut path = Vec::new();
-            for node in children.iter().flatten() {
-                path.extend(proof_internal(node, &key_nibs[1..])?);
-            }
-            path
-        }
-        MptNodeData::Extension(_, child) => {
-            let ext_nibs = node.nibs();
-            let ext_len = ext_nibs.len();
-            if key_nibs[..ext_len] == ext_nibs {
-                proof_internal(child, &key_nibs[ext_len..])?
-            } else {
-                vec![]
-            }
-        }
-        MptNodeData::Digest(_) => bail!("Cannot descend pointer!"),
-    };
-    path.push(node.to_rlp());
-
-    Ok(path)
-}
-
 /// The size of the stack to use for the EVM.
 pub const BIG_STACK_SIZE: usize = 8 * 1024 * 1024;
 
@@ -0,0 +1,118 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "ef-tests")]
+
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context};
+use revm::primitives::SpecId;
+use rstest::rstest;
+use zeth_primitives::{
+    rlp::Decodable,
+    transaction::{Transaction, TransactionKind, TxEssence},
+    B160, U256,
+};
+use zeth_testeth::ethtests::{read_transaction_test, TransactionTestCase};
+
+/// Maximum size of the init code of a contract creation, see
+/// [EIP-3860](https://eips.ethereum.org/EIPS/eip-3860).
+const MAX_INITCODE_SIZE: usize = 2 * 24576;
+
+#[rstest]
+fn signature(#[files("testdata/TransactionTests/ttSignature/*.json")] path: PathBuf) {
+    transaction_test(path);
+}
+
+#[rstest]
+fn rs_value(#[files("testdata/TransactionTests/ttRSValue/*.json")] path: PathBuf) {
+    transaction_test(path);
+}
+
+#[rstest]
+fn v_value(#[files("testdata/TransactionTests/ttVValue/*.json")] path: PathBuf) {
+    transaction_test(path);
+}
+
+fn transaction_test(path: PathBuf) {
+    for TransactionTestCase {
+        name,
+        spec,
+        txbytes,
+        result,
+    } in read_transaction_test(path)
+    {
+        println!("test '{}' ({:?})", name, spec);
+        let validated = validate(&txbytes, spec);
+        match result.exception {
+            Some(exception) => {
+                assert!(
+                    validated.is_err(),
+                    "expected exception '{}', got {:?}",
+                    exception,
+                    validated
+                );
+            }
+            None => {
+                let (tx, sender, intrinsic_gas) = validated.unwrap();
+                assert_eq!(Some(sender), result.sender);
+                assert_eq!(Some(tx.hash()), result.hash);
+                assert_eq!(Some(U256::from(intrinsic_gas)), result.intrinsic_gas);
+            }
+        }
+    }
+}
+
+/// Decodes and validates the transaction like a mainnet client, returning it together
+/// with its sender and intrinsic gas.
+fn validate(mut txbytes: &[u8], spec: SpecId) -> anyhow::Result<(Transaction, B160, u64)> {
+    let tx = Transaction::decode(&mut txbytes).context("invalid RLP")?;
+    ensure!(txbytes.is_empty(), "trailing bytes");
+    let sender = tx.verify_signature(1)?;
+
+    let (nonce, kind, data, access_list) = match &tx.essence {
+        TxEssence::Legacy(tx) => (tx.nonce, tx.to, &tx.data, None),
+        TxEssence::Eip2930(tx) => (tx.nonce, tx.to, &tx.data, Some(&tx.access_list)),
+        TxEssence::Eip1559(tx) => {
+            ensure!(
+                tx.max_priority_fee_per_gas <= tx.max_fee_per_gas,
+                "priority fee greater than max fee"
+            );
+            (tx.nonce, tx.to, &tx.data, Some(&tx.access_list))
+        }
+    };
+    ensure!(nonce < u64::MAX, "nonce overflow");
+
+    let mut intrinsic_gas = 21000u64;
+    if kind == TransactionKind::Create {
+        intrinsic_gas += 32000;
+        if spec >= SpecId::SHANGHAI {
+            ensure!(data.len() <= MAX_INITCODE_SIZE, "init code too large");
+            intrinsic_gas += 2 * ((data.len() as u64 + 31) / 32);
+        }
+    }
+    let zeros = data.iter().filter(|b| **b == 0).count() as u64;
+    intrinsic_gas += 4 * zeros + 16 * (data.len() as u64 - zeros);
+    if let Some(access_list) = access_list {
+        for item in &access_list.0 {
+            intrinsic_gas += 2400 + 1900 * item.storage_keys.len() as u64;
+        }
+    }
+    ensure!(
+        tx.gas_limit() >= U256::from(intrinsic_gas),
+        "intrinsic gas too low"
+    );
+
+    Ok((tx, sender, intrinsic_gas))
+}